@@ -0,0 +1,217 @@
+// Uncompressed Windows BMP: 1, 4 and 8 bit palettized, 16, 24 and 32 bit, with or without bit
+// field masks.
+
+use crate::bitmap::{check_size, read_u16_le, read_u32_le, Bitmap, ImageError};
+use crate::color::Color;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+pub fn decode(data: &[u8]) -> Result<Bitmap, ImageError> {
+    if !data.starts_with(b"BM") {
+        return Err(ImageError::Corrupt("missing BMP signature"));
+    }
+
+    let pixel_offset = read_u32_le(data, 10)? as usize;
+    let header_size = read_u32_le(data, 14)? as usize;
+    if header_size < 40 {
+        return Err(ImageError::Unsupported("BMP header version"));
+    }
+
+    let width = read_u32_le(data, 18)? as i32;
+    let height = read_u32_le(data, 22)? as i32;
+    let bits_per_pixel = read_u16_le(data, 28)?;
+    let compression = read_u32_le(data, 30)?;
+    let colors_used = read_u32_le(data, 46)? as usize;
+
+    // Negative heights are stored top to bottom
+    let is_top_down = height < 0;
+    let height = (height as i64).abs();
+    check_size(width as i64, height)?;
+    let height = height as i32;
+
+    let masks = match (compression, bits_per_pixel) {
+        (BI_RGB, 16) => [0x7c00, 0x03e0, 0x001f, 0],
+        (BI_RGB, 32) => [0x00ff0000, 0x0000ff00, 0x000000ff, 0],
+        (BI_BITFIELDS, 16) | (BI_BITFIELDS, 32) | (BI_ALPHABITFIELDS, 32) => {
+            // Masks follow a plain 40 byte header, newer headers include them
+            let alpha = if compression == BI_ALPHABITFIELDS || header_size >= 56 {
+                read_u32_le(data, 66)?
+            } else {
+                0
+            };
+            [
+                read_u32_le(data, 54)?,
+                read_u32_le(data, 58)?,
+                read_u32_le(data, 62)?,
+                alpha,
+            ]
+        }
+        (BI_RGB, 1) | (BI_RGB, 4) | (BI_RGB, 8) | (BI_RGB, 24) => [0; 4],
+        (BI_RGB, _) | (BI_BITFIELDS, _) => return Err(ImageError::Unsupported("BMP bit depth")),
+        _ => return Err(ImageError::Unsupported("BMP compression")),
+    };
+
+    let palette = if bits_per_pixel <= 8 {
+        let count = if colors_used == 0 {
+            1 << bits_per_pixel
+        } else {
+            colors_used.min(1 << bits_per_pixel)
+        };
+        let start = 14 + header_size;
+        let entries = data
+            .get(start..start + count * 4)
+            .ok_or(ImageError::Corrupt("truncated palette"))?;
+        entries
+            .chunks(4)
+            .map(|bgrx| Color::rgb(bgrx[2], bgrx[1], bgrx[0]))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let stride = (width as usize * bits_per_pixel as usize + 31) / 32 * 4;
+    let pixel_data = data
+        .get(pixel_offset..pixel_offset + stride * height as usize)
+        .ok_or(ImageError::Corrupt("truncated pixel data"))?;
+
+    let mut bitmap = Bitmap::new(width, height);
+    for (y, row) in pixel_data.chunks(stride).enumerate() {
+        for x in 0..width as usize {
+            let color = match bits_per_pixel {
+                1 | 4 | 8 => {
+                    let bit = x * bits_per_pixel as usize;
+                    let byte = row[bit / 8];
+                    let shift = 8 - bits_per_pixel as usize - bit % 8;
+                    let index = (byte >> shift) as usize & ((1 << bits_per_pixel) - 1);
+                    *palette
+                        .get(index)
+                        .ok_or(ImageError::Corrupt("palette index out of range"))?
+                }
+                24 => Color::rgb(row[x * 3 + 2], row[x * 3 + 1], row[x * 3]),
+                16 => {
+                    let value = u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32;
+                    masked_color(value, &masks)
+                }
+                _ => {
+                    let bytes = &row[x * 4..x * 4 + 4];
+                    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    masked_color(value, &masks)
+                }
+            };
+            bitmap.set_pixel(x as i32, y as i32, color);
+        }
+    }
+
+    if is_top_down {
+        bitmap.flip_rows();
+    }
+
+    Ok(bitmap)
+}
+
+/// Without an alpha mask the pixel is opaque.
+fn masked_color(value: u32, masks: &[u32; 4]) -> Color {
+    let alpha = if masks[3] == 0 {
+        255
+    } else {
+        masked_channel(value, masks[3])
+    };
+    Color::rgba(
+        masked_channel(value, masks[0]),
+        masked_channel(value, masks[1]),
+        masked_channel(value, masks[2]),
+        alpha,
+    )
+}
+
+/// Extracts the bits selected by `mask`, scaled to 8 bits.
+fn masked_channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    let channel = ((value & mask) >> shift) as u64;
+    (channel * 255 / max) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bmp(width: i32, height: i32, bits_per_pixel: u16, extra: &[u8], pixels: &[u8]) -> Vec<u8> {
+        let pixel_offset = 14 + 40 + extra.len() as u32;
+        let mut data = Vec::new();
+        data.extend_from_slice(b"BM");
+        data.extend_from_slice(&(pixel_offset + pixels.len() as u32).to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&pixel_offset.to_le_bytes());
+
+        data.extend_from_slice(&40u32.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&bits_per_pixel.to_le_bytes());
+        data.extend_from_slice(&[0; 24]);
+
+        data.extend_from_slice(extra);
+        data.extend_from_slice(pixels);
+        data
+    }
+
+    #[test]
+    fn test_24_bit() {
+        // Two rows of 2 pixels, padded to 8 bytes, bottom row first
+        let pixels = [
+            0, 0, 255, 0, 255, 0, 0, 0, //
+            255, 0, 0, 255, 255, 255, 0, 0,
+        ];
+        let bitmap = decode(&bmp(2, 2, 24, &[], &pixels)).unwrap();
+        assert_eq!(bitmap.pixel(0, 0), Color::rgb(255, 0, 0));
+        assert_eq!(bitmap.pixel(1, 0), Color::rgb(0, 255, 0));
+        assert_eq!(bitmap.pixel(0, 1), Color::rgb(0, 0, 255));
+        assert_eq!(bitmap.pixel(1, 1), Color::WHITE);
+
+        let flipped = decode(&bmp(2, -2, 24, &[], &pixels)).unwrap();
+        assert_eq!(flipped.pixel(0, 1), Color::rgb(255, 0, 0));
+        assert_eq!(flipped.pixel(1, 0), Color::WHITE);
+    }
+
+    #[test]
+    fn test_palettized() {
+        let palette = [0, 0, 0, 0, 255, 255, 255, 0];
+        let pixels = [0b1010_0000, 0, 0, 0];
+        let bitmap = decode(&bmp(3, 1, 1, &palette, &pixels)).unwrap();
+        assert_eq!(bitmap.pixel(0, 0), Color::WHITE);
+        assert_eq!(bitmap.pixel(1, 0), Color::BLACK);
+        assert_eq!(bitmap.pixel(2, 0), Color::WHITE);
+    }
+
+    #[test]
+    fn test_32_bit() {
+        let pixels = [0x10, 0x20, 0x30, 0x00];
+        let bitmap = decode(&bmp(1, 1, 32, &[], &pixels)).unwrap();
+        assert_eq!(bitmap.pixel(0, 0), Color::rgb(0x30, 0x20, 0x10));
+    }
+
+    #[test]
+    fn test_masked_channel() {
+        assert_eq!(masked_channel(0x7c00, 0x7c00), 255);
+        assert_eq!(masked_channel(0x0000, 0x7c00), 0);
+        assert_eq!(masked_channel(0x80000000, 0xff000000), 0x80);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let data = bmp(2, 2, 24, &[], &[0; 4]);
+        assert!(matches!(decode(&data), Err(ImageError::Corrupt(_))));
+        assert!(matches!(decode(&data[..20]), Err(ImageError::Corrupt(_))));
+        assert!(matches!(
+            decode(&bmp(1, 1, 2, &[], &[0; 4])),
+            Err(ImageError::Unsupported(_))
+        ));
+    }
+}
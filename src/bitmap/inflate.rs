@@ -0,0 +1,423 @@
+// DEFLATE (RFC 1951) and zlib (RFC 1950) decoding, modeled on zlib's puff.c.
+
+use std::fmt;
+
+const MAX_BITS: usize = 15;
+const MAX_LIT_LEN_CODES: usize = 286;
+const MAX_DIST_CODES: usize = 30;
+const FIXED_LIT_LEN_CODES: usize = 288;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order code length code lengths are stored in, in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum InflateError {
+    UnexpectedEnd,
+    BadHeader,
+    BadBlockType,
+    BadStoredLength,
+    BadCode,
+    BadDistance,
+    BadChecksum,
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            InflateError::UnexpectedEnd => "unexpected end of data",
+            InflateError::BadHeader => "bad zlib header",
+            InflateError::BadBlockType => "bad block type",
+            InflateError::BadStoredLength => "stored block length doesn't match its complement",
+            InflateError::BadCode => "bad huffman code",
+            InflateError::BadDistance => "distance is too far back",
+            InflateError::BadChecksum => "adler-32 checksum mismatch",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// Decodes a zlib stream: 2 byte header, DEFLATE data and an Adler-32 checksum.
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, InflateError> {
+    if data.len() < 2 {
+        return Err(InflateError::UnexpectedEnd);
+    }
+
+    let (cmf, flg) = (data[0], data[1]);
+    let is_deflate = cmf & 0x0f == 8 && cmf >> 4 <= 7;
+    let has_dictionary = flg & 0x20 != 0;
+    if !is_deflate || has_dictionary || (cmf as u16 * 256 + flg as u16) % 31 != 0 {
+        return Err(InflateError::BadHeader);
+    }
+
+    let mut bits = BitReader::new(&data[2..]);
+    let output = inflate_bits(&mut bits)?;
+
+    let checksum = bits.aligned_bytes(4)?;
+    let checksum = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if checksum != adler32(&output) {
+        return Err(InflateError::BadChecksum);
+    }
+
+    Ok(output)
+}
+
+/// Decodes raw DEFLATE data.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, InflateError> {
+    inflate_bits(&mut BitReader::new(data))
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+
+    let mut a = 1u32;
+    let mut b = 0u32;
+    // 5552 is the most bytes that can be summed before b can overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+
+    b << 16 | a
+}
+
+fn inflate_bits(bits: &mut BitReader) -> Result<Vec<u8>, InflateError> {
+    let mut output = Vec::new();
+
+    loop {
+        let is_last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => stored(bits, &mut output)?,
+            1 => {
+                let (lit_len, dist) = fixed_codes();
+                codes(bits, &mut output, &lit_len, &dist)?;
+            }
+            2 => {
+                let (lit_len, dist) = dynamic_codes(bits)?;
+                codes(bits, &mut output, &lit_len, &dist)?;
+            }
+            _ => return Err(InflateError::BadBlockType),
+        }
+
+        if is_last {
+            return Ok(output);
+        }
+    }
+}
+
+fn stored(bits: &mut BitReader, output: &mut Vec<u8>) -> Result<(), InflateError> {
+    let header = bits.aligned_bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if len != !complement {
+        return Err(InflateError::BadStoredLength);
+    }
+
+    output.extend_from_slice(bits.aligned_bytes(len as usize)?);
+    Ok(())
+}
+
+fn codes(
+    bits: &mut BitReader,
+    output: &mut Vec<u8>,
+    lit_len: &Huffman,
+    dist: &Huffman,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = lit_len.decode(bits)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return Err(InflateError::BadCode);
+            }
+            let len =
+                LENGTH_BASE[symbol] as usize + bits.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+            let symbol = dist.decode(bits)? as usize;
+            if symbol >= DIST_BASE.len() {
+                return Err(InflateError::BadCode);
+            }
+            let distance =
+                DIST_BASE[symbol] as usize + bits.bits(DIST_EXTRA[symbol] as u32)? as usize;
+            if distance > output.len() {
+                return Err(InflateError::BadDistance);
+            }
+
+            // Byte by byte, the copy may overlap what it's producing
+            let start = output.len() - distance;
+            for i in 0..len {
+                output.push(output[start + i]);
+            }
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; FIXED_LIT_LEN_CODES];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+
+    // Both tables are complete, so building them can't fail
+    let lit_len = Huffman::new(&lengths).unwrap();
+    let dist = Huffman::new(&[5; MAX_DIST_CODES]).unwrap();
+    (lit_len, dist)
+}
+
+fn dynamic_codes(bits: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    let lit_len_count = bits.bits(5)? as usize + 257;
+    let dist_count = bits.bits(5)? as usize + 1;
+    let code_length_count = bits.bits(4)? as usize + 4;
+    if lit_len_count > MAX_LIT_LEN_CODES || dist_count > MAX_DIST_CODES {
+        return Err(InflateError::BadCode);
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[index] = bits.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    // Literal/length and distance code lengths are one sequence, repeats can cross between them
+    let mut lengths = [0u8; MAX_LIT_LEN_CODES + MAX_DIST_CODES];
+    let count = lit_len_count + dist_count;
+    let mut i = 0;
+    while i < count {
+        let symbol = code_length_code.decode(bits)?;
+        let (length, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if i == 0 {
+                    return Err(InflateError::BadCode);
+                }
+                (lengths[i - 1], 3 + bits.bits(2)? as usize)
+            }
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+
+        if i + repeat > count {
+            return Err(InflateError::BadCode);
+        }
+        for length_slot in lengths[i..i + repeat].iter_mut() {
+            *length_slot = length;
+        }
+        i += repeat;
+    }
+
+    if lengths[256] == 0 {
+        // No end of block code
+        return Err(InflateError::BadCode);
+    }
+
+    let lit_len = Huffman::new(&lengths[..lit_len_count])?;
+    let dist = Huffman::new(&lengths[lit_len_count..count])?;
+    Ok((lit_len, dist))
+}
+
+/// Canonical Huffman code, stored as the number of codes of each length and the symbols sorted
+/// by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        // Over-subscribed codes are invalid, incomplete ones are allowed (e.g. a single
+        // distance code) and fail when an unused code shows up
+        let mut left = 1i32;
+        for &count in counts.iter().skip(1) {
+            left <<= 1;
+            left -= count as i32;
+            if left < 0 {
+                return Err(InflateError::BadCode);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Result<u16, InflateError> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+
+        for length in 1..=MAX_BITS {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err(InflateError::BadCode)
+    }
+}
+
+/// Reads bits least significant first, as DEFLATE packs them.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            position: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, InflateError> {
+        while self.bit_count < count {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(InflateError::UnexpectedEnd)?;
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    /// Drops the bits left in the current byte and returns the next `count` whole bytes.
+    fn aligned_bytes(&mut self, count: usize) -> Result<&'a [u8], InflateError> {
+        // Whole bytes can still be buffered, give them back
+        self.position -= (self.bit_count / 8) as usize;
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or(InflateError::UnexpectedEnd)?;
+        self.position += count;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stored() {
+        let data = [0x01, 0x05, 0x00, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o'];
+        assert_eq!(inflate(&data), Ok(b"hello".to_vec()));
+        assert_eq!(
+            inflate(&[0x01, 0x05, 0x00, 0x00, 0x00]),
+            Err(InflateError::BadStoredLength)
+        );
+    }
+
+    #[test]
+    fn test_fixed() {
+        // zlib.compress(b"abcabcabcabcabcabc")
+        let data = [
+            0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x4e, 0x44, 0x45, 0x00, 0x41, 0x7c, 0x06, 0xe5,
+        ];
+        assert_eq!(zlib_decompress(&data), Ok(b"abcabcabcabcabcabc".to_vec()));
+    }
+
+    #[test]
+    fn test_dynamic() {
+        let text: Vec<u8> = (0..2000u32)
+            .map(|i| b"the quick brown fox jumps over the lazy dog "[(i * 7 % 44) as usize])
+            .collect();
+        assert_eq!(zlib_decompress(DYNAMIC), Ok(text));
+    }
+
+    #[test]
+    fn test_corrupt() {
+        assert_eq!(zlib_decompress(&[]), Err(InflateError::UnexpectedEnd));
+        assert_eq!(zlib_decompress(&[0x78, 0x00]), Err(InflateError::BadHeader));
+        assert_eq!(inflate(&[0x07]), Err(InflateError::BadBlockType));
+
+        let mut data = DYNAMIC.to_vec();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert_eq!(zlib_decompress(&data), Err(InflateError::BadChecksum));
+        assert_eq!(
+            zlib_decompress(&DYNAMIC[..DYNAMIC.len() / 2]),
+            Err(InflateError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    // zlib.compress(text, 9), a single dynamic block
+    const DYNAMIC: &[u8] = &[
+        0x78, 0xda, 0xed, 0xca, 0xc7, 0x01, 0x80, 0x30, 0x0c, 0x04, 0xc1, 0x56, 0xb6, 0x35, 0x82,
+        0xc0, 0x44, 0x81, 0x91, 0x49, 0xd5, 0xd3, 0x06, 0x8f, 0x9b, 0xf7, 0x44, 0xb3, 0x16, 0x9b,
+        0xfb, 0xe2, 0xb8, 0xb5, 0xd4, 0x7e, 0xc4, 0x93, 0x26, 0x96, 0x5c, 0x31, 0x5c, 0xe3, 0x89,
+        0xef, 0xf9, 0x26, 0x61, 0x74, 0x1b, 0x6f, 0xe8, 0xea, 0xea, 0xea, 0xea, 0xea, 0xea, 0xfe,
+        0xf2, 0x7e, 0x20, 0x8f, 0xdc, 0x6b,
+    ];
+}
@@ -0,0 +1,317 @@
+pub mod bmp;
+pub mod inflate;
+pub mod png;
+pub mod tga;
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bitmap::inflate::InflateError;
+use crate::color::Color;
+use crate::math::clamp;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    UnknownFormat,
+    Unsupported(&'static str),
+    Corrupt(&'static str),
+    Inflate(InflateError),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io(error) => write!(f, "{}", error),
+            ImageError::UnknownFormat => write!(f, "unknown image format"),
+            ImageError::Unsupported(what) => write!(f, "unsupported {}", what),
+            ImageError::Corrupt(what) => write!(f, "corrupt image: {}", what),
+            ImageError::Inflate(error) => write!(f, "corrupt image data: {}", error),
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(error: io::Error) -> Self {
+        ImageError::Io(error)
+    }
+}
+
+impl From<InflateError> for ImageError {
+    fn from(error: InflateError) -> Self {
+        ImageError::Inflate(error)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sampling {
+    Nearest,
+    Bilinear,
+}
+
+/// Part of a bitmap in pixels, `x, y` is its bottom left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BitmapRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl BitmapRect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> BitmapRect {
+        BitmapRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Row 0 is the bottom row, like in `RenderBuffer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bitmap {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<Color>,
+}
+
+impl Bitmap {
+    /// Transparent black bitmap.
+    pub fn new(width: i32, height: i32) -> Bitmap {
+        Bitmap {
+            width,
+            height,
+            pixels: vec![Color::rgba(0, 0, 0, 0); (width * height) as usize],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Bitmap, ImageError> {
+        Bitmap::decode(&fs::read(path)?)
+    }
+
+    /// Decodes a PNG, BMP or TGA file. TGA has no signature, so anything else is tried as TGA.
+    pub fn decode(data: &[u8]) -> Result<Bitmap, ImageError> {
+        if data.starts_with(&png::SIGNATURE) {
+            png::decode(data)
+        } else if data.starts_with(b"BM") {
+            bmp::decode(data)
+        } else {
+            tga::decode(data).map_err(|error| match error {
+                ImageError::Unsupported(_) | ImageError::Corrupt(_) => ImageError::UnknownFormat,
+                error => error,
+            })
+        }
+    }
+
+    pub fn rect(&self) -> BitmapRect {
+        BitmapRect::new(0, 0, self.width, self.height)
+    }
+
+    /// Cell `index` of a sprite sheet made of `cell_width` x `cell_height` cells, counted left
+    /// to right and top to bottom. `None` for empty cells and cells that aren't in the bitmap.
+    pub fn cell(&self, cell_width: i32, cell_height: i32, index: i32) -> Option<BitmapRect> {
+        if cell_width <= 0 || cell_height <= 0 || index < 0 {
+            return None;
+        }
+
+        let columns = self.width / cell_width;
+        let rows = self.height / cell_height;
+        if columns == 0 || index >= columns * rows {
+            return None;
+        }
+
+        let column = index % columns;
+        let row = index / columns;
+        Some(BitmapRect::new(
+            column * cell_width,
+            self.height - (row + 1) * cell_height,
+            cell_width,
+            cell_height,
+        ))
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    /// `u, v` are in pixels, texel centers are at `.5`. Samples outside `source` are clamped to
+    /// its edge.
+    pub fn sample(&self, source: &BitmapRect, u: f32, v: f32, sampling: Sampling) -> Color {
+        match sampling {
+            Sampling::Nearest => self.clamped_pixel(source, u.floor() as i32, v.floor() as i32),
+            Sampling::Bilinear => {
+                let (u, v) = (u - 0.5, v - 0.5);
+                let (x, y) = (u.floor(), v.floor());
+                let (tx, ty) = (u - x, v - y);
+                let (x, y) = (x as i32, y as i32);
+
+                let bottom = self
+                    .clamped_pixel(source, x, y)
+                    .lerp(self.clamped_pixel(source, x + 1, y), tx);
+                let top = self
+                    .clamped_pixel(source, x, y + 1)
+                    .lerp(self.clamped_pixel(source, x + 1, y + 1), tx);
+                bottom.lerp(top, ty)
+            }
+        }
+    }
+
+    fn clamped_pixel(&self, source: &BitmapRect, x: i32, y: i32) -> Color {
+        let x = clamp(source.x, x, source.x + source.width - 1);
+        let y = clamp(source.y, y, source.y + source.height - 1);
+        self.pixel(clamp(0, x, self.width - 1), clamp(0, y, self.height - 1))
+    }
+
+    /// Turns rows stored top to bottom into bottom to top, or back.
+    fn flip_rows(&mut self) {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        for y in 0..height / 2 {
+            let (top, bottom) = self.pixels.split_at_mut((height - 1 - y) * width);
+            top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[..width]);
+        }
+    }
+}
+
+/// How an entity or background is textured: a bitmap, or a part of a sprite sheet, drawn with
+/// a sampling mode and an extra opacity.
+#[derive(Clone)]
+pub struct Sprite {
+    pub bitmap: Arc<Bitmap>,
+    pub source: Option<BitmapRect>,
+    pub sampling: Sampling,
+    pub alpha: f32,
+}
+
+impl Sprite {
+    pub fn new(bitmap: Arc<Bitmap>) -> Sprite {
+        Sprite {
+            bitmap,
+            source: None,
+            sampling: Sampling::Nearest,
+            alpha: 1.0,
+        }
+    }
+
+    pub fn source(&self) -> BitmapRect {
+        self.source.unwrap_or_else(|| self.bitmap.rect())
+    }
+}
+
+/// Sprites are equal if they share a bitmap, not just equal pixels.
+impl PartialEq for Sprite {
+    fn eq(&self, other: &Sprite) -> bool {
+        Arc::ptr_eq(&self.bitmap, &other.bitmap)
+            && self.source == other.source
+            && self.sampling == other.sampling
+            && self.alpha == other.alpha
+    }
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> Result<u16, ImageError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(ImageError::Corrupt("truncated header"))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Result<u32, ImageError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(ImageError::Corrupt("truncated header"))
+}
+
+/// Largest side accepted, so a corrupt header can't ask for a huge allocation.
+const MAX_SIZE: i32 = 1 << 14;
+
+fn check_size(width: i64, height: i64) -> Result<(), ImageError> {
+    if width <= 0 || height <= 0 {
+        return Err(ImageError::Corrupt("empty image"));
+    }
+    if width > MAX_SIZE as i64 || height > MAX_SIZE as i64 {
+        return Err(ImageError::Unsupported("image size"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn checker() -> Bitmap {
+        let mut bitmap = Bitmap::new(2, 2);
+        bitmap.set_pixel(0, 0, Color::BLACK);
+        bitmap.set_pixel(1, 0, Color::WHITE);
+        bitmap.set_pixel(0, 1, Color::WHITE);
+        bitmap.set_pixel(1, 1, Color::BLACK);
+        bitmap
+    }
+
+    #[test]
+    fn test_sample() {
+        let bitmap = checker();
+        let rect = bitmap.rect();
+        assert_eq!(
+            bitmap.sample(&rect, 1.2, 0.7, Sampling::Nearest),
+            Color::WHITE
+        );
+        assert_eq!(
+            bitmap.sample(&rect, 0.5, 0.5, Sampling::Bilinear),
+            Color::BLACK
+        );
+        assert_eq!(
+            bitmap.sample(&rect, 1.0, 0.5, Sampling::Bilinear),
+            Color::rgb(0x80, 0x80, 0x80)
+        );
+        // Clamped to the edge instead of wrapping around
+        assert_eq!(
+            bitmap.sample(&rect, -3.0, 0.5, Sampling::Bilinear),
+            Color::BLACK
+        );
+
+        let rect = BitmapRect::new(1, 0, 1, 1);
+        assert_eq!(
+            bitmap.sample(&rect, 0.0, 1.5, Sampling::Bilinear),
+            Color::WHITE
+        );
+    }
+
+    #[test]
+    fn test_cell() {
+        let bitmap = Bitmap::new(32, 16);
+        assert_eq!(bitmap.cell(8, 8, 0), Some(BitmapRect::new(0, 8, 8, 8)));
+        assert_eq!(bitmap.cell(8, 8, 3), Some(BitmapRect::new(24, 8, 8, 8)));
+        assert_eq!(bitmap.cell(8, 8, 5), Some(BitmapRect::new(8, 0, 8, 8)));
+
+        assert_eq!(bitmap.cell(8, 8, 8), None);
+        assert_eq!(bitmap.cell(8, 8, -1), None);
+        assert_eq!(bitmap.cell(0, 8, 0), None);
+        assert_eq!(bitmap.cell(8, -8, 0), None);
+        assert_eq!(bitmap.cell(40, 8, 0), None);
+    }
+
+    #[test]
+    fn test_flip_rows() {
+        let mut bitmap = Bitmap::new(1, 3);
+        bitmap.set_pixel(0, 0, Color::WHITE);
+        bitmap.flip_rows();
+        assert_eq!(bitmap.pixel(0, 2), Color::WHITE);
+        assert_eq!(bitmap.pixel(0, 0), Color::rgba(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        assert!(matches!(
+            Bitmap::decode(b"GIF89a"),
+            Err(ImageError::UnknownFormat)
+        ));
+    }
+}
@@ -0,0 +1,390 @@
+// PNG decoding: every colour type and bit depth, but no Adam7 interlacing.
+
+use crate::bitmap::inflate::zlib_decompress;
+use crate::bitmap::{check_size, Bitmap, ImageError};
+use crate::color::Color;
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const GRAYSCALE: u8 = 0;
+const TRUE_COLOR: u8 = 2;
+const INDEXED: u8 = 3;
+const GRAYSCALE_ALPHA: u8 = 4;
+const TRUE_COLOR_ALPHA: u8 = 6;
+
+struct Header {
+    width: i32,
+    height: i32,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            TRUE_COLOR => 3,
+            GRAYSCALE_ALPHA => 2,
+            TRUE_COLOR_ALPHA => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    fn stride(&self) -> usize {
+        (self.width as usize * self.bits_per_pixel() + 7) / 8
+    }
+}
+
+pub fn decode(data: &[u8]) -> Result<Bitmap, ImageError> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(ImageError::Corrupt("missing PNG signature"));
+    }
+
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut transparency = Vec::new();
+    let mut compressed = Vec::new();
+
+    let mut chunks = &data[SIGNATURE.len()..];
+    loop {
+        let (chunk, rest) = read_chunk(chunks)?;
+        chunks = rest;
+
+        let chunk_data = chunk.data;
+        match &chunk.chunk_type {
+            b"IHDR" => header = Some(parse_header(chunk_data)?),
+            b"PLTE" => {
+                palette = chunk_data
+                    .chunks_exact(3)
+                    .map(|rgb| Color::rgb(rgb[0], rgb[1], rgb[2]))
+                    .collect()
+            }
+            b"tRNS" => transparency = chunk_data.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(chunk_data),
+            b"IEND" => break,
+            _ => {
+                // Ancillary chunks (lowercase first letter) can be skipped, critical ones can't
+                if chunk.chunk_type[0] & 0x20 == 0 {
+                    return Err(ImageError::Unsupported("PNG critical chunk"));
+                }
+            }
+        }
+    }
+
+    let header = header.ok_or(ImageError::Corrupt("missing IHDR"))?;
+    let mut filtered = zlib_decompress(&compressed)?;
+    let stride = header.stride();
+    if filtered.len() < (stride + 1) * header.height as usize {
+        return Err(ImageError::Corrupt("truncated pixel data"));
+    }
+    unfilter(&mut filtered, stride, (header.bits_per_pixel() + 7) / 8)?;
+
+    let mut bitmap = Bitmap::new(header.width, header.height);
+    for (y, row) in filtered
+        .chunks(stride + 1)
+        .take(header.height as usize)
+        .enumerate()
+    {
+        let row = &row[1..];
+        for x in 0..header.width as usize {
+            let color = pixel_color(&header, row, x, &palette, &transparency)?;
+            // Rows are stored top to bottom
+            bitmap.set_pixel(x as i32, header.height - 1 - y as i32, color);
+        }
+    }
+
+    Ok(bitmap)
+}
+
+struct Chunk<'a> {
+    chunk_type: [u8; 4],
+    data: &'a [u8],
+}
+
+/// Returns the first chunk in `data` and what follows it.
+fn read_chunk(data: &[u8]) -> Result<(Chunk<'_>, &[u8]), ImageError> {
+    let truncated = ImageError::Corrupt("truncated chunk");
+    if data.len() < 12 {
+        return Err(truncated);
+    }
+
+    let length = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if data.len() - 12 < length {
+        return Err(truncated);
+    }
+
+    let chunk_type = [data[4], data[5], data[6], data[7]];
+    let crc_start = 8 + length;
+    let crc = &data[crc_start..crc_start + 4];
+    if u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) != crc32(&data[4..crc_start]) {
+        return Err(ImageError::Corrupt("chunk CRC mismatch"));
+    }
+
+    let chunk = Chunk {
+        chunk_type,
+        data: &data[8..crc_start],
+    };
+    Ok((chunk, &data[crc_start + 4..]))
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, ImageError> {
+    if data.len() != 13 {
+        return Err(ImageError::Corrupt("bad IHDR length"));
+    }
+
+    let width = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+    let height = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    check_size(width as i64, height as i64)?;
+
+    let header = Header {
+        width: width as i32,
+        height: height as i32,
+        bit_depth: data[8],
+        color_type: data[9],
+    };
+
+    let valid_depth = match header.color_type {
+        GRAYSCALE => [1, 2, 4, 8, 16].contains(&header.bit_depth),
+        INDEXED => [1, 2, 4, 8].contains(&header.bit_depth),
+        TRUE_COLOR | GRAYSCALE_ALPHA | TRUE_COLOR_ALPHA => [8, 16].contains(&header.bit_depth),
+        _ => return Err(ImageError::Corrupt("bad color type")),
+    };
+    if !valid_depth {
+        return Err(ImageError::Corrupt("bad bit depth"));
+    }
+    if data[10] != 0 || data[11] != 0 {
+        return Err(ImageError::Unsupported("PNG compression or filter method"));
+    }
+    if data[12] != 0 {
+        return Err(ImageError::Unsupported("interlaced PNG"));
+    }
+
+    Ok(header)
+}
+
+/// Undoes the per row filters in place. Each row starts with its filter type, `bpp` is the
+/// number of bytes per pixel rounded up to 1.
+fn unfilter(data: &mut [u8], stride: usize, bpp: usize) -> Result<(), ImageError> {
+    let mut previous: Option<usize> = None;
+    let mut start = 0;
+    while start + stride < data.len() {
+        let filter = data[start];
+        let row = start + 1;
+
+        for i in 0..stride {
+            let a = if i >= bpp { data[row + i - bpp] } else { 0 };
+            let b = previous.map(|previous| data[previous + i]).unwrap_or(0);
+            let c = match previous {
+                Some(previous) if i >= bpp => data[previous + i - bpp],
+                _ => 0,
+            };
+
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(ImageError::Corrupt("bad filter type")),
+            };
+            data[row + i] = data[row + i].wrapping_add(predictor);
+        }
+
+        previous = Some(row);
+        start += stride + 1;
+    }
+
+    Ok(())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn pixel_color(
+    header: &Header,
+    row: &[u8],
+    x: usize,
+    palette: &[Color],
+    transparency: &[u8],
+) -> Result<Color, ImageError> {
+    let depth = header.bit_depth;
+    let channels = header.channels();
+    let sample = |channel: usize| -> u16 {
+        let index = x * channels + channel;
+        match depth {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+            8 => row[index] as u16,
+            _ => {
+                let bit = index * depth as usize;
+                let shift = 8 - depth as usize - bit % 8;
+                (row[bit / 8] >> shift) as u16 & ((1 << depth) - 1)
+            }
+        }
+    };
+    let to_u8 = |value: u16| -> u8 {
+        match depth {
+            16 => (value >> 8) as u8,
+            _ => (value as u32 * 255 / ((1 << depth) - 1)) as u8,
+        }
+    };
+    // tRNS holds 16 bit samples at the image's bit depth
+    let is_transparent = |values: &[u16]| -> bool {
+        transparency.len() == values.len() * 2
+            && values.iter().enumerate().all(|(i, &value)| {
+                u16::from_be_bytes([transparency[i * 2], transparency[i * 2 + 1]]) == value
+            })
+    };
+
+    let color = match header.color_type {
+        GRAYSCALE => {
+            let gray = sample(0);
+            let alpha = if is_transparent(&[gray]) { 0 } else { 255 };
+            let gray = to_u8(gray);
+            Color::rgba(gray, gray, gray, alpha)
+        }
+        TRUE_COLOR => {
+            let (r, g, b) = (sample(0), sample(1), sample(2));
+            let alpha = if is_transparent(&[r, g, b]) { 0 } else { 255 };
+            Color::rgba(to_u8(r), to_u8(g), to_u8(b), alpha)
+        }
+        INDEXED => {
+            let index = sample(0) as usize;
+            let color = *palette
+                .get(index)
+                .ok_or(ImageError::Corrupt("palette index out of range"))?;
+            Color {
+                a: transparency.get(index).copied().unwrap_or(255),
+                ..color
+            }
+        }
+        GRAYSCALE_ALPHA => {
+            let gray = to_u8(sample(0));
+            Color::rgba(gray, gray, gray, to_u8(sample(1)))
+        }
+        _ => Color::rgba(
+            to_u8(sample(0)),
+            to_u8(sample(1)),
+            to_u8(sample(2)),
+            to_u8(sample(3)),
+        ),
+    };
+
+    Ok(color)
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+/// The CRC-32 PNG chunks are checked with.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in data {
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc ^ 0xffffffff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 20, 30), 10);
+    }
+
+    #[test]
+    fn test_rgba() {
+        let bitmap = decode(RGBA).unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (2, 2));
+        // Top row in the file is red, green
+        assert_eq!(bitmap.pixel(0, 1), Color::rgb(255, 0, 0));
+        assert_eq!(bitmap.pixel(1, 1), Color::rgb(0, 255, 0));
+        assert_eq!(bitmap.pixel(0, 0), Color::rgba(0, 0, 255, 128));
+        assert_eq!(bitmap.pixel(1, 0), Color::rgba(255, 255, 255, 0));
+    }
+
+    #[test]
+    fn test_indexed() {
+        let bitmap = decode(INDEXED_2_BIT).unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (3, 1));
+        assert_eq!(bitmap.pixel(0, 0), Color::rgba(0, 0, 0, 0));
+        assert_eq!(bitmap.pixel(1, 0), Color::rgb(255, 0, 0));
+        assert_eq!(bitmap.pixel(2, 0), Color::rgb(0, 0, 255));
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let mut data = RGBA.to_vec();
+        data[20] ^= 0xff;
+        assert!(matches!(decode(&data), Err(ImageError::Corrupt(_))));
+        assert!(matches!(
+            decode(&RGBA[..RGBA.len() - 20]),
+            Err(ImageError::Corrupt(_))
+        ));
+    }
+
+    // 2x2, true colour with alpha, the rows use the sub and Paeth filters
+    const RGBA: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x06, 0x00, 0x00, 0x00, 0x72,
+        0xb6, 0x0d, 0x24, 0x00, 0x00, 0x00, 0x03, 0x74, 0x45, 0x58, 0x74, 0x61, 0x00, 0x62, 0xdc,
+        0x49, 0xa2, 0x3b, 0x00, 0x00, 0x00, 0x17, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xfc,
+        0xcf, 0xc0, 0xf0, 0x9f, 0x11, 0x48, 0xb0, 0x30, 0x32, 0xfc, 0x6f, 0x04, 0xd2, 0x0d, 0x00,
+        0x38, 0x2d, 0x06, 0x04, 0x41, 0x92, 0x18, 0x9c, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e,
+        0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+    // 3x1, 2 bit palette indices 0, 1 and 2, index 0 is transparent
+    const INDEXED_2_BIT: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x00, 0x00, 0x00, 0x66,
+        0x8e, 0xfc, 0x27, 0x00, 0x00, 0x00, 0x09, 0x50, 0x4c, 0x54, 0x45, 0x00, 0x00, 0x00, 0xff,
+        0x00, 0x00, 0x00, 0x00, 0xff, 0x4a, 0xa5, 0xad, 0x81, 0x00, 0x00, 0x00, 0x01, 0x74, 0x52,
+        0x4e, 0x53, 0x00, 0x40, 0xe6, 0xd8, 0x66, 0x00, 0x00, 0x00, 0x03, 0x74, 0x45, 0x58, 0x74,
+        0x61, 0x00, 0x62, 0xdc, 0x49, 0xa2, 0x3b, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54,
+        0x78, 0x9c, 0x63, 0x90, 0x00, 0x00, 0x00, 0x1a, 0x00, 0x19, 0x2d, 0x88, 0xf4, 0x36, 0x00,
+        0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+}
@@ -0,0 +1,157 @@
+// Truevision TGA: 24/32 bit true colour and 8 bit greyscale, raw or run-length encoded.
+
+use crate::bitmap::{check_size, read_u16_le, Bitmap, ImageError};
+use crate::color::Color;
+
+const HEADER_SIZE: usize = 18;
+
+const TRUE_COLOR: u8 = 2;
+const GRAYSCALE: u8 = 3;
+const RLE_TRUE_COLOR: u8 = 10;
+const RLE_GRAYSCALE: u8 = 11;
+
+pub fn decode(data: &[u8]) -> Result<Bitmap, ImageError> {
+    if data.len() < HEADER_SIZE {
+        return Err(ImageError::Corrupt("truncated header"));
+    }
+
+    let id_length = data[0] as usize;
+    let color_map_type = data[1];
+    let image_type = data[2];
+    let color_map_length = read_u16_le(data, 5)? as usize;
+    let color_map_entry_bits = data[7] as usize;
+    let width = read_u16_le(data, 12)? as i32;
+    let height = read_u16_le(data, 14)? as i32;
+    let bits_per_pixel = data[16];
+    let descriptor = data[17];
+
+    if color_map_type > 1 {
+        return Err(ImageError::Corrupt("bad color map type"));
+    }
+
+    let is_gray = match image_type {
+        TRUE_COLOR | RLE_TRUE_COLOR => false,
+        GRAYSCALE | RLE_GRAYSCALE => true,
+        _ => return Err(ImageError::Unsupported("TGA image type")),
+    };
+    let bytes_per_pixel = match (is_gray, bits_per_pixel) {
+        (false, 24) => 3,
+        (false, 32) => 4,
+        (true, 8) => 1,
+        _ => return Err(ImageError::Unsupported("TGA bit depth")),
+    };
+    check_size(width as i64, height as i64)?;
+
+    // A color map can be present even when it isn't used
+    let color_map_size = if color_map_type == 1 {
+        color_map_length * ((color_map_entry_bits + 7) / 8)
+    } else {
+        0
+    };
+    let start = HEADER_SIZE + id_length + color_map_size;
+    let mut data = data
+        .get(start..)
+        .ok_or(ImageError::Corrupt("truncated header"))?;
+
+    let pixel_count = (width * height) as usize;
+    let mut pixels = Vec::with_capacity(pixel_count);
+    let is_rle = image_type == RLE_TRUE_COLOR || image_type == RLE_GRAYSCALE;
+    while pixels.len() < pixel_count {
+        let (count, is_run) = if is_rle {
+            let (&packet, rest) = data
+                .split_first()
+                .ok_or(ImageError::Corrupt("truncated pixel data"))?;
+            data = rest;
+            ((packet & 0x7f) as usize + 1, packet & 0x80 != 0)
+        } else {
+            (pixel_count, false)
+        };
+        let count = count.min(pixel_count - pixels.len());
+
+        let read_count = if is_run { 1 } else { count };
+        let bytes = data
+            .get(..read_count * bytes_per_pixel)
+            .ok_or(ImageError::Corrupt("truncated pixel data"))?;
+        data = &data[read_count * bytes_per_pixel..];
+
+        for pixel in bytes.chunks(bytes_per_pixel) {
+            let color = match pixel {
+                [gray] => Color::rgb(*gray, *gray, *gray),
+                [b, g, r] => Color::rgb(*r, *g, *b),
+                _ => Color::rgba(pixel[2], pixel[1], pixel[0], pixel[3]),
+            };
+            let repeat = if is_run { count } else { 1 };
+            pixels.extend(std::iter::repeat(color).take(repeat));
+        }
+    }
+
+    let mut bitmap = Bitmap {
+        width,
+        height,
+        pixels,
+    };
+
+    // Rows are bottom to top unless the origin is at the top
+    if descriptor & 0x20 != 0 {
+        bitmap.flip_rows();
+    }
+
+    Ok(bitmap)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tga(image_type: u8, width: u16, height: u16, bits: u8, descriptor: u8) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data[2] = image_type;
+        data[12..14].copy_from_slice(&width.to_le_bytes());
+        data[14..16].copy_from_slice(&height.to_le_bytes());
+        data[16] = bits;
+        data[17] = descriptor;
+        data
+    }
+
+    #[test]
+    fn test_true_color() {
+        let mut data = tga(TRUE_COLOR, 2, 1, 32, 0);
+        data.extend_from_slice(&[0x10, 0x20, 0x30, 0x40, 0xff, 0xff, 0xff, 0xff]);
+        let bitmap = decode(&data).unwrap();
+        assert_eq!(bitmap.pixel(0, 0), Color::rgba(0x30, 0x20, 0x10, 0x40));
+        assert_eq!(bitmap.pixel(1, 0), Color::rgba(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn test_top_origin() {
+        let mut data = tga(GRAYSCALE, 1, 2, 8, 0x20);
+        data.extend_from_slice(&[0xff, 0x00]);
+        let bitmap = decode(&data).unwrap();
+        assert_eq!(bitmap.pixel(0, 1), Color::WHITE);
+        assert_eq!(bitmap.pixel(0, 0), Color::BLACK);
+    }
+
+    #[test]
+    fn test_rle() {
+        let mut data = tga(RLE_TRUE_COLOR, 5, 1, 24, 0);
+        // Run of 3 red pixels, then 2 raw pixels
+        data.extend_from_slice(&[0x82, 0, 0, 255, 0x01, 0, 255, 0, 255, 0, 0]);
+        let bitmap = decode(&data).unwrap();
+        let red = Color::rgb(255, 0, 0);
+        assert_eq!(
+            bitmap.pixels,
+            vec![red, red, red, Color::rgb(0, 255, 0), Color::rgb(0, 0, 255)]
+        );
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let mut data = tga(RLE_TRUE_COLOR, 5, 1, 24, 0);
+        data.extend_from_slice(&[0x82, 0, 0]);
+        assert!(matches!(decode(&data), Err(ImageError::Corrupt(_))));
+        assert!(matches!(
+            decode(&tga(1, 1, 1, 8, 0)),
+            Err(ImageError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::math::Vec2;
+
+/// How the camera's view box is fitted into a window with a different aspect ratio.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fit {
+    /// The whole view box is visible, the window shows more of the world on two sides.
+    Contain,
+    /// Like `Contain`, but what lies outside the view box is covered by `draw_letterbox`.
+    Letterbox,
+    /// The view box fills the window, two of its sides are cropped.
+    Cover,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    /// World point at the center of the window.
+    pub position: Vec2,
+    pub zoom: f32,
+    /// Radians, counter-clockwise. The world appears rotated the other way.
+    pub rotation: f32,
+    /// World units visible vertically at zoom 1, the view box is `aspect` times as wide.
+    pub view_height: f32,
+    pub aspect: f32,
+    pub fit: Fit,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: Vec2::zero(),
+            zoom: 1.0,
+            rotation: 0.0,
+            view_height: 100.0,
+            aspect: 16.0 / 9.0,
+            fit: Fit::Contain,
+        }
+    }
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera::default()
+    }
+
+    pub fn pixels_per_unit(&self, width: i32, height: i32) -> f32 {
+        let fit_width = width as f32 / (self.view_height * self.aspect);
+        let fit_height = height as f32 / self.view_height;
+
+        let scale = match self.fit {
+            Fit::Contain | Fit::Letterbox => fit_width.min(fit_height),
+            Fit::Cover => fit_width.max(fit_height),
+        };
+        scale * self.zoom
+    }
+
+    pub fn world_to_pixels(&self, p: Vec2, width: i32, height: i32) -> Vec2 {
+        let scale = self.pixels_per_unit(width, height);
+        let p = (p - self.position).rotated(-self.rotation) * scale;
+        Vec2::new(p.x + width as f32 * 0.5, p.y + height as f32 * 0.5)
+    }
+
+    pub fn pixels_to_world(&self, p: Vec2, width: i32, height: i32) -> Vec2 {
+        let scale = self.pixels_per_unit(width, height);
+        let p = Vec2::new(p.x - width as f32 * 0.5, p.y - height as f32 * 0.5);
+        (p / scale).rotated(self.rotation) + self.position
+    }
+
+    /// Pixel rect, as min and max corners, covered by the view box at zoom 1 and without
+    /// rotation. This is the area left uncovered by letterboxing.
+    pub fn viewport(&self, width: i32, height: i32) -> (Vec2, Vec2) {
+        let scale = self.pixels_per_unit(width, height) / self.zoom;
+        let half_size = Vec2::new(
+            (self.view_height * self.aspect * scale * 0.5).min(width as f32 * 0.5),
+            (self.view_height * scale * 0.5).min(height as f32 * 0.5),
+        );
+        let center = Vec2::new(width as f32 * 0.5, height as f32 * 0.5);
+        (center - half_size, center + half_size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!((a - b).len() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_fit() {
+        let mut camera = Camera::new();
+        camera.aspect = 2.0;
+
+        // Wider than the view box, height limits
+        assert_eq!(camera.pixels_per_unit(400, 100), 1.0);
+        // Taller, width limits
+        assert_eq!(camera.pixels_per_unit(200, 400), 1.0);
+
+        camera.fit = Fit::Cover;
+        assert_eq!(camera.pixels_per_unit(400, 100), 2.0);
+        assert_eq!(camera.pixels_per_unit(200, 400), 4.0);
+
+        camera.zoom = 0.5;
+        assert_eq!(camera.pixels_per_unit(200, 400), 2.0);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut camera = Camera::new();
+        camera.position = Vec2::new(10.0, -5.0);
+        camera.zoom = 1.5;
+        camera.rotation = 0.3;
+
+        let p = Vec2::new(-42.0, 17.0);
+        let pixels = camera.world_to_pixels(p, 640, 360);
+        assert_near(camera.pixels_to_world(pixels, 640, 360), p);
+    }
+
+    #[test]
+    fn test_transform() {
+        let mut camera = Camera::new();
+        camera.aspect = 2.0;
+        assert_near(
+            camera.world_to_pixels(Vec2::new(50.0, 25.0), 400, 200),
+            Vec2::new(300.0, 150.0),
+        );
+
+        // Looking at the point puts it in the center
+        camera.position = Vec2::new(50.0, 25.0);
+        assert_near(
+            camera.world_to_pixels(Vec2::new(50.0, 25.0), 400, 200),
+            Vec2::new(200.0, 100.0),
+        );
+
+        // Turning the camera left turns the world right
+        camera.position = Vec2::zero();
+        camera.rotation = std::f32::consts::FRAC_PI_2;
+        assert_near(
+            camera.world_to_pixels(Vec2::new(0.0, 10.0), 400, 200),
+            Vec2::new(220.0, 100.0),
+        );
+    }
+
+    #[test]
+    fn test_viewport() {
+        let mut camera = Camera::new();
+        camera.aspect = 2.0;
+        assert_eq!(
+            camera.viewport(400, 100),
+            (Vec2::new(100.0, 0.0), Vec2::new(300.0, 100.0))
+        );
+        assert_eq!(
+            camera.viewport(200, 400),
+            (Vec2::new(0.0, 150.0), Vec2::new(200.0, 250.0))
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::math::clamp;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// `src * a + dst * (1 - a)`
+    Alpha,
+    /// `dst + src * a`, saturating
+    Additive,
+    /// `dst * src`, faded in by `a`
+    Multiply,
+}
+
+/// 8-bit RGBA colour. Render buffers store colours as `0x00RRGGBB`, alpha only matters while
+/// blending.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color::rgba(r, g, b, 255)
+    }
+
+    /// Opaque colour from `0x00RRGGBB`, the render buffer layout.
+    pub const fn from_u32(color: u32) -> Color {
+        Color::rgb((color >> 16) as u8, (color >> 8) as u8, color as u8)
+    }
+
+    /// Colour from `0xAARRGGBB`.
+    pub const fn unpack_argb(color: u32) -> Color {
+        Color::rgba(
+            (color >> 16) as u8,
+            (color >> 8) as u8,
+            color as u8,
+            (color >> 24) as u8,
+        )
+    }
+
+    /// `0x00RRGGBB`, alpha is dropped.
+    pub const fn to_u32(self) -> u32 {
+        (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
+
+    /// `0xAARRGGBB`
+    pub const fn pack_argb(self) -> u32 {
+        (self.a as u32) << 24 | self.to_u32()
+    }
+
+    pub fn with_alpha(self, alpha: f32) -> Color {
+        Color {
+            a: unit_to_u8(alpha),
+            ..self
+        }
+    }
+
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        let t = unit_to_u8(t) as u32;
+        Color::rgba(
+            lerp_u8(self.r, other.r, t),
+            lerp_u8(self.g, other.g, t),
+            lerp_u8(self.b, other.b, t),
+            lerp_u8(self.a, other.a, t),
+        )
+    }
+
+    /// Blends `self` over `dst` (`0x00RRGGBB`), returning the new `0x00RRGGBB` value.
+    #[inline(always)]
+    pub fn blend(self, dst: u32, mode: BlendMode) -> u32 {
+        let dst = Color::from_u32(dst);
+        let a = self.a as u32;
+
+        let blend_channel = |src: u8, dst: u8| -> u8 {
+            let (src, dst) = (src as u32, dst as u32);
+            match mode {
+                BlendMode::Alpha => lerp_u8(dst as u8, src as u8, a),
+                BlendMode::Additive => (dst + mul_u8(src, a)).min(255) as u8,
+                BlendMode::Multiply => lerp_u8(dst as u8, mul_u8(dst, src) as u8, a),
+            }
+        };
+
+        Color::rgb(
+            blend_channel(self.r, dst.r),
+            blend_channel(self.g, dst.g),
+            blend_channel(self.b, dst.b),
+        )
+        .to_u32()
+    }
+}
+
+impl From<u32> for Color {
+    fn from(color: u32) -> Self {
+        Color::from_u32(color)
+    }
+}
+
+fn unit_to_u8(value: f32) -> u8 {
+    (clamp(0.0, value, 1.0) * 255.0 + 0.5) as u8
+}
+
+/// `a * b / 255`, rounded
+#[inline(always)]
+fn mul_u8(a: u32, b: u32) -> u32 {
+    let t = a * b + 128;
+    (t + (t >> 8)) >> 8
+}
+
+/// `a + (b - a) * t / 255`
+#[inline(always)]
+fn lerp_u8(a: u8, b: u8, t: u32) -> u8 {
+    (mul_u8(a as u32, 255 - t) + mul_u8(b as u32, t)) as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packing() {
+        let color = Color::unpack_argb(0x80112233);
+        assert_eq!(color, Color::rgba(0x11, 0x22, 0x33, 0x80));
+        assert_eq!(color.pack_argb(), 0x80112233);
+        assert_eq!(color.to_u32(), 0x00112233);
+        assert_eq!(Color::from_u32(0xff445566), Color::rgb(0x44, 0x55, 0x66));
+    }
+
+    #[test]
+    fn test_alpha_blend() {
+        assert_eq!(Color::WHITE.blend(0x000000, BlendMode::Alpha), 0xffffff);
+        assert_eq!(
+            Color::WHITE
+                .with_alpha(0.0)
+                .blend(0x123456, BlendMode::Alpha),
+            0x123456
+        );
+        assert_eq!(
+            Color::WHITE
+                .with_alpha(0.5)
+                .blend(0x000000, BlendMode::Alpha),
+            0x808080
+        );
+        assert_eq!(
+            Color::rgb(0, 0, 255)
+                .with_alpha(0.5)
+                .blend(0xff0000, BlendMode::Alpha),
+            0x7f0080
+        );
+    }
+
+    #[test]
+    fn test_additive_blend() {
+        assert_eq!(
+            Color::rgb(0x80, 0x10, 0).blend(0x901000, BlendMode::Additive),
+            0xff2000
+        );
+        assert_eq!(
+            Color::WHITE
+                .with_alpha(0.0)
+                .blend(0x101010, BlendMode::Additive),
+            0x101010
+        );
+    }
+
+    #[test]
+    fn test_multiply_blend() {
+        assert_eq!(Color::BLACK.blend(0xffffff, BlendMode::Multiply), 0x000000);
+        assert_eq!(Color::WHITE.blend(0x123456, BlendMode::Multiply), 0x123456);
+        assert_eq!(
+            Color::rgb(0x80, 0x80, 0x80).blend(0xff00ff, BlendMode::Multiply),
+            0x800080
+        );
+    }
+
+    #[test]
+    fn test_lerp() {
+        assert_eq!(Color::BLACK.lerp(Color::WHITE, 0.0), Color::BLACK);
+        assert_eq!(Color::BLACK.lerp(Color::WHITE, 1.0), Color::WHITE);
+        assert_eq!(
+            Color::BLACK.lerp(Color::WHITE, 0.5),
+            Color::rgb(0x80, 0x80, 0x80)
+        );
+    }
+}
@@ -0,0 +1,777 @@
+// Deferred rendering for `RenderBuffer::recording`. Draw calls are recorded in pixel space,
+// binned into tiles of whole rows and the tiles are rasterized in parallel by a `Rasterizer`'s
+// worker threads, which live as long as it does. Every tile replays
+// its commands in order through a `RenderBuffer` that only holds the tile's rows, so the result
+// is the same as drawing immediately.
+
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::bitmap::Sprite;
+use crate::color::Color;
+use crate::dirty_rects::PixelRect;
+use crate::font::{text_width, TextAlign, GLYPH_HEIGHT, LINE_ADVANCE};
+use crate::math::Vec2;
+use crate::software_rendering::{Paint, RenderBuffer};
+
+/// Tiles span the full width, so each one owns a contiguous slice of the buffer.
+pub const TILE_ROWS: i32 = 32;
+
+/// A pixel space draw call, one per `RenderBuffer` primitive.
+#[derive(Clone, PartialEq)]
+pub(crate) enum Command {
+    Clear(u32),
+    FadeToBlack(f32),
+    Line {
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        paint: Paint,
+    },
+    LineAa {
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        color: Color,
+    },
+    Rect {
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        paint: Paint,
+    },
+    RoundedRect {
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        radius: f32,
+        color: u32,
+    },
+    Ellipse {
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+        paint: Paint,
+    },
+    Polygon {
+        points: Vec<Vec2>,
+        paint: Paint,
+    },
+    CircleOutline {
+        cx: i32,
+        cy: i32,
+        radius: i32,
+        color: u32,
+    },
+    EllipseOutline {
+        cx: i32,
+        cy: i32,
+        rx: i32,
+        ry: i32,
+        color: u32,
+    },
+    Text {
+        x: i32,
+        y: i32,
+        text: String,
+        scale: i32,
+        color: u32,
+        align: TextAlign,
+    },
+    Bitmap {
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        sprite: Sprite,
+    },
+    BitmapMapped {
+        corners: [Vec2; 4],
+        sprite: Sprite,
+    },
+}
+
+impl Command {
+    /// Pixels the command may touch. It's used for binning and finding what changed, so it may
+    /// be larger than what's drawn. `None` covers the whole frame.
+    pub(crate) fn bounds(&self) -> Option<PixelRect> {
+        // Float shapes get a pixel of slack on every side for rounding
+        fn float_bounds(points: &[Vec2]) -> Option<PixelRect> {
+            let min = points.iter().fold(Vec2::new(f32::MAX, f32::MAX), |min, p| {
+                Vec2::new(min.x.min(p.x), min.y.min(p.y))
+            });
+            let max = points.iter().fold(Vec2::new(f32::MIN, f32::MIN), |max, p| {
+                Vec2::new(max.x.max(p.x), max.y.max(p.y))
+            });
+            Some(PixelRect::new(
+                min.x.floor() as i32 - 1,
+                min.y.floor() as i32 - 1,
+                max.x.ceil() as i32 + 2,
+                max.y.ceil() as i32 + 2,
+            ))
+        }
+
+        match self {
+            Command::Clear(_) | Command::FadeToBlack(_) => None,
+            Command::Line { x0, y0, x1, y1, .. } => Some(PixelRect::new(
+                *x0.min(x1),
+                *y0.min(y1),
+                x0.max(x1) + 1,
+                y0.max(y1) + 1,
+            )),
+            Command::LineAa { x0, y0, x1, y1, .. } => {
+                float_bounds(&[Vec2::new(*x0, *y0), Vec2::new(*x1, *y1)])
+            }
+            Command::Rect { x0, y0, x1, y1, .. } => Some(PixelRect::new(*x0, *y0, *x1, *y1)),
+            Command::RoundedRect { x0, y0, x1, y1, .. }
+            | Command::Bitmap { x0, y0, x1, y1, .. } => {
+                float_bounds(&[Vec2::new(*x0, *y0), Vec2::new(*x1, *y1)])
+            }
+            Command::Ellipse { cx, cy, rx, ry, .. } => {
+                float_bounds(&[Vec2::new(cx - rx, cy - ry), Vec2::new(cx + rx, cy + ry)])
+            }
+            Command::Polygon { points, .. } => float_bounds(points),
+            Command::BitmapMapped { corners, .. } => float_bounds(corners),
+            Command::CircleOutline { cx, cy, radius, .. } => Some(PixelRect::new(
+                cx - radius,
+                cy - radius,
+                cx + radius + 1,
+                cy + radius + 1,
+            )),
+            Command::EllipseOutline { cx, cy, rx, ry, .. } => {
+                Some(PixelRect::new(cx - rx, cy - ry, cx + rx + 1, cy + ry + 1))
+            }
+            Command::Text {
+                x, y, text, scale, ..
+            } => {
+                // Wide enough for any alignment
+                let scale = (*scale).max(1);
+                let width = text_width(text) * scale;
+                let line_count = text.lines().count() as i32;
+                Some(PixelRect::new(
+                    x - width,
+                    *y,
+                    x + width + 1,
+                    y + (line_count * LINE_ADVANCE + GLYPH_HEIGHT) * scale,
+                ))
+            }
+        }
+    }
+
+    fn execute(&self, render_buffer: &mut RenderBuffer) {
+        match self {
+            Command::Clear(color) => render_buffer.clear(*color),
+            Command::FadeToBlack(amount) => render_buffer.fade_to_black(*amount),
+            Command::Line {
+                x0,
+                y0,
+                x1,
+                y1,
+                paint,
+            } => render_buffer.draw_line_painted(*x0, *y0, *x1, *y1, *paint),
+            Command::LineAa {
+                x0,
+                y0,
+                x1,
+                y1,
+                color,
+            } => render_buffer.draw_line_aa_in_pixels(*x0, *y0, *x1, *y1, *color),
+            Command::Rect {
+                x0,
+                y0,
+                x1,
+                y1,
+                paint,
+            } => render_buffer.fill_rect(*x0, *y0, *x1, *y1, *paint),
+            Command::RoundedRect {
+                x0,
+                y0,
+                x1,
+                y1,
+                radius,
+                color,
+            } => render_buffer.draw_rounded_rect_in_pixels(*x0, *y0, *x1, *y1, *radius, *color),
+            Command::Ellipse {
+                cx,
+                cy,
+                rx,
+                ry,
+                paint,
+            } => render_buffer.fill_ellipse(*cx, *cy, *rx, *ry, *paint),
+            Command::Polygon { points, paint } => render_buffer.fill_polygon(points, *paint),
+            Command::CircleOutline {
+                cx,
+                cy,
+                radius,
+                color,
+            } => render_buffer.draw_circle_outline_in_pixels(*cx, *cy, *radius, *color),
+            Command::EllipseOutline {
+                cx,
+                cy,
+                rx,
+                ry,
+                color,
+            } => render_buffer.draw_ellipse_outline_in_pixels(*cx, *cy, *rx, *ry, *color),
+            Command::Text {
+                x,
+                y,
+                text,
+                scale,
+                color,
+                align,
+            } => render_buffer.draw_text_in_pixels(*x, *y, text, *scale, *color, *align),
+            Command::Bitmap {
+                x0,
+                y0,
+                x1,
+                y1,
+                sprite,
+            } => render_buffer.draw_bitmap_in_pixels(*x0, *y0, *x1, *y1, sprite),
+            Command::BitmapMapped { corners, sprite } => {
+                render_buffer.draw_bitmap_mapped(corners, sprite)
+            }
+        }
+    }
+}
+
+impl Hash for Command {
+    /// Floats are hashed by their bits. `0.0` and `-0.0` compare equal but hash differently,
+    /// which only makes a command look changed.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        fn floats<H: Hasher>(values: &[f32], state: &mut H) {
+            values.iter().for_each(|value| value.to_bits().hash(state));
+        }
+        fn paint<H: Hasher>(paint: &Paint, state: &mut H) {
+            match paint {
+                Paint::Solid(color) => color.hash(state),
+                Paint::Blended(color, mode) => {
+                    color.pack_argb().hash(state);
+                    (*mode as u8).hash(state);
+                }
+            }
+        }
+        fn sprite<H: Hasher>(sprite: &Sprite, state: &mut H) {
+            (Arc::as_ptr(&sprite.bitmap) as usize).hash(state);
+            sprite.alpha.to_bits().hash(state);
+        }
+
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Command::Clear(color) => color.hash(state),
+            Command::FadeToBlack(amount) => floats(&[*amount], state),
+            Command::Line {
+                x0,
+                y0,
+                x1,
+                y1,
+                paint: p,
+            }
+            | Command::Rect {
+                x0,
+                y0,
+                x1,
+                y1,
+                paint: p,
+            } => {
+                [x0, y0, x1, y1].hash(state);
+                paint(p, state);
+            }
+            Command::LineAa {
+                x0,
+                y0,
+                x1,
+                y1,
+                color,
+            } => {
+                floats(&[*x0, *y0, *x1, *y1], state);
+                color.pack_argb().hash(state);
+            }
+            Command::RoundedRect {
+                x0,
+                y0,
+                x1,
+                y1,
+                radius,
+                color,
+            } => {
+                floats(&[*x0, *y0, *x1, *y1, *radius], state);
+                color.hash(state);
+            }
+            Command::Ellipse {
+                cx,
+                cy,
+                rx,
+                ry,
+                paint: p,
+            } => {
+                floats(&[*cx, *cy, *rx, *ry], state);
+                paint(p, state);
+            }
+            Command::Polygon { points, paint: p } => {
+                points
+                    .iter()
+                    .for_each(|point| floats(&[point.x, point.y], state));
+                paint(p, state);
+            }
+            Command::CircleOutline {
+                cx,
+                cy,
+                radius,
+                color,
+            } => [*cx, *cy, *radius, *color as i32].hash(state),
+            Command::EllipseOutline {
+                cx,
+                cy,
+                rx,
+                ry,
+                color,
+            } => [*cx, *cy, *rx, *ry, *color as i32].hash(state),
+            Command::Text {
+                x,
+                y,
+                text,
+                scale,
+                color,
+                ..
+            } => {
+                [*x, *y, *scale, *color as i32].hash(state);
+                text.hash(state);
+            }
+            Command::Bitmap {
+                x0,
+                y0,
+                x1,
+                y1,
+                sprite: s,
+            } => {
+                floats(&[*x0, *y0, *x1, *y1], state);
+                sprite(s, state);
+            }
+            Command::BitmapMapped { corners, sprite: s } => {
+                corners
+                    .iter()
+                    .for_each(|corner| floats(&[corner.x, corner.y], state));
+                sprite(s, state);
+            }
+        }
+    }
+}
+
+/// Indices of the commands touching each tile, in recording order.
+fn bin(commands: &[Command], tile_count: usize) -> Vec<Vec<usize>> {
+    let mut bins = vec![Vec::new(); tile_count];
+    let last_tile = tile_count as i32 - 1;
+    for (index, command) in commands.iter().enumerate() {
+        let (first, last) = match command.bounds() {
+            Some(bounds) => {
+                if bounds.is_empty() || bounds.y1 <= 0 || bounds.y0 >= tile_count as i32 * TILE_ROWS
+                {
+                    continue;
+                }
+                (
+                    bounds.y0.div_euclid(TILE_ROWS).max(0),
+                    (bounds.y1 - 1).div_euclid(TILE_ROWS).min(last_tile),
+                )
+            }
+            None => (0, last_tile),
+        };
+
+        for tile in bins[first as usize..=last as usize].iter_mut() {
+            tile.push(index);
+        }
+    }
+    bins
+}
+
+/// Threads that rasterize tiles, started once and kept across frames. The thread flushing a
+/// frame draws tiles too, so `threads` counts it.
+pub struct Rasterizer {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    work_ready: Condvar,
+    work_done: Condvar,
+}
+
+struct State {
+    job: Option<Job>,
+    generation: u64,
+    busy: usize,
+    panicked: bool,
+    shutdown: bool,
+}
+
+/// Draws tiles until none are left. Only valid until `Rasterizer::run` returns, which waits
+/// for every worker to be done with it.
+#[derive(Clone, Copy)]
+struct Job(*const (dyn Fn() + Sync));
+
+// Safety: the closure behind the pointer is `Sync`, and `run` keeps it alive while it's shared.
+unsafe impl Send for Job {}
+
+impl Default for Rasterizer {
+    /// One thread per core.
+    fn default() -> Self {
+        let threads = thread::available_parallelism()
+            .map(|threads| threads.get())
+            .unwrap_or(1);
+        Rasterizer::new(threads)
+    }
+}
+
+impl Rasterizer {
+    pub fn new(threads: usize) -> Rasterizer {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                job: None,
+                generation: 0,
+                busy: 0,
+                panicked: false,
+                shutdown: false,
+            }),
+            work_ready: Condvar::new(),
+            work_done: Condvar::new(),
+        });
+
+        let workers = (1..threads.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || work(&shared))
+            })
+            .collect();
+
+        Rasterizer { shared, workers }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len() + 1
+    }
+
+    /// Runs `job` on this thread and every worker, returning once all of them are done.
+    fn run(&self, job: &(dyn Fn() + Sync)) {
+        if self.workers.is_empty() {
+            job();
+            return;
+        }
+
+        // Safety: the lifetime is erased so the workers can share the job. It's never used after
+        // `busy` drops back to zero, which is waited for below even if the job panics.
+        let shared_job: &'static (dyn Fn() + Sync) = unsafe { std::mem::transmute(job) };
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.job = Some(Job(shared_job));
+            state.generation += 1;
+            state.busy = self.workers.len();
+            state.panicked = false;
+        }
+        self.shared.work_ready.notify_all();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(job));
+
+        let mut state = self.shared.state.lock().unwrap();
+        while state.busy > 0 {
+            state = self.shared.work_done.wait(state).unwrap();
+        }
+        state.job = None;
+        let panicked = state.panicked;
+        drop(state);
+
+        if let Err(payload) = result {
+            panic::resume_unwind(payload);
+        }
+        assert!(!panicked, "a rasterizer worker panicked");
+    }
+}
+
+impl Drop for Rasterizer {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.work_ready.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work(shared: &Shared) {
+    let mut generation = 0;
+    loop {
+        let job = {
+            let mut state = shared.state.lock().unwrap();
+            while !state.shutdown && state.generation == generation {
+                state = shared.work_ready.wait(state).unwrap();
+            }
+            if state.shutdown {
+                return;
+            }
+            generation = state.generation;
+            state.job
+        };
+
+        // Safety: `run` doesn't return, so the job stays alive, until `busy` is back to zero
+        let result = match job {
+            Some(Job(job)) => panic::catch_unwind(AssertUnwindSafe(|| unsafe { (*job)() })),
+            None => Ok(()),
+        };
+
+        let mut state = shared.state.lock().unwrap();
+        state.panicked |= result.is_err();
+        state.busy -= 1;
+        if state.busy == 0 {
+            shared.work_done.notify_all();
+        }
+    }
+}
+
+/// Draws `commands` into the `width` x `height` frame in `pixels`, only inside `clips`, which
+/// must not overlap. The rasterizer's threads take the next undrawn tile until none are left.
+pub(crate) fn rasterize(
+    commands: &[Command],
+    pixels: &mut [u32],
+    width: i32,
+    height: i32,
+    clips: &[PixelRect],
+    rasterizer: &Rasterizer,
+) {
+    if width <= 0 || height <= 0 || commands.is_empty() || clips.is_empty() {
+        return;
+    }
+
+    let tile_size = (TILE_ROWS * width) as usize;
+    let pixels = &mut pixels[..(width * height) as usize];
+    let bins = bin(commands, (pixels.len() + tile_size - 1) / tile_size);
+    let bounds: Vec<_> = commands.iter().map(Command::bounds).collect();
+
+    let draw_tile = |tile_index: usize, tile: &mut [u32]| {
+        let row0 = tile_index as i32 * TILE_ROWS;
+        let rows = PixelRect::new(0, row0, width, row0 + TILE_ROWS);
+        for clip in clips.iter().map(|clip| clip.intersection(&rows)) {
+            if clip.is_empty() {
+                continue;
+            }
+
+            let mut render_buffer = RenderBuffer::tile(tile, width, height, row0, clip);
+            for &index in bins[tile_index].iter() {
+                if bounds[index].map_or(true, |bounds| bounds.intersects(&clip)) {
+                    commands[index].execute(&mut render_buffer);
+                }
+            }
+        }
+    };
+
+    if rasterizer.threads() == 1 || bins.len() == 1 {
+        for (tile_index, tile) in pixels.chunks_mut(tile_size).enumerate() {
+            draw_tile(tile_index, tile);
+        }
+        return;
+    }
+
+    let tiles = Mutex::new(pixels.chunks_mut(tile_size).enumerate());
+    rasterizer.run(&|| loop {
+        // The lock is only held while taking a tile
+        let next = tiles.lock().unwrap().next();
+        match next {
+            Some((tile_index, tile)) => draw_tile(tile_index, tile),
+            None => break,
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bitmap::{Bitmap, Sampling};
+    use crate::color::BlendMode;
+    use crate::dirty_rects::FrameHistory;
+
+    const WIDTH: i32 = 150;
+    const HEIGHT: i32 = 110;
+
+    fn sprite() -> Sprite {
+        let mut bitmap = Bitmap::new(2, 2);
+        bitmap.set_pixel(0, 0, Color::rgb(255, 0, 0));
+        bitmap.set_pixel(1, 1, Color::rgba(0, 0, 255, 128));
+        Sprite::new(Arc::new(bitmap))
+    }
+
+    /// `offset` moves some of the shapes, for testing what changes between frames.
+    fn draw_scene(render_buffer: &mut RenderBuffer, sprite: &Sprite, offset: i32) {
+        let smooth = Sprite {
+            sampling: Sampling::Bilinear,
+            ..sprite.clone()
+        };
+
+        render_buffer.clear(0x202020);
+        render_buffer.draw_rect_in_pixels(-10, 20, 70, 45, 0x00ff00);
+        render_buffer.draw_rect_blended_in_pixels(
+            30,
+            -5,
+            90,
+            200,
+            Color::rgba(255, 0, 0, 100),
+            BlendMode::Additive,
+        );
+        render_buffer.draw_line_in_pixels(-20, -30, 170, 130, 0xffffff);
+        render_buffer.draw_line_blended_in_pixels(
+            0,
+            100,
+            140,
+            3,
+            Color::rgba(0, 0, 255, 200),
+            BlendMode::Alpha,
+        );
+        render_buffer.draw_line_aa_in_pixels(5.3, 60.7, 120.2, 33.1, Color::rgb(255, 255, 0));
+        render_buffer.draw_circle_in_pixels(75.5, 55.2, 40.3, 0x8000ff);
+        render_buffer.draw_circle_in_pixels(20.5 + offset as f32, 95.0, 6.0, 0xffff00);
+        render_buffer.draw_rounded_rect_in_pixels(10.2, 70.5, 60.8, 105.1, 8.0, 0x00ffff);
+        render_buffer.draw_polygon_in_pixels(
+            &[
+                Vec2::new(100.0, 10.0),
+                Vec2::new(145.0, 40.0),
+                Vec2::new(120.0, 95.0),
+            ],
+            0xff8000,
+        );
+        render_buffer.draw_circle_outline_in_pixels(40, 33, 31, 0xff00ff);
+        render_buffer.draw_ellipse_outline_in_pixels(100, 60, 45, 20, 0x808080);
+        render_buffer.draw_text_in_pixels(75, 30, "TILES\nDRAW ME", 2, 0xffffff, TextAlign::Center);
+        render_buffer.draw_text_in_pixels(120, 5 - offset, "MOVE", 1, 0x00ff00, TextAlign::Right);
+        render_buffer.draw_bitmap_in_pixels(90.3, 65.0, 140.0, 108.6, sprite);
+        render_buffer.camera.rotation = 0.4;
+        render_buffer.draw_bitmap(Vec2::new(-40.0, -20.0), Vec2::new(20.0, 15.0), &smooth);
+        render_buffer.draw_rect_blended(
+            Vec2::new(30.0, 20.0),
+            Vec2::new(25.0, 5.0),
+            Color::rgba(0, 255, 0, 128),
+            BlendMode::Multiply,
+        );
+        render_buffer.fade_to_black(0.25);
+    }
+
+    #[test]
+    fn test_same_as_immediate() {
+        let sprite = sprite();
+        let mut expected = vec![0; (WIDTH * HEIGHT) as usize];
+        draw_scene(
+            &mut RenderBuffer::new(&mut expected, WIDTH, HEIGHT),
+            &sprite,
+            0,
+        );
+
+        for &threads in [1, 2, 3, 8].iter() {
+            // The same workers draw every frame
+            let rasterizer = Rasterizer::new(threads);
+            for _ in 0..2 {
+                let mut pixels = vec![0; (WIDTH * HEIGHT) as usize];
+                let mut render_buffer = RenderBuffer::recording(&mut pixels, WIDTH, HEIGHT);
+                draw_scene(&mut render_buffer, &sprite, 0);
+                render_buffer.flush(&rasterizer);
+                drop(render_buffer);
+                assert!(pixels == expected, "{} threads", threads);
+            }
+        }
+    }
+
+    #[test]
+    fn test_changes_same_as_immediate() {
+        let sprite = sprite();
+        let mut history = FrameHistory::new();
+        let rasterizer = Rasterizer::new(3);
+        let mut pixels = vec![0; (WIDTH * HEIGHT) as usize];
+        let mut previous = None;
+        for &offset in [0, 0, 5, 6, 6, -30, 0].iter() {
+            let mut expected = vec![0; (WIDTH * HEIGHT) as usize];
+            draw_scene(
+                &mut RenderBuffer::new(&mut expected, WIDTH, HEIGHT),
+                &sprite,
+                offset,
+            );
+
+            let mut render_buffer = RenderBuffer::recording(&mut pixels, WIDTH, HEIGHT);
+            draw_scene(&mut render_buffer, &sprite, offset);
+            let dirty = render_buffer.flush_changes(&mut history, &rasterizer);
+            drop(render_buffer);
+            assert!(pixels == expected, "offset {}", offset);
+            if previous == Some(offset) {
+                assert!(dirty.is_empty());
+            } else if previous.is_some() {
+                assert!(!dirty.is_empty() && dirty.len() < 4);
+                assert!(dirty
+                    .iter()
+                    .all(|rect| rect.area() < (WIDTH * HEIGHT / 4) as i64));
+            }
+            previous = Some(offset);
+        }
+    }
+
+    #[test]
+    fn test_recording() {
+        let rasterizer = Rasterizer::new(2);
+        let mut pixels = vec![0; 4];
+        let mut render_buffer = RenderBuffer::recording(&mut pixels, 2, 2);
+        render_buffer.clear(0xffffff);
+        assert_eq!(render_buffer.pixels[..], [0; 4]);
+        render_buffer.flush(&rasterizer);
+        assert_eq!(render_buffer.pixels[..], [0xffffff; 4]);
+
+        // Flushed commands aren't drawn again
+        render_buffer.pixels[0] = 0;
+        render_buffer.flush(&rasterizer);
+        assert_eq!(render_buffer.pixels[0], 0);
+    }
+
+    #[test]
+    fn test_rasterizer_panics() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let rasterizer = Rasterizer::new(4);
+        let calls = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            rasterizer.run(&|| {
+                if calls.fetch_add(1, Ordering::SeqCst) == 1 {
+                    panic!("tile failed");
+                }
+            })
+        }));
+        assert!(result.is_err());
+
+        // Every thread was done with the job by then, and the workers are still usable
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        rasterizer.run(&|| {
+            calls.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn test_bin() {
+        let rect = |y0, y1| Command::Rect {
+            x0: 0,
+            x1: 1,
+            y0,
+            y1,
+            paint: Paint::Solid(0),
+        };
+        let commands = [
+            rect(0, TILE_ROWS),
+            rect(TILE_ROWS - 1, TILE_ROWS + 1),
+            rect(-100, -1),
+            rect(2 * TILE_ROWS, 10 * TILE_ROWS),
+            Command::Clear(0),
+        ];
+        assert_eq!(
+            bin(&commands, 3),
+            vec![vec![0, 1, 4], vec![1, 4], vec![3, 4]]
+        );
+    }
+}
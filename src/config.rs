@@ -0,0 +1,24 @@
+use std::env;
+use std::path::PathBuf;
+
+const APP_NAME: &str = "breakout";
+
+/// Per-user directory for files the game writes, e.g. high scores.
+///
+/// `$XDG_CONFIG_HOME/breakout` (falling back to `~/.config/breakout`) on unix and
+/// `%APPDATA%\breakout` on Windows. The directory is not created.
+pub fn config_dir() -> Option<PathBuf> {
+    #[cfg(windows)]
+    {
+        env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join(APP_NAME))
+    }
+
+    #[cfg(not(windows))]
+    {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join(APP_NAME))
+    }
+}
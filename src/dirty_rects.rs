@@ -0,0 +1,300 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::command_list::Command;
+
+/// More rects than this are merged into their bounding box.
+const MAX_RECTS: usize = 32;
+
+/// Pixel rect from `x0, y0` up to, but not including, `x1, y1`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PixelRect {
+    pub x0: i32,
+    pub y0: i32,
+    pub x1: i32,
+    pub y1: i32,
+}
+
+impl PixelRect {
+    pub fn new(x0: i32, y0: i32, x1: i32, y1: i32) -> PixelRect {
+        PixelRect { x0, y0, x1, y1 }
+    }
+
+    pub fn width(&self) -> i32 {
+        (self.x1 - self.x0).max(0)
+    }
+
+    pub fn height(&self) -> i32 {
+        (self.y1 - self.y0).max(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x1 <= self.x0 || self.y1 <= self.y0
+    }
+
+    pub fn area(&self) -> i64 {
+        self.width() as i64 * self.height() as i64
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x0 && x < self.x1 && y >= self.y0 && y < self.y1
+    }
+
+    pub fn intersects(&self, other: &PixelRect) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    pub fn intersection(&self, other: &PixelRect) -> PixelRect {
+        PixelRect::new(
+            self.x0.max(other.x0),
+            self.y0.max(other.y0),
+            self.x1.min(other.x1),
+            self.y1.min(other.y1),
+        )
+    }
+
+    pub fn union(&self, other: &PixelRect) -> PixelRect {
+        PixelRect::new(
+            self.x0.min(other.x0),
+            self.y0.min(other.y0),
+            self.x1.max(other.x1),
+            self.y1.max(other.y1),
+        )
+    }
+}
+
+/// Limits `rects` to the frame and merges them until none overlap, so each pixel is repainted
+/// at most once. Many small rects, or rects covering most of the frame, become one.
+pub fn merge(rects: &mut Vec<PixelRect>, width: i32, height: i32) {
+    let frame = PixelRect::new(0, 0, width, height);
+    rects
+        .iter_mut()
+        .for_each(|rect| *rect = rect.intersection(&frame));
+    rects.retain(|rect| !rect.is_empty());
+
+    if rects.len() > MAX_RECTS {
+        let bounds = rects
+            .iter()
+            .fold(rects[0], |bounds, rect| bounds.union(rect));
+        rects.clear();
+        rects.push(bounds);
+    }
+
+    let mut i = 0;
+    while i < rects.len() {
+        match (i + 1..rects.len()).find(|&j| rects[i].intersects(&rects[j])) {
+            Some(j) => {
+                rects[i] = rects[i].union(&rects[j]);
+                rects.swap_remove(j);
+                // The grown rect may now overlap ones already passed
+                i = 0;
+            }
+            None => i += 1,
+        }
+    }
+
+    let area: i64 = rects.iter().map(PixelRect::area).sum();
+    if area * 4 > frame.area() * 3 {
+        rects.clear();
+        rects.push(frame);
+    }
+}
+
+/// The commands of the last frame drawn into a buffer, so the next frame only repaints the
+/// regions where its commands differ.
+#[derive(Default)]
+pub struct FrameHistory {
+    commands: Vec<Command>,
+    /// Size of the frame `commands` were drawn into, `None` if the buffer can't be trusted.
+    size: Option<(i32, i32)>,
+}
+
+impl FrameHistory {
+    pub fn new() -> FrameHistory {
+        FrameHistory::default()
+    }
+
+    /// Makes the next frame repaint everything, e.g. after the buffer was reallocated.
+    pub fn invalidate(&mut self) {
+        self.size = None;
+        self.commands.clear();
+    }
+
+    /// Regions where drawing `commands` gives different pixels than the last frame did.
+    ///
+    /// Each command is matched with an equal one from the last frame. Commands without a match,
+    /// on either side, are dirty, and so are matched commands that were drawn in a different
+    /// order relative to the others, since where they overlap the result may have changed.
+    pub(crate) fn changes(&self, commands: &[Command], width: i32, height: i32) -> Vec<PixelRect> {
+        let frame = PixelRect::new(0, 0, width, height);
+        if self.size != Some((width, height)) {
+            return vec![frame];
+        }
+
+        let mut candidates: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (index, command) in self.commands.iter().enumerate() {
+            candidates.entry(key(command)).or_default().push(index);
+        }
+
+        let mut rects = Vec::new();
+        let mut matched = vec![false; self.commands.len()];
+        let mut last_matched = None;
+        for command in commands.iter() {
+            let previous = candidates.get_mut(&key(command)).and_then(|indices| {
+                let position = indices
+                    .iter()
+                    .position(|&index| self.commands[index] == *command)?;
+                Some(indices.remove(position))
+            });
+
+            let is_dirty = match previous {
+                Some(index) => {
+                    matched[index] = true;
+                    let in_order = last_matched.map_or(true, |last| index > last);
+                    last_matched = Some(last_matched.map_or(index, |last: usize| last.max(index)));
+                    !in_order
+                }
+                None => true,
+            };
+            if is_dirty {
+                rects.push(command.bounds().unwrap_or(frame));
+            }
+        }
+
+        for (command, _) in self
+            .commands
+            .iter()
+            .zip(matched.iter())
+            .filter(|(_, &matched)| !matched)
+        {
+            rects.push(command.bounds().unwrap_or(frame));
+        }
+
+        merge(&mut rects, width, height);
+        rects
+    }
+
+    pub(crate) fn set(&mut self, commands: Vec<Command>, width: i32, height: i32) {
+        self.commands = commands;
+        self.size = Some((width, height));
+    }
+}
+
+fn key(command: &Command) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    command.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::software_rendering::Paint;
+
+    fn rect(x0: i32, y0: i32, x1: i32, y1: i32) -> Command {
+        colored_rect(x0, y0, x1, y1, 0xffffff)
+    }
+
+    fn colored_rect(x0: i32, y0: i32, x1: i32, y1: i32, color: u32) -> Command {
+        Command::Rect {
+            x0,
+            y0,
+            x1,
+            y1,
+            paint: Paint::Solid(color),
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut rects = vec![
+            PixelRect::new(0, 0, 10, 10),
+            PixelRect::new(50, 50, 60, 60),
+            PixelRect::new(5, 5, 20, 20),
+            PixelRect::new(-10, 90, 5, 200),
+            PixelRect::new(30, 30, 30, 40),
+        ];
+        merge(&mut rects, 100, 100);
+        assert_eq!(
+            rects,
+            vec![
+                PixelRect::new(0, 0, 20, 20),
+                PixelRect::new(50, 50, 60, 60),
+                PixelRect::new(0, 90, 5, 100),
+            ]
+        );
+
+        // Chains of overlaps collapse into one
+        let mut rects: Vec<_> = (0..10)
+            .map(|i| PixelRect::new(i, i, i + 2, i + 2))
+            .collect();
+        merge(&mut rects, 100, 100);
+        assert_eq!(rects, vec![PixelRect::new(0, 0, 11, 11)]);
+
+        let mut rects = vec![PixelRect::new(0, 0, 90, 90)];
+        merge(&mut rects, 100, 100);
+        assert_eq!(rects, vec![PixelRect::new(0, 0, 100, 100)]);
+    }
+
+    #[test]
+    fn test_changes() {
+        let mut history = FrameHistory::new();
+        let frame = vec![Command::Clear(0), rect(0, 0, 10, 10), rect(20, 20, 30, 30)];
+        assert_eq!(
+            history.changes(&frame, 100, 100),
+            vec![PixelRect::new(0, 0, 100, 100)]
+        );
+        history.set(frame.clone(), 100, 100);
+        assert!(history.changes(&frame, 100, 100).is_empty());
+
+        // Moving a rect dirties where it was and where it is
+        let moved = vec![Command::Clear(0), rect(0, 0, 10, 10), rect(25, 20, 35, 30)];
+        assert_eq!(
+            history.changes(&moved, 100, 100),
+            vec![PixelRect::new(20, 20, 35, 30)]
+        );
+
+        // Swapping rects that don't overlap leaves every pixel as it was. Order changes are
+        // handled conservatively without looking at overlap, so the rect that moved in the order
+        // may be repainted, but nothing beyond it
+        let swapped = vec![Command::Clear(0), rect(20, 20, 30, 30), rect(0, 0, 10, 10)];
+        let bounds = PixelRect::new(0, 0, 10, 10);
+        assert!(history
+            .changes(&swapped, 100, 100)
+            .iter()
+            .all(|dirty| dirty.intersection(&bounds) == *dirty));
+
+        // A new clear color repaints everything
+        let cleared = vec![Command::Clear(1), rect(0, 0, 10, 10), rect(20, 20, 30, 30)];
+        assert_eq!(
+            history.changes(&cleared, 100, 100),
+            vec![PixelRect::new(0, 0, 100, 100)]
+        );
+
+        assert_eq!(
+            history.changes(&frame, 50, 100),
+            vec![PixelRect::new(0, 0, 50, 100)]
+        );
+        history.invalidate();
+        assert_eq!(
+            history.changes(&frame, 100, 100),
+            vec![PixelRect::new(0, 0, 100, 100)]
+        );
+    }
+
+    #[test]
+    fn test_changes_overlap_order() {
+        let mut history = FrameHistory::new();
+        let red = colored_rect(0, 0, 10, 10, 0xff0000);
+        let blue = colored_rect(5, 5, 15, 15, 0x0000ff);
+        history.set(vec![Command::Clear(0), red.clone(), blue.clone()], 100, 100);
+
+        // Drawing red last puts it on top where the two overlap, which must be repainted
+        let dirty = history.changes(&[Command::Clear(0), blue, red], 100, 100);
+        let overlap = PixelRect::new(5, 5, 10, 10);
+        assert!(dirty
+            .iter()
+            .any(|dirty| dirty.intersection(&overlap) == overlap));
+    }
+}
@@ -0,0 +1,313 @@
+// Screenshots and frame sequences. Frames are `0x00RRGGBB` pixels with the bottom row first, the
+// encoders write 8-bit RGB with the top row first.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bitmap::inflate::adler32;
+use crate::bitmap::png::{crc32, SIGNATURE};
+
+/// Most bytes a stored DEFLATE block can hold.
+const MAX_STORED_BLOCK: usize = 65535;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+}
+
+impl ImageFormat {
+    /// The format for a file name's extension, PNG unless it's `.ppm`.
+    pub fn from_path(path: &Path) -> ImageFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("ppm") => ImageFormat::Ppm,
+            _ => ImageFormat::Png,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<ImageFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Png => "png",
+        }
+    }
+
+    pub fn encode(self, pixels: &[u32], width: i32, height: i32) -> Vec<u8> {
+        match self {
+            ImageFormat::Ppm => encode_ppm(pixels, width, height),
+            ImageFormat::Png => encode_png(pixels, width, height),
+        }
+    }
+}
+
+/// Writes a `width` x `height` frame to `path`, in the format its extension names.
+pub fn save(path: &Path, pixels: &[u32], width: i32, height: i32) -> io::Result<()> {
+    fs::write(
+        path,
+        ImageFormat::from_path(path).encode(pixels, width, height),
+    )
+}
+
+/// Binary PPM (`P6`).
+pub fn encode_ppm(pixels: &[u32], width: i32, height: i32) -> Vec<u8> {
+    let mut data = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for row in rows_top_down(pixels, width, height) {
+        row.iter().for_each(|&pixel| data.extend(rgb(pixel)));
+    }
+    data
+}
+
+/// 8-bit RGB PNG. The image data isn't compressed, which keeps encoding fast enough to write
+/// every frame.
+pub fn encode_png(pixels: &[u32], width: i32, height: i32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend(&(width as u32).to_be_bytes());
+    header.extend(&(height as u32).to_be_bytes());
+    // Bit depth, true color, deflate, adaptive filtering, no interlacing
+    header.extend(&[8, 2, 0, 0, 0]);
+
+    // Every scanline starts with its filter type, 0 is none
+    let mut scanlines =
+        Vec::with_capacity((width.max(0) * 3 + 1) as usize * height.max(0) as usize);
+    for row in rows_top_down(pixels, width, height) {
+        scanlines.push(0);
+        row.iter().for_each(|&pixel| scanlines.extend(rgb(pixel)));
+    }
+
+    let mut data = SIGNATURE.to_vec();
+    write_chunk(&mut data, b"IHDR", &header);
+    write_chunk(&mut data, b"IDAT", &zlib_store(&scanlines));
+    write_chunk(&mut data, b"IEND", &[]);
+    data
+}
+
+/// A zlib stream made of stored, uncompressed, DEFLATE blocks.
+pub fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let block_count = ((data.len() + MAX_STORED_BLOCK - 1) / MAX_STORED_BLOCK).max(1);
+    let mut stream = Vec::with_capacity(data.len() + block_count * 5 + 6);
+    // Deflate with a 32K window and no dictionary, the header is a multiple of 31
+    stream.extend(&[0x78, 0x01]);
+
+    for index in 0..block_count {
+        let end = data.len().min((index + 1) * MAX_STORED_BLOCK);
+        let block = &data[index * MAX_STORED_BLOCK..end];
+        let is_final = index == block_count - 1;
+        // BFINAL, then BTYPE 00 for stored, padded to a byte
+        stream.push(is_final as u8);
+        stream.extend(&(block.len() as u16).to_le_bytes());
+        stream.extend(&(!(block.len() as u16)).to_le_bytes());
+        stream.extend(block);
+    }
+
+    stream.extend(&adler32(data).to_be_bytes());
+    stream
+}
+
+/// Writes every frame it's given as a numbered image, e.g. `frame_000000.png`, for making videos.
+pub struct FrameSequence {
+    directory: PathBuf,
+    format: ImageFormat,
+    next_frame: u32,
+}
+
+impl FrameSequence {
+    /// Creates `directory` if needed. Existing frames in it are overwritten.
+    pub fn new(directory: &Path, format: ImageFormat) -> io::Result<FrameSequence> {
+        fs::create_dir_all(directory)?;
+        Ok(FrameSequence {
+            directory: directory.to_path_buf(),
+            format,
+            next_frame: 0,
+        })
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.next_frame
+    }
+
+    pub fn write(&mut self, pixels: &[u32], width: i32, height: i32) -> io::Result<PathBuf> {
+        let path = self.directory.join(format!(
+            "frame_{:06}.{}",
+            self.next_frame,
+            self.format.extension()
+        ));
+        fs::write(&path, self.format.encode(pixels, width, height))?;
+        self.next_frame += 1;
+        Ok(path)
+    }
+}
+
+/// Screenshots and recordings made while playing, written to numbered files in `directory` so
+/// earlier ones are kept.
+pub struct Exporter {
+    directory: PathBuf,
+    format: ImageFormat,
+    recording: Option<FrameSequence>,
+}
+
+impl Exporter {
+    pub fn new(directory: &Path, format: ImageFormat) -> Exporter {
+        Exporter {
+            directory: directory.to_path_buf(),
+            format,
+            recording: None,
+        }
+    }
+
+    /// Writes `screenshot_NNNN` with the first unused number.
+    pub fn screenshot(&self, pixels: &[u32], width: i32, height: i32) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.directory)?;
+        let path = self.unused_path("screenshot_", &format!(".{}", self.format.extension()));
+        fs::write(&path, self.format.encode(pixels, width, height))?;
+        Ok(path)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Starts writing every frame passed to `record` into a new `recording_NNNN` directory, or
+    /// stops doing so.
+    pub fn toggle_recording(&mut self) -> io::Result<()> {
+        if self.recording.take().is_none() {
+            let directory = self.unused_path("recording_", "");
+            self.recording = Some(FrameSequence::new(&directory, self.format)?);
+        }
+        Ok(())
+    }
+
+    /// Writes the frame if recording. Recording stops if writing fails.
+    pub fn record(&mut self, pixels: &[u32], width: i32, height: i32) -> io::Result<()> {
+        if let Some(recording) = self.recording.as_mut() {
+            if let Err(error) = recording.write(pixels, width, height) {
+                self.recording = None;
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    fn unused_path(&self, prefix: &str, suffix: &str) -> PathBuf {
+        (0..)
+            .map(|number| {
+                self.directory
+                    .join(format!("{}{:04}{}", prefix, number, suffix))
+            })
+            .find(|path| !path.exists())
+            .unwrap()
+    }
+}
+
+/// `0x00RRGGBB` as bytes, the top byte is ignored.
+fn rgb(pixel: u32) -> [u8; 3] {
+    [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]
+}
+
+fn rows_top_down(pixels: &[u32], width: i32, height: i32) -> impl Iterator<Item = &[u32]> {
+    let width = width.max(0) as usize;
+    let height = height.max(0) as usize;
+    pixels[..width * height].chunks_exact(width.max(1)).rev()
+}
+
+fn write_chunk(data: &mut Vec<u8>, chunk_type: &[u8; 4], chunk_data: &[u8]) {
+    data.extend(&(chunk_data.len() as u32).to_be_bytes());
+    let start = data.len();
+    data.extend(chunk_type);
+    data.extend(chunk_data);
+    let crc = crc32(&data[start..]);
+    data.extend(&crc.to_be_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bitmap::inflate::zlib_decompress;
+    use crate::bitmap::Bitmap;
+    use crate::color::Color;
+
+    // Bottom row red and green, top row blue and white, with junk in the unused top byte
+    const PIXELS: [u32; 4] = [0xff_ff0000, 0x00_00ff00, 0x12_0000ff, 0x00_ffffff];
+
+    #[test]
+    fn test_ppm() {
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend(&[0, 0, 255, 255, 255, 255, 255, 0, 0, 0, 255, 0]);
+        assert_eq!(encode_ppm(&PIXELS, 2, 2), expected);
+    }
+
+    #[test]
+    fn test_png() {
+        let bitmap = Bitmap::decode(&encode_png(&PIXELS, 2, 2)).unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (2, 2));
+        assert_eq!(bitmap.pixel(0, 0), Color::rgb(255, 0, 0));
+        assert_eq!(bitmap.pixel(1, 0), Color::rgb(0, 255, 0));
+        assert_eq!(bitmap.pixel(0, 1), Color::rgb(0, 0, 255));
+        assert_eq!(bitmap.pixel(1, 1), Color::rgb(255, 255, 255));
+
+        // Big enough to need several stored blocks
+        let pixels: Vec<u32> = (0..300 * 200u32)
+            .map(|i| i.wrapping_mul(2654435761))
+            .collect();
+        let bitmap = Bitmap::decode(&encode_png(&pixels, 300, 200)).unwrap();
+        assert_eq!(
+            bitmap.pixel(123, 45).pack_argb(),
+            0xff000000 | (pixels[45 * 300 + 123] & 0xffffff)
+        );
+    }
+
+    #[test]
+    fn test_zlib_store() {
+        for &length in [0, 1, MAX_STORED_BLOCK, MAX_STORED_BLOCK + 1, 200_000].iter() {
+            let data: Vec<u8> = (0..length).map(|i| (i * 7) as u8).collect();
+            assert_eq!(zlib_decompress(&zlib_store(&data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_image_format() {
+        assert_eq!(ImageFormat::from_path(Path::new("a.PPM")), ImageFormat::Ppm);
+        assert_eq!(ImageFormat::from_path(Path::new("a.png")), ImageFormat::Png);
+        assert_eq!(ImageFormat::from_path(Path::new("a")), ImageFormat::Png);
+        assert_eq!(ImageFormat::parse("ppm"), Some(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::parse("gif"), None);
+    }
+
+    #[test]
+    fn test_exporter() {
+        let dir = std::env::temp_dir().join(format!("breakout-export-{}", std::process::id()));
+        let mut exporter = Exporter::new(&dir, ImageFormat::Ppm);
+        assert_eq!(
+            exporter.screenshot(&PIXELS, 2, 2).unwrap(),
+            dir.join("screenshot_0000.ppm")
+        );
+        assert_eq!(
+            exporter.screenshot(&PIXELS, 2, 2).unwrap(),
+            dir.join("screenshot_0001.ppm")
+        );
+
+        // Frames are only written while recording
+        exporter.record(&PIXELS, 2, 2).unwrap();
+        exporter.toggle_recording().unwrap();
+        exporter.record(&PIXELS, 2, 2).unwrap();
+        exporter.record(&PIXELS, 2, 2).unwrap();
+        exporter.toggle_recording().unwrap();
+        exporter.record(&PIXELS, 2, 2).unwrap();
+        let recording = dir.join("recording_0000");
+        assert_eq!(fs::read_dir(&recording).unwrap().count(), 2);
+        assert_eq!(
+            fs::read(recording.join("frame_000001.ppm")).unwrap(),
+            encode_ppm(&PIXELS, 2, 2)
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
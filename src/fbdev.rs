@@ -0,0 +1,854 @@
+// Runs the game on a Linux framebuffer device, for kiosks and cabinets without a display server.
+// Frames are written to the device file, only where they changed, and input is read from evdev
+// devices. On kernels with DRM drivers the framebuffer is usually provided by fbdev emulation.
+// The virtual terminal is switched to graphics mode and the input devices are grabbed while it
+// runs, so the console neither draws over the game nor sees its key presses.
+//
+// Anything that can be written to works as the framebuffer, e.g. a regular file for testing, if
+// its size and pixel format are given since they can't be queried.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::raw::{c_int, c_ulong};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command_list::Rasterizer;
+use crate::config;
+use crate::dirty_rects::{FrameHistory, PixelRect};
+use crate::game::Game;
+use crate::input::gamepad::GamepadButton;
+use crate::input::keyboard::Key;
+use crate::input::mouse::Button;
+use crate::input::Input;
+use crate::software_rendering::RenderBuffer;
+
+pub const USAGE: &str = "usage: breakout --fbdev [--device PATH] [--size WIDTHxHEIGHT] \
+                         [--bpp 16|24|32] [--input PATH]... [--frames COUNT]";
+
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+/// How often the whole frame is written when the console can't be kept off the screen.
+const REPAINT_INTERVAL: Duration = Duration::from_secs(1);
+
+const FBIOGET_VSCREENINFO: c_ulong = 0x4600;
+const FBIOGET_FSCREENINFO: c_ulong = 0x4602;
+const KDSETMODE: c_ulong = 0x4b3a;
+const KD_TEXT: c_ulong = 0;
+const KD_GRAPHICS: c_ulong = 1;
+const EVIOCGRAB: c_ulong = 0x4004_4590;
+const SIGINT: c_int = 2;
+const SIGTERM: c_int = 15;
+
+// See linux/input-event-codes.h
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+const KEY_ESC: u16 = 1;
+const KEY_BACKSPACE: u16 = 14;
+const KEY_ENTER: u16 = 28;
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_C: u16 = 46;
+const KEY_SPACE: u16 = 57;
+const KEY_RIGHTCTRL: u16 = 97;
+const KEY_UP: u16 = 103;
+const KEY_LEFT: u16 = 105;
+const KEY_RIGHT: u16 = 106;
+const KEY_DOWN: u16 = 108;
+const KEY_F11: u16 = 87;
+const KEY_F12: u16 = 88;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+/// Gamepad buttons by position, south is the bottom face button.
+const GAMEPAD_CODES: [(u16, GamepadButton); 12] = [
+    (0x130, GamepadButton::A),
+    (0x131, GamepadButton::B),
+    (0x134, GamepadButton::X),
+    (0x133, GamepadButton::Y),
+    (0x136, GamepadButton::LeftShoulder),
+    (0x137, GamepadButton::RightShoulder),
+    (0x13a, GamepadButton::Back),
+    (0x13b, GamepadButton::Start),
+    (0x220, GamepadButton::DPadUp),
+    (0x221, GamepadButton::DPadDown),
+    (0x222, GamepadButton::DPadLeft),
+    (0x223, GamepadButton::DPadRight),
+];
+/// Letter and digit codes follow the keyboard rows, each row starting at the code.
+const KEY_ROWS: [(u16, &str); 4] = [
+    (2, "1234567890"),
+    (16, "QWERTYUIOP"),
+    (30, "ASDFGHJKL"),
+    (44, "ZXCVBNM"),
+];
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+}
+
+/// Set by SIGINT and SIGTERM once the console is taken, so it's given back before exiting.
+static SIGNALED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: c_int) {
+    SIGNALED.store(true, Ordering::Relaxed);
+}
+
+/// The virtual terminal in graphics mode, which hides its text and cursor and keeps kernel
+/// messages off the screen. Text mode is restored when dropped.
+struct Console {
+    tty: File,
+}
+
+impl Console {
+    /// The controlling terminal, or the foreground one when that isn't a virtual terminal, e.g.
+    /// over ssh.
+    fn take() -> Option<Console> {
+        ["/dev/tty", "/dev/tty0"].iter().find_map(|path| {
+            let tty = OpenOptions::new().write(true).open(path).ok()?;
+            set_console_mode(&tty, KD_GRAPHICS).ok()?;
+            Some(Console { tty })
+        })
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        let _ = set_console_mode(&self.tty, KD_TEXT);
+    }
+}
+
+fn set_console_mode(tty: &File, mode: c_ulong) -> io::Result<()> {
+    // Safe since KDSETMODE takes the mode by value
+    if unsafe { ioctl(tty.as_raw_fd(), KDSETMODE, mode) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// See `struct fb_var_screeninfo` in linux/fb.h.
+#[repr(C)]
+#[derive(Default)]
+struct VarScreenInfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: [u32; 3],
+    green: [u32; 3],
+    blue: [u32; 3],
+    transp: [u32; 3],
+    rest: [u32; 20],
+}
+
+/// See `struct fb_fix_screeninfo` in linux/fb.h.
+#[repr(C)]
+#[derive(Default)]
+struct FixScreenInfo {
+    id: [u8; 16],
+    smem_start: c_ulong,
+    smem_len: u32,
+    kind: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: c_ulong,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+/// Where a color channel's bits are in a device pixel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl Channel {
+    pub fn new(offset: u32, length: u32) -> Channel {
+        Channel { offset, length }
+    }
+
+    fn encode(self, value: u32) -> u32 {
+        let length = self.length.min(8);
+        (value >> (8 - length)) << self.offset
+    }
+}
+
+/// Packed little-endian pixels of 16, 24 or 32 bits.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PixelFormat {
+    pub bits_per_pixel: u32,
+    pub red: Channel,
+    pub green: Channel,
+    pub blue: Channel,
+}
+
+impl PixelFormat {
+    /// The usual format for a pixel size: RGB565, or 8 bits per channel with red highest.
+    pub fn from_bits_per_pixel(bits_per_pixel: u32) -> Option<PixelFormat> {
+        let (red, green, blue) = match bits_per_pixel {
+            16 => (Channel::new(11, 5), Channel::new(5, 6), Channel::new(0, 5)),
+            24 | 32 => (Channel::new(16, 8), Channel::new(8, 8), Channel::new(0, 8)),
+            _ => return None,
+        };
+        Some(PixelFormat {
+            bits_per_pixel,
+            red,
+            green,
+            blue,
+        })
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel as usize / 8
+    }
+
+    /// Writes the `0x00RRGGBB` pixel to `output`, which is `bytes_per_pixel` long.
+    fn encode(&self, pixel: u32, output: &mut [u8]) {
+        let value = self.red.encode((pixel >> 16) & 0xff)
+            | self.green.encode((pixel >> 8) & 0xff)
+            | self.blue.encode(pixel & 0xff);
+        output.copy_from_slice(&value.to_le_bytes()[..output.len()]);
+    }
+}
+
+/// Size and layout of the visible part of a framebuffer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Geometry {
+    pub width: i32,
+    pub height: i32,
+    /// Bytes from one row to the next, rows may be padded.
+    pub line_length: usize,
+    /// Top left corner of the visible part, in pixels, for panned framebuffers.
+    pub x_offset: usize,
+    pub y_offset: usize,
+    pub format: PixelFormat,
+}
+
+impl Geometry {
+    /// Tightly packed rows, without panning.
+    pub fn packed(width: i32, height: i32, format: PixelFormat) -> Geometry {
+        Geometry {
+            width,
+            height,
+            line_length: width as usize * format.bytes_per_pixel(),
+            x_offset: 0,
+            y_offset: 0,
+            format,
+        }
+    }
+
+    /// Asks the framebuffer device for its geometry.
+    fn query(file: &File) -> io::Result<Geometry> {
+        let mut var = VarScreenInfo::default();
+        let mut fix = FixScreenInfo::default();
+        // Safe since both structs match the kernel's layout and outlive the calls
+        let failed = unsafe {
+            ioctl(file.as_raw_fd(), FBIOGET_VSCREENINFO, &mut var as *mut _) != 0
+                || ioctl(file.as_raw_fd(), FBIOGET_FSCREENINFO, &mut fix as *mut _) != 0
+        };
+        if failed {
+            return Err(io::Error::last_os_error());
+        }
+
+        let channel = |channel: [u32; 3]| Channel::new(channel[0], channel[1]);
+        let format = PixelFormat {
+            bits_per_pixel: var.bits_per_pixel,
+            red: channel(var.red),
+            green: channel(var.green),
+            blue: channel(var.blue),
+        };
+        if PixelFormat::from_bits_per_pixel(var.bits_per_pixel).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "unsupported framebuffer depth of {} bits",
+                    var.bits_per_pixel
+                ),
+            ));
+        }
+
+        Ok(Geometry {
+            width: var.xres as i32,
+            height: var.yres as i32,
+            line_length: fix.line_length as usize,
+            x_offset: var.xoffset as usize,
+            y_offset: var.yoffset as usize,
+            format,
+        })
+    }
+}
+
+pub struct Framebuffer {
+    file: File,
+    pub geometry: Geometry,
+    /// One row span in the device format, reused between writes.
+    span: Vec<u8>,
+}
+
+impl Framebuffer {
+    /// Opens the framebuffer at `path`, querying its geometry unless it's given.
+    pub fn open(path: &Path, geometry: Option<Geometry>) -> io::Result<Framebuffer> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let geometry = match geometry {
+            Some(geometry) => geometry,
+            None => Geometry::query(&file).map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!(
+                        "can't query {}, pass --size and --bpp if it's not a framebuffer \
+                         device: {}",
+                        path.display(),
+                        error
+                    ),
+                )
+            })?,
+        };
+        Ok(Framebuffer {
+            file,
+            geometry,
+            span: Vec::new(),
+        })
+    }
+
+    /// Writes the `rects` of the frame in `pixels`, which is `geometry`'s size with the bottom
+    /// row first.
+    pub fn present(&mut self, pixels: &[u32], rects: &[PixelRect]) -> io::Result<()> {
+        let geometry = self.geometry;
+        let bytes_per_pixel = geometry.format.bytes_per_pixel();
+        for rect in rects.iter() {
+            self.span.resize(rect.width() as usize * bytes_per_pixel, 0);
+            for y in rect.y0..rect.y1 {
+                let start = (y * geometry.width + rect.x0) as usize;
+                let row = &pixels[start..start + rect.width() as usize];
+                for (&pixel, output) in row.iter().zip(self.span.chunks_exact_mut(bytes_per_pixel))
+                {
+                    geometry.format.encode(pixel, output);
+                }
+
+                // The device's rows go from the top down
+                let device_row = geometry.y_offset + (geometry.height - 1 - y) as usize;
+                let device_column = geometry.x_offset + rect.x0 as usize;
+                let offset = device_row * geometry.line_length + device_column * bytes_per_pixel;
+                self.file.write_all_at(&self.span, offset as u64)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub device: PathBuf,
+    /// Size and bits per pixel, for files that aren't framebuffer devices.
+    pub size: Option<(i32, i32)>,
+    pub bits_per_pixel: Option<u32>,
+    /// Evdev devices, every readable `/dev/input/event*` if empty.
+    pub inputs: Vec<PathBuf>,
+    /// Runs until Ctrl+C or killed if not set.
+    pub frames: Option<u32>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            device: PathBuf::from("/dev/fb0"),
+            size: None,
+            bits_per_pixel: None,
+            inputs: Vec::new(),
+            frames: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line arguments, without the program name. `--fbdev` itself is
+    /// accepted and ignored.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--fbdev" {
+                continue;
+            }
+
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {}", arg))?;
+            let invalid = || format!("invalid value for {}: {}", arg, value);
+            match arg.as_str() {
+                "--device" => options.device = PathBuf::from(value),
+                "--size" => {
+                    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                    let width = width.parse().map_err(|_| invalid())?;
+                    let height = height.parse().map_err(|_| invalid())?;
+                    if width <= 0 || height <= 0 {
+                        return Err(invalid());
+                    }
+                    options.size = Some((width, height));
+                }
+                "--bpp" => {
+                    let bits_per_pixel = value.parse().map_err(|_| invalid())?;
+                    PixelFormat::from_bits_per_pixel(bits_per_pixel).ok_or_else(invalid)?;
+                    options.bits_per_pixel = Some(bits_per_pixel);
+                }
+                "--input" => options.inputs.push(PathBuf::from(value)),
+                "--frames" => options.frames = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        if options.size.is_some() != options.bits_per_pixel.is_some() {
+            return Err("--size and --bpp must be given together".to_string());
+        }
+        Ok(options)
+    }
+
+    fn geometry(&self) -> Option<Geometry> {
+        let (width, height) = self.size?;
+        let format = PixelFormat::from_bits_per_pixel(self.bits_per_pixel?)?;
+        Some(Geometry::packed(width, height, format))
+    }
+}
+
+pub fn run(options: &Options) -> io::Result<()> {
+    let mut framebuffer = Framebuffer::open(&options.device, options.geometry())?;
+    let (width, height) = (framebuffer.geometry.width, framebuffer.geometry.height);
+
+    let inputs = if options.inputs.is_empty() {
+        input_devices()
+    } else {
+        options.inputs.clone()
+    };
+    let events = spawn_input_readers(&inputs)?;
+
+    // Files given a size aren't framebuffer devices, so the console is left alone for them
+    let console = match options.geometry() {
+        Some(_) => None,
+        None => Console::take(),
+    };
+    if console.is_some() {
+        // Safe since the handler only stores to an atomic
+        unsafe {
+            signal(SIGINT, on_signal);
+            signal(SIGTERM, on_signal);
+        }
+    }
+
+    let mut game = Game::new();
+    if let Some(config_dir) = config::config_dir() {
+        game.set_config_dir(&config_dir);
+    }
+    let mut input = Input::new();
+    input.mouse.position.x = width as f32 * 0.5;
+    input.mouse.position.y = height as f32 * 0.5;
+    let rasterizer = Rasterizer::default();
+
+    let mut pixels = vec![0; (width * height) as usize];
+    let mut history = FrameHistory::new();
+    let mut last_dt = FRAME_TIME.as_secs_f32();
+    let mut frame = 0;
+    let mut last_repaint = Instant::now();
+    let mut ctrl_down = false;
+    let mut interrupted = false;
+
+    while !interrupted
+        && !SIGNALED.load(Ordering::Relaxed)
+        && options.frames.map_or(true, |frames| frame < frames)
+    {
+        let frame_start = Instant::now();
+
+        input.begin_frame();
+        while let Ok(event) = events.try_recv() {
+            interrupted |= is_interrupt(&event, &mut ctrl_down);
+            apply_event(&event, &mut input, width, height);
+        }
+
+        // Without graphics mode the console can draw over any part of the frame, which only
+        // presenting the changes would never repair
+        if console.is_none() && last_repaint.elapsed() >= REPAINT_INTERVAL {
+            history.invalidate();
+            last_repaint = frame_start;
+        }
+
+        let dirty = {
+            let mut render_buffer = RenderBuffer::recording(&mut pixels, width, height);
+            game.simulate(&mut render_buffer, &input, last_dt);
+            render_buffer.flush_changes(&mut history, &rasterizer)
+        };
+        framebuffer.present(&pixels, &dirty)?;
+
+        if let Some(rest) = FRAME_TIME.checked_sub(frame_start.elapsed()) {
+            thread::sleep(rest);
+        }
+        last_dt = frame_start.elapsed().as_secs_f32();
+        frame += 1;
+    }
+    Ok(())
+}
+
+/// The readable `/dev/input/event*` devices.
+fn input_devices() -> Vec<PathBuf> {
+    let mut devices: Vec<PathBuf> = fs::read_dir("/dev/input")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with("event"))
+                && File::open(path).is_ok()
+        })
+        .collect();
+    devices.sort();
+    devices
+}
+
+/// Reads each device on its own thread, since reads block.
+fn spawn_input_readers(paths: &[PathBuf]) -> io::Result<Receiver<InputEvent>> {
+    let (sender, receiver) = mpsc::channel();
+    for path in paths.iter() {
+        let file = File::open(path)?;
+        // Keeps the events from the console too, which fails harmlessly for regular files. Safe
+        // since EVIOCGRAB takes its flag by value.
+        unsafe {
+            ioctl(file.as_raw_fd(), EVIOCGRAB, 1 as c_ulong);
+        }
+        let sender: Sender<InputEvent> = sender.clone();
+        thread::spawn(move || read_events(file, sender));
+    }
+    Ok(receiver)
+}
+
+fn read_events<R: Read>(mut device: R, sender: Sender<InputEvent>) {
+    let mut buffer = vec![0; INPUT_EVENT_SIZE * 64];
+    let mut pending = Vec::new();
+    while let Ok(count) = device.read(&mut buffer) {
+        if count == 0 {
+            break;
+        }
+
+        pending.extend_from_slice(&buffer[..count]);
+        let whole = pending.len() / INPUT_EVENT_SIZE * INPUT_EVENT_SIZE;
+        for event in pending[..whole].chunks_exact(INPUT_EVENT_SIZE) {
+            if sender.send(InputEvent::parse(event)).is_err() {
+                return;
+            }
+        }
+        pending.drain(..whole);
+    }
+}
+
+/// `struct input_event` is a `struct timeval`, two longs, followed by these.
+const INPUT_EVENT_SIZE: usize = 2 * std::mem::size_of::<c_ulong>() + 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct InputEvent {
+    kind: u16,
+    code: u16,
+    value: i32,
+}
+
+impl InputEvent {
+    fn parse(data: &[u8]) -> InputEvent {
+        let data = &data[INPUT_EVENT_SIZE - 8..];
+        InputEvent {
+            kind: u16::from_ne_bytes([data[0], data[1]]),
+            code: u16::from_ne_bytes([data[2], data[3]]),
+            value: i32::from_ne_bytes([data[4], data[5], data[6], data[7]]),
+        }
+    }
+}
+
+/// Key and button presses, relative pointer motion and gamepad hats. The pointer is kept inside
+/// the frame.
+fn apply_event(event: &InputEvent, input: &mut Input, width: i32, height: i32) {
+    match event.kind {
+        EV_KEY => {
+            // 0 is a release, 1 a press and 2 a repeat
+            let is_down = event.value != 0;
+            let state = match event.code {
+                BTN_LEFT => input.mouse.button_mut(Button::Left),
+                BTN_RIGHT => input.mouse.button_mut(Button::Right),
+                code => match GAMEPAD_CODES.iter().find(|&&(other, _)| other == code) {
+                    Some(&(_, button)) => input.gamepad.button_mut(button),
+                    None => match evdev_key(code) {
+                        Some(key) => input.keyboard.key_mut(key),
+                        None => return,
+                    },
+                },
+            };
+            state.is_down = is_down;
+        }
+        EV_ABS => {
+            // Many gamepads report the d-pad as a hat, -1 to 1 on each axis
+            let (negative, positive) = match event.code {
+                ABS_HAT0X => (GamepadButton::DPadLeft, GamepadButton::DPadRight),
+                ABS_HAT0Y => (GamepadButton::DPadUp, GamepadButton::DPadDown),
+                _ => return,
+            };
+            input.gamepad.button_mut(negative).is_down = event.value < 0;
+            input.gamepad.button_mut(positive).is_down = event.value > 0;
+        }
+        EV_REL => {
+            let position = &mut input.mouse.position;
+            match event.code {
+                REL_X => position.x = (position.x + event.value as f32).clamp(0.0, width as f32),
+                // Devices count y down, pixels count it up
+                REL_Y => position.y = (position.y - event.value as f32).clamp(0.0, height as f32),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether `event` completes a Ctrl+C, which grabbed keyboards don't send to the terminal.
+fn is_interrupt(event: &InputEvent, ctrl_down: &mut bool) -> bool {
+    if event.kind != EV_KEY {
+        return false;
+    }
+    match event.code {
+        KEY_LEFTCTRL | KEY_RIGHTCTRL => {
+            *ctrl_down = event.value != 0;
+            false
+        }
+        KEY_C => *ctrl_down && event.value == 1,
+        _ => false,
+    }
+}
+
+fn evdev_key(code: u16) -> Option<Key> {
+    match code {
+        KEY_UP => Some(Key::Up),
+        KEY_DOWN => Some(Key::Down),
+        KEY_LEFT => Some(Key::Left),
+        KEY_RIGHT => Some(Key::Right),
+        KEY_F11 => Some(Key::F11),
+        KEY_F12 => Some(Key::F12),
+        KEY_ESC => Some(Key::Escape),
+        KEY_BACKSPACE => Some(Key::Backspace),
+        KEY_ENTER => Some(Key::Enter),
+        KEY_SPACE => Some(Key::Space),
+        _ => KEY_ROWS.iter().find_map(|&(first, row)| {
+            let c = row.chars().nth(code.checked_sub(first)? as usize)?;
+            Key::from_char(c)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("breakout-fbdev-{}-{}", name, std::process::id()))
+    }
+
+    fn event_bytes(kind: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut data = vec![0; INPUT_EVENT_SIZE - 8];
+        data.extend(&kind.to_ne_bytes());
+        data.extend(&code.to_ne_bytes());
+        data.extend(&value.to_ne_bytes());
+        data
+    }
+
+    #[test]
+    fn test_pixel_format() {
+        let mut output = [0; 2];
+        let rgb565 = PixelFormat::from_bits_per_pixel(16).unwrap();
+        rgb565.encode(0xff8040, &mut output);
+        assert_eq!(u16::from_le_bytes(output), 0xfc08);
+
+        let mut output = [0; 4];
+        let bgrx = PixelFormat {
+            red: Channel::new(0, 8),
+            blue: Channel::new(16, 8),
+            ..PixelFormat::from_bits_per_pixel(32).unwrap()
+        };
+        bgrx.encode(0xff123456, &mut output);
+        assert_eq!(output, [0x12, 0x34, 0x56, 0]);
+
+        let mut output = [0; 3];
+        let rgb888 = PixelFormat::from_bits_per_pixel(24).unwrap();
+        rgb888.encode(0x123456, &mut output);
+        assert_eq!(output, [0x56, 0x34, 0x12]);
+
+        assert!(PixelFormat::from_bits_per_pixel(8).is_none());
+    }
+
+    #[test]
+    fn test_present() {
+        let path = temp_path("present");
+        // 2x2 frame in a 3x3 framebuffer with padded rows, panned one pixel right and down
+        let format = PixelFormat::from_bits_per_pixel(16).unwrap();
+        let geometry = Geometry {
+            width: 2,
+            height: 2,
+            line_length: 8,
+            x_offset: 1,
+            y_offset: 1,
+            format,
+        };
+        fs::write(&path, vec![0xaa; 24]).unwrap();
+        let mut framebuffer = Framebuffer::open(&path, Some(geometry)).unwrap();
+
+        let pixels = [0xff0000, 0x00ff00, 0x0000ff, 0xffffff];
+        framebuffer
+            .present(&pixels, &[PixelRect::new(0, 0, 1, 2)])
+            .unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa,
+            0xaa, 0xaa, 0x1f, 0x00, 0xaa, 0xaa, 0xaa, 0xaa,
+            0xaa, 0xaa, 0x00, 0xf8, 0xaa, 0xaa, 0xaa, 0xaa,
+        ];
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run() {
+        let path = temp_path("run");
+        let input = temp_path("input");
+        fs::write(&path, []).unwrap();
+        fs::write(&input, event_bytes(EV_REL, REL_X, 5)).unwrap();
+
+        let options = Options::parse(
+            [
+                "--fbdev",
+                "--device",
+                path.to_str().unwrap(),
+                "--size",
+                "64x48",
+                "--bpp",
+                "32",
+                "--input",
+                input.to_str().unwrap(),
+                "--frames",
+                "2",
+            ]
+            .iter()
+            .map(|arg| arg.to_string()),
+        )
+        .unwrap();
+        run(&options).unwrap();
+
+        // The first frame is written in full
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), 64 * 48 * 4);
+        assert!(data.chunks_exact(4).any(|pixel| pixel != [0, 0, 0, 0]));
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let parse = |args: &str| Options::parse(args.split_whitespace().map(String::from));
+        assert_eq!(parse("--fbdev"), Ok(Options::default()));
+        assert!(parse("--fbdev --size 10x10").is_err());
+        assert!(parse("--fbdev --size 10x10 --bpp 8").is_err());
+        assert_eq!(
+            parse("--input a --input b").unwrap().inputs,
+            vec![PathBuf::from("a"), PathBuf::from("b")]
+        );
+    }
+
+    #[test]
+    fn test_input_events() {
+        let mut data = event_bytes(EV_KEY, KEY_LEFT, 1);
+        data.extend(event_bytes(EV_REL, REL_X, -30));
+        data.extend(event_bytes(EV_REL, REL_Y, 4));
+        data.extend(event_bytes(EV_KEY, BTN_LEFT, 1));
+        data.extend(event_bytes(EV_KEY, BTN_LEFT, 0));
+        // Half an event, which is dropped at the end of the stream
+        data.extend(&event_bytes(EV_KEY, KEY_UP, 1)[..5]);
+
+        let (sender, receiver) = mpsc::channel();
+        read_events(&data[..], sender);
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(events.len(), 5);
+
+        let mut input = Input::new();
+        input.mouse.position.x = 10.0;
+        input.mouse.position.y = 10.0;
+        for event in events[..4].iter() {
+            apply_event(event, &mut input, 100, 50);
+        }
+        assert!(input.keyboard.key(Key::Left).is_down());
+        assert!(input.mouse.button(Button::Left).is_down());
+        assert_eq!(input.mouse.position.x, 0.0);
+        assert_eq!(input.mouse.position.y, 6.0);
+
+        apply_event(&events[4], &mut input, 100, 50);
+        assert!(!input.mouse.button(Button::Left).is_down());
+
+        let gamepad_events = [
+            InputEvent {
+                kind: EV_KEY,
+                code: 0x13b,
+                value: 1,
+            },
+            InputEvent {
+                kind: EV_ABS,
+                code: ABS_HAT0X,
+                value: -1,
+            },
+        ];
+        for event in gamepad_events.iter() {
+            apply_event(event, &mut input, 100, 50);
+        }
+        assert!(input.gamepad.button(GamepadButton::Start).is_down());
+        assert!(input.gamepad.button(GamepadButton::DPadLeft).is_down());
+        assert!(!input.gamepad.button(GamepadButton::DPadRight).is_down());
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let key = |code, value| InputEvent {
+            kind: EV_KEY,
+            code,
+            value,
+        };
+        let mut ctrl_down = false;
+        assert!(!is_interrupt(&key(KEY_C, 1), &mut ctrl_down));
+        assert!(!is_interrupt(&key(KEY_RIGHTCTRL, 1), &mut ctrl_down));
+        assert!(!is_interrupt(&key(KEY_C, 2), &mut ctrl_down));
+        assert!(is_interrupt(&key(KEY_C, 1), &mut ctrl_down));
+        assert!(!is_interrupt(&key(KEY_RIGHTCTRL, 0), &mut ctrl_down));
+        assert!(!is_interrupt(&key(KEY_C, 1), &mut ctrl_down));
+    }
+
+    #[test]
+    fn test_evdev_key() {
+        assert_eq!(evdev_key(2), Some(Key::Num1));
+        assert_eq!(evdev_key(11), Some(Key::Num0));
+        assert_eq!(evdev_key(16), Some(Key::Q));
+        assert_eq!(evdev_key(25), Some(Key::P));
+        assert_eq!(evdev_key(38), Some(Key::L));
+        assert_eq!(evdev_key(50), Some(Key::M));
+        assert_eq!(evdev_key(KEY_ESC), Some(Key::Escape));
+        assert_eq!(evdev_key(12), None);
+        assert_eq!(evdev_key(0), None);
+    }
+}
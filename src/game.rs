@@ -1,14 +1,28 @@
 use std::collections::HashSet;
+use std::path::Path;
 
+use crate::bitmap::Sprite;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::font::TextAlign;
+use crate::high_score::{self, HighScore, HighScores, INITIALS_LEN};
+use crate::hud::{Hud, Timer};
 use crate::index_vec::{GIndex, IndexVec};
-use crate::input::mouse::Button;
+use crate::input::action::{Action, Actions, Binding, Bindings, ACTIONS};
+use crate::input::keyboard::Key;
 use crate::input::Input;
+use crate::juice::{Juice, JuiceEvent};
+use crate::level::*;
 use crate::math::*;
+use crate::menu::{Menu, MenuAction, MenuItem, Page, Screen};
+use crate::particles::{EmitterConfig, Particles};
+use crate::score::Score;
 use crate::software_rendering::*;
 
 #[derive(Clone)]
 pub enum PowerUp {
-    Invincible
+    Invincible,
+    ExtraLife,
 }
 
 #[derive(Default, Clone)]
@@ -25,8 +39,18 @@ pub struct Entity {
 
     pub life: i32,
     pub color: Option<u32>,
+    /// Drawn instead of `color` when set.
+    pub sprite: Option<Sprite>,
+
+    pub points: u32,
+    pub explosion_radius: f32,
+
+    pub path: Vec<Vec2>,
+    pub path_speed: f32,
+    pub path_target: usize,
 
     pub spawn_power_ups: Vec<PowerUp>,
+    pub power_up: Option<PowerUp>,
 }
 
 impl Entity {
@@ -35,9 +59,50 @@ impl Entity {
     }
 }
 
+const LEVEL_COMPLETE_TIME: f32 = 2.0;
+const FADE_TIME: f32 = 0.5;
+const SERVE_TIME: f32 = 1.0;
+const INVINCIBLE_TIME: f32 = 10.0;
+/// Keyboard paddle control, in arena units per second (squared).
+const PADDLE_ACCELERATION: f32 = 600.0;
+const PADDLE_MAX_SPEED: f32 = 120.0;
+const INITIAL_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+#[derive(Copy, Clone, PartialEq, Default)]
+pub enum GameState {
+    #[default]
+    Playing,
+    LevelComplete {
+        time_left: f32,
+    },
+    GameWon,
+    GameOver,
+    EnterInitials {
+        won: bool,
+    },
+}
+
+pub struct Options {
+    pub debug: bool,
+    pub start_lives: i32,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            debug: cfg!(debug_assertions),
+            start_lives: 3,
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Game {
+    pub options: Options,
+
     initialized: bool,
+    state: GameState,
+    fade_in: f32,
     arena_half_size: Vec2,
 
     levels: Vec<Box<dyn Level>>,
@@ -46,16 +111,155 @@ pub struct Game {
     entities: IndexVec<Entity>,
 
     player: Option<GIndex>,
+    serving: Option<GIndex>,
+    serve_time: f32,
+
+    /// Signed speed while the paddle is moved with the keyboard.
+    paddle_speed: f32,
+    /// The paddle only follows the mouse after it moves, so it doesn't fight the keyboard.
+    paddle_follows_mouse: bool,
+    last_mouse_position: Vec2,
+
+    score: Score,
+
+    bindings: Bindings,
+    /// Waiting for the key or button to bind to this action.
+    rebinding: Option<Action>,
+
+    high_scores: HighScores,
+    initials: [u8; INITIALS_LEN],
+    initials_cursor: usize,
+
+    menu: Menu,
+    particles: Particles,
+    juice: Juice,
+
+    /// Looks at the arena, the HUD and menus are drawn with a default camera.
+    camera: Camera,
 }
 
 impl Game {
     pub fn new() -> Game {
-        Game::default()
+        Game {
+            initials: [b'A'; INITIALS_LEN],
+            paddle_follows_mouse: true,
+            ..Game::default()
+        }
+    }
+
+    /// Loads the high score table and the input bindings from, and saves them to, `config_dir`.
+    pub fn set_config_dir(&mut self, config_dir: &Path) {
+        self.high_scores = HighScores::load(config_dir);
+        self.bindings = Bindings::load(config_dir);
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn high_scores(&self) -> &HighScores {
+        &self.high_scores
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    pub fn lives(&self) -> i32 {
+        self.player
+            .and_then(|player| self.entities.get(player))
+            .map(|player| player.life)
+            .unwrap_or(0)
+    }
+
+    pub fn score(&self) -> &Score {
+        &self.score
+    }
+
+    fn new_game(&mut self) {
+        self.score.reset();
+        self.load_level(0);
+        self.reset_lives();
+    }
+
+    fn reset_lives(&mut self) {
+        let start_lives = self.options.start_lives;
+        if let Some(player) = self.player.and_then(|player| self.entities.get_mut(player)) {
+            player.life = start_lives;
+        }
+    }
+
+    fn end_game(&mut self, won: bool) {
+        if self.high_scores.qualifies(LEVEL_PACK, self.score.total()) {
+            self.initials_cursor = 0;
+            self.state = GameState::EnterInitials { won };
+        } else {
+            self.show_result(won);
+        }
+    }
+
+    fn show_result(&mut self, won: bool) {
+        if won {
+            self.state = GameState::GameWon;
+            self.menu.push(Screen::GameWon);
+        } else {
+            self.state = GameState::GameOver;
+            self.menu.push(Screen::GameOver);
+        }
+    }
+
+    fn enter_initials(&mut self, input: &Input, actions: &Actions, won: bool) {
+        let keyboard = &input.keyboard;
+        let initial = &mut self.initials[self.initials_cursor];
+        let char_index = INITIAL_CHARS.iter().position(|c| c == initial).unwrap_or(0);
+
+        if let Some(c) = keyboard.typed() {
+            *initial = c as u8;
+            self.initials_cursor = (self.initials_cursor + 1).min(INITIALS_LEN - 1);
+        } else if keyboard.key(Key::Up).pressed() {
+            *initial = INITIAL_CHARS[(char_index + 1) % INITIAL_CHARS.len()];
+        } else if keyboard.key(Key::Down).pressed() {
+            *initial = INITIAL_CHARS[(char_index + INITIAL_CHARS.len() - 1) % INITIAL_CHARS.len()];
+        } else if keyboard.key(Key::Left).pressed() || keyboard.key(Key::Backspace).pressed() {
+            self.initials_cursor = self.initials_cursor.saturating_sub(1);
+        } else if keyboard.key(Key::Right).pressed() {
+            self.initials_cursor = (self.initials_cursor + 1).min(INITIALS_LEN - 1);
+        }
+
+        if actions.action(Action::Fire).pressed() {
+            let entry = HighScore {
+                initials: String::from_utf8_lossy(&self.initials).into_owned(),
+                score: self.score.total(),
+                level: self.current_level + 1,
+                date: high_score::today(),
+            };
+            self.high_scores.insert(LEVEL_PACK, entry);
+            // Not being able to write the table shouldn't interrupt the game
+            let _ = self.high_scores.save();
+
+            self.show_result(won);
+        }
+    }
+
+    fn serve_ball(&mut self) {
+        let position = self
+            .player
+            .and_then(|player| self.entities.get(player))
+            .map(|player| player.position + Vec2::new(0.0, player.half_size.y + 1.0))
+            .unwrap_or_default();
+        let ball = spawn_ball(&mut self.entities, position, Vec2::zero());
+        self.serving = Some(ball);
+        self.serve_time = SERVE_TIME;
     }
 
     fn load_level(&mut self, level_index: usize) {
         if let Some(level) = self.levels.get(level_index) {
+            let lives = self.lives();
+
             self.entities.clear();
+            self.particles.clear();
+            self.juice.clear();
+            self.serving = None;
 
             self.arena_half_size = Vec2::new(85.0, 45.0);
 
@@ -94,13 +298,15 @@ impl Game {
                 player.position.y = -40.0;
                 player.half_size = Vec2::new(10.0, 2.0);
                 player.color = Some(0x00ff00);
-                player.life = 1;
+                player.life = lives;
                 self.player = Some(self.entities.insert(player));
             }
 
             level.load(&mut self.entities);
 
             self.current_level = level_index;
+            self.state = GameState::Playing;
+            self.score.begin_level(level_index);
         }
     }
 
@@ -110,15 +316,391 @@ impl Game {
             self.levels.push(Box::new(Level0 {}));
             self.levels.push(Box::new(Level1 {}));
 
-            self.load_level(0);
+            self.new_game();
+            self.menu.push(Screen::Title);
+        }
+
+        // Menus work in screen space, not through the arena camera
+        render_buffer.camera = Camera::new();
+
+        let actions = self.bindings.resolve(input);
+
+        // Menu
+        {
+            let pause = actions.action(Action::Pause).pressed();
+            let restart = actions.action(Action::Restart).pressed();
+            if let Some(action) = self.rebinding {
+                if let Some(binding) = Binding::pressed(input) {
+                    // Escape cancels rather than being bound
+                    if binding != Binding::Key(Key::Escape) {
+                        self.bindings.set(action, vec![binding]);
+                        // Not being able to write the bindings shouldn't interrupt the game
+                        let _ = self.bindings.save();
+                    }
+                    self.rebinding = None;
+                }
+            } else if let Some(screen) = self.menu.screen() {
+                let page = self.page(screen);
+                let shortcut = match screen {
+                    Screen::Pause if pause => Some(MenuAction::Resume),
+                    Screen::Pause if restart => Some(MenuAction::RestartLevel),
+                    Screen::GameOver | Screen::GameWon if restart => Some(MenuAction::NewGame),
+                    _ => None,
+                };
+                if let Some(action) =
+                    shortcut.or_else(|| self.menu.update(render_buffer, input, &page))
+                {
+                    self.apply_menu_action(action);
+                }
+            } else if self.state == GameState::Playing {
+                if pause {
+                    self.menu.push(Screen::Pause);
+                } else if restart {
+                    self.apply_menu_action(MenuAction::RestartLevel);
+                }
+            }
+        }
+
+        // Level manager, frozen while a menu is open
+        if !self.menu.is_open() {
+            match self.state {
+                GameState::Playing => {
+                    self.fade_in = (self.fade_in - dt).max(0.0);
+                    if !self.juice.is_frozen() {
+                        self.update_entities(render_buffer, input, &actions, dt);
+                        self.particles.update(dt);
+                    }
+                    self.update_juice(dt);
+
+                    let mut ball_count = 0;
+                    for entity in self.entities.iter() {
+                        if entity.tags.contains("Ball") {
+                            ball_count += 1;
+                        }
+                    }
+
+                    if self.is_level_cleared() {
+                        self.score.level_cleared();
+                        self.state = GameState::LevelComplete {
+                            time_left: LEVEL_COMPLETE_TIME,
+                        };
+                    } else if ball_count == 0 {
+                        if let Some(player) =
+                            self.player.and_then(|player| self.entities.get_mut(player))
+                        {
+                            player.life -= 1;
+                        }
+                        self.score.ball_lost();
+                        self.juice.trigger(JuiceEvent::LifeLost);
+
+                        if self.lives() > 0 {
+                            self.serve_ball();
+                        } else {
+                            self.end_game(false);
+                        }
+                    }
+
+                    if self.options.debug {
+                        let level_count = self.levels.len();
+                        if actions.action(Action::PreviousLevel).pressed() {
+                            self.load_level((self.current_level + level_count - 1) % level_count);
+                        } else if actions.action(Action::NextLevel).pressed() {
+                            self.load_level((self.current_level + 1) % level_count);
+                        }
+                    }
+                }
+                GameState::LevelComplete { time_left } => {
+                    self.particles.update(dt);
+                    self.update_juice(dt);
+
+                    let time_left = time_left - dt;
+                    if time_left > 0.0 {
+                        self.state = GameState::LevelComplete { time_left };
+                    } else if self.current_level + 1 < self.levels.len() {
+                        self.load_level(self.current_level + 1);
+                        self.fade_in = FADE_TIME;
+                    } else {
+                        self.end_game(true);
+                    }
+                }
+                GameState::EnterInitials { won } => {
+                    self.enter_initials(input, &actions, won);
+                }
+                GameState::GameWon | GameState::GameOver => {}
+            }
+        }
+
+        self.render(render_buffer);
+    }
+
+    fn page(&self, screen: Screen) -> Page {
+        let mut body = None;
+        let mut items = Vec::new();
+
+        let title = match screen {
+            Screen::Title => {
+                items.push(MenuItem::new("PLAY", MenuAction::NewGame));
+                items.push(MenuItem::new(
+                    "LEVEL SELECT",
+                    MenuAction::Open(Screen::LevelSelect),
+                ));
+                items.push(MenuItem::new(
+                    "HIGH SCORES",
+                    MenuAction::Open(Screen::HighScores),
+                ));
+                items.push(MenuItem::new("OPTIONS", MenuAction::Open(Screen::Options)));
+                "BREAKOUT"
+            }
+            Screen::LevelSelect => {
+                for (i, level) in self.levels.iter().enumerate() {
+                    let label = format!("{} {}", i + 1, level.name().to_uppercase());
+                    items.push(MenuItem::new(&label, MenuAction::StartLevel(i)));
+                }
+                items.push(MenuItem::new("BACK", MenuAction::Back));
+                "SELECT LEVEL"
+            }
+            Screen::Options => {
+                let label = format!("STARTING LIVES: {}", self.options.start_lives);
+                items.push(MenuItem::new(&label, MenuAction::CycleStartLives));
+                let label = format!("DEBUG: {}", if self.options.debug { "ON" } else { "OFF" });
+                items.push(MenuItem::new(&label, MenuAction::ToggleDebug));
+                items.push(MenuItem::new(
+                    "CONTROLS",
+                    MenuAction::Open(Screen::Controls),
+                ));
+                items.push(MenuItem::new("BACK", MenuAction::Back));
+                "OPTIONS"
+            }
+            Screen::Controls => {
+                for &(action, _) in ACTIONS.iter() {
+                    items.push(MenuItem::new(&action.label(), MenuAction::Rebind(action)));
+                }
+                items.push(MenuItem::new("RESET CONTROLS", MenuAction::ResetBindings));
+                items.push(MenuItem::new("BACK", MenuAction::Back));
+
+                body = Some(self.controls_body());
+                "CONTROLS"
+            }
+            Screen::HighScores => {
+                let entries = self.high_scores.entries(LEVEL_PACK);
+                let lines: Vec<_> = entries
+                    .iter()
+                    .enumerate()
+                    .map(|(i, entry)| {
+                        format!(
+                            "{:>2}. {:<3} {:>7}  L{}  {}",
+                            i + 1,
+                            entry.initials,
+                            entry.score,
+                            entry.level,
+                            entry.date
+                        )
+                    })
+                    .collect();
+                body = Some(if lines.is_empty() {
+                    "NO SCORES YET".to_string()
+                } else {
+                    lines.join("\n")
+                });
+                items.push(MenuItem::new("BACK", MenuAction::Back));
+                "HIGH SCORES"
+            }
+            Screen::Pause => {
+                items.push(MenuItem::new("RESUME", MenuAction::Resume));
+                items.push(MenuItem::new("RESTART LEVEL", MenuAction::RestartLevel));
+                items.push(MenuItem::new("OPTIONS", MenuAction::Open(Screen::Options)));
+                items.push(MenuItem::new("MAIN MENU", MenuAction::MainMenu));
+                "PAUSED"
+            }
+            Screen::GameOver => {
+                body = Some(format!("SCORE {}", self.score.total()));
+                items.push(MenuItem::new("CONTINUE", MenuAction::Continue));
+                items.push(MenuItem::new("RESTART", MenuAction::NewGame));
+                items.push(MenuItem::new("MAIN MENU", MenuAction::MainMenu));
+                "GAME OVER"
+            }
+            Screen::GameWon => {
+                body = Some(format!("SCORE {}", self.score.total()));
+                items.push(MenuItem::new("PLAY AGAIN", MenuAction::NewGame));
+                items.push(MenuItem::new("MAIN MENU", MenuAction::MainMenu));
+                "YOU WIN!"
+            }
+        };
+
+        Page {
+            title: title.to_string(),
+            body,
+            items,
         }
+    }
+
+    /// The bindings of the selected action, or what to press while rebinding. Always two lines,
+    /// so the items don't move with the selection.
+    fn controls_body(&self) -> String {
+        if let Some(action) = self.rebinding {
+            return format!(
+                "PRESS A KEY OR BUTTON FOR {}\nESCAPE CANCELS",
+                action.label()
+            );
+        }
+
+        let bindings = self
+            .menu
+            .selected()
+            .and_then(|selected| ACTIONS.get(selected))
+            .map(|&(action, _)| {
+                let labels: Vec<_> = self
+                    .bindings
+                    .get(action)
+                    .iter()
+                    .map(|binding| binding.label())
+                    .collect();
+                labels.join(", ")
+            })
+            .unwrap_or_default();
+        format!("{}\nPICK AN ACTION TO CHANGE IT", bindings)
+    }
+
+    /// Names whatever saves the initials, since that can be rebound.
+    fn initials_prompt(&self) -> String {
+        let labels: Vec<_> = self
+            .bindings
+            .get(Action::Fire)
+            .iter()
+            .map(|binding| binding.label())
+            .collect();
+        format!(
+            "NEW HIGH SCORE!\nTYPE OR UP/DOWN/LEFT/RIGHT\n{} TO SAVE",
+            labels.join(", ")
+        )
+    }
 
+    fn apply_menu_action(&mut self, action: MenuAction) {
+        match action {
+            MenuAction::Open(screen) => self.menu.push(screen),
+            MenuAction::Back => {
+                if let Some(screen) = self.menu.screen() {
+                    if !screen.is_root() {
+                        self.menu.pop();
+                    }
+                }
+            }
+            MenuAction::NewGame => {
+                self.menu.clear();
+                self.new_game();
+            }
+            MenuAction::StartLevel(level_index) => {
+                self.menu.clear();
+                self.score.reset();
+                self.load_level(level_index);
+                self.reset_lives();
+            }
+            MenuAction::Resume => self.menu.pop(),
+            MenuAction::RestartLevel => {
+                self.menu.clear();
+                self.load_level(self.current_level);
+            }
+            MenuAction::Continue => {
+                self.menu.clear();
+                self.load_level(self.current_level);
+                self.reset_lives();
+            }
+            MenuAction::MainMenu => {
+                self.menu.clear();
+                self.new_game();
+                self.menu.push(Screen::Title);
+            }
+            MenuAction::ToggleDebug => self.options.debug = !self.options.debug,
+            MenuAction::Rebind(action) => self.rebinding = Some(action),
+            MenuAction::ResetBindings => {
+                self.bindings.reset();
+                let _ = self.bindings.save();
+            }
+            MenuAction::CycleStartLives => {
+                self.options.start_lives = match self.options.start_lives {
+                    1 => 3,
+                    3 => 5,
+                    5 => 9,
+                    _ => 1,
+                };
+            }
+        }
+    }
+
+    fn is_level_cleared(&self) -> bool {
+        !self
+            .entities
+            .iter()
+            .any(|entity| entity.tags.contains("Block") && !entity.tags.contains("Indestructible"))
+    }
+
+    fn update_entities(
+        &mut self,
+        render_buffer: &RenderBuffer,
+        input: &Input,
+        actions: &Actions,
+        dt: f32,
+    ) {
         // Player Controller
         {
+            let direction = actions.action(Action::MoveRight).is_down() as i32 as f32
+                - actions.action(Action::MoveLeft).is_down() as i32 as f32;
+            if input.mouse.position != self.last_mouse_position {
+                self.paddle_follows_mouse = true;
+            }
+            self.last_mouse_position = input.mouse.position;
+
+            let mouse_p = self.camera.pixels_to_world(
+                input.mouse.position,
+                render_buffer.width,
+                render_buffer.height,
+            );
+            if direction != 0.0 {
+                self.paddle_follows_mouse = false;
+                // Turning around starts again from rest
+                if self.paddle_speed * direction < 0.0 {
+                    self.paddle_speed = 0.0;
+                }
+                self.paddle_speed = (self.paddle_speed + direction * PADDLE_ACCELERATION * dt)
+                    .clamp(-PADDLE_MAX_SPEED, PADDLE_MAX_SPEED);
+            } else {
+                self.paddle_speed = 0.0;
+            }
+
+            let arena_half_width = self.arena_half_size.x;
+            let paddle_speed = self.paddle_speed;
+            let paddle_follows_mouse = self.paddle_follows_mouse;
             if let Some(player) = self.player.and_then(|player| self.entities.get_mut(player)) {
-                let mouse_p = render_buffer.pixels_to_world(input.mouse.position);
-                let new_player_p = Vec2::new(mouse_p.x, player.position.y);
-                player.velocity = (new_player_p - player.position) / dt;
+                if paddle_follows_mouse {
+                    let new_player_p = Vec2::new(mouse_p.x, player.position.y);
+                    player.velocity = (new_player_p - player.position) / dt;
+                } else if dt > 0.0 {
+                    let max_x = arena_half_width - player.half_size.x;
+                    let new_x = (player.position.x + paddle_speed * dt).clamp(-max_x, max_x);
+                    player.velocity = Vec2::new((new_x - player.position.x) / dt, 0.0);
+                }
+            }
+        }
+
+        // Serve
+        {
+            if let (Some(player), Some(ball)) = (self.player, self.serving) {
+                if let (Some(player), Some(ball)) = self.entities.get_two_mut(player, ball) {
+                    ball.position = Vec2::new(
+                        player.position.x,
+                        player.position.y + player.half_size.y + ball.half_size.y,
+                    );
+
+                    self.serve_time -= dt;
+                    if self.serve_time > 0.0 && !actions.action(Action::Launch).pressed() {
+                        ball.velocity = player.velocity;
+                    } else {
+                        ball.velocity = Vec2::new(30.0, 40.0);
+                        self.serving = None;
+                    }
+                } else {
+                    self.serving = None;
+                }
             }
         }
 
@@ -135,8 +717,26 @@ impl Game {
             }
         }
 
+        // Block path
+        {
+            for entity in self.entities.iter_mut() {
+                if entity.path.len() > 1 {
+                    let target = entity.path[entity.path_target];
+                    let step = entity.path_speed * dt;
+                    if (target - entity.position).len() <= step {
+                        entity.path_target = (entity.path_target + 1) % entity.path.len();
+                    }
+
+                    let target = entity.path[entity.path_target];
+                    entity.velocity = (target - entity.position).normalized() * entity.path_speed;
+                }
+            }
+        }
+
         // Count down
         {
+            self.score.update(dt);
+
             for entity in self.entities.iter_mut() {
                 entity.invincible = (entity.invincible - dt).max(0.0);
             }
@@ -172,11 +772,9 @@ impl Game {
                             if let Some(collision) =
                             swept_aabb2(&movement, a.half_size, b.position, b.half_size)
                             {
-                                if movement.vec() * collision.normal <= 0.0 {
-                                    if collision.t < t {
-                                        t = collision.t;
-                                        c = Some((index_b, collision));
-                                    }
+                                if movement.vec() * collision.normal <= 0.0 && collision.t < t {
+                                    t = collision.t;
+                                    c = Some((index_b, collision));
                                 }
                             }
                         }
@@ -194,7 +792,10 @@ impl Game {
 
                             if b.tags.contains("Block") {
                                 a.velocity = a.velocity.reflect(&collision.normal);
-                                b.life -= 1;
+                                if !b.tags.contains("Indestructible") {
+                                    b.life -= 1;
+                                    self.score.block_hit();
+                                }
                             } else if b.tags.contains("Wall") {
                                 a.velocity = a.velocity.reflect(&collision.normal);
                                 if !is_player_invincible && b.tags.contains("DeadWall") {
@@ -210,13 +811,25 @@ impl Game {
                                     a.velocity = a.velocity.reflect(&collision.normal);
                                 }
                                 a.velocity.x = (a.position.x - b.position.x) * 7.5;
+                                self.score.paddle_hit();
+                                self.particles
+                                    .emit(&EmitterConfig::paddle_hit(), a.position);
+                                self.juice.trigger(JuiceEvent::PaddleHit);
                             }
                         } else if a.tags.contains("PowerUp") {
                             if b.tags.contains("Wall") {
                                 a.life -= 1;
                             } else if b.tags.contains("Player") {
                                 a.life -= 1;
-                                b.invincible += 10.0;
+                                self.score.power_up_picked();
+                                let color = Color::from_u32(a.color.unwrap_or(0xffffff));
+                                self.particles
+                                    .emit(&EmitterConfig::power_up_pickup(color), a.position);
+                                match a.power_up {
+                                    Some(PowerUp::Invincible) => b.invincible += INVINCIBLE_TIME,
+                                    Some(PowerUp::ExtraLife) => b.life += 1,
+                                    None => {}
+                                }
                             }
                         }
                     }
@@ -230,30 +843,57 @@ impl Game {
         {
             let mut new_entities = Vec::new();
             let mut to_remove_entities = Vec::new();
+            let mut explosions = Vec::new();
             for (index, entity) in self.entities.iter().with_index() {
-                if entity.life == 0 {
-                    if entity.tags.contains("Block") {
-                        to_remove_entities.push(index);
-
-                        if entity.spawn_power_ups.len() > 0 {
-                            let mut power_up = Entity::new();
-                            power_up.tags.insert("PowerUp".to_string());
-                            power_up.position = entity.position;
-                            power_up.velocity = Vec2::new(0.0, -10.0);
-                            power_up.half_size = Vec2::new(1.0, 1.0);
-                            power_up.collide_with.insert("Wall".to_string());
-                            power_up.collide_with.insert("Player".to_string());
-                            power_up.life = 1;
-                            power_up.color = Some(0xffff00);
-                            new_entities.push(power_up);
-                        }
-                    } else if entity.tags.contains("Ball") {
-                        to_remove_entities.push(index);
-                    } else if entity.tags.contains("PowerUp") {
-                        to_remove_entities.push(index);
+                if entity.tags.contains("Block") && entity.life <= 0 {
+                    to_remove_entities.push(index);
+                    self.score.block_destroyed(entity.points);
+
+                    let color = Color::from_u32(entity.color.unwrap_or(0xffffff));
+                    self.particles
+                        .emit(&EmitterConfig::block_break(color), entity.position);
+                    self.juice.trigger(JuiceEvent::BlockBroken);
+
+                    if entity.tags.contains("Explosive") {
+                        self.juice.trigger(JuiceEvent::Explosion);
+                        explosions.push((entity.position, entity.explosion_radius));
+                    }
+
+                    for kind in entity.spawn_power_ups.iter() {
+                        let mut power_up = Entity::new();
+                        power_up.tags.insert("PowerUp".to_string());
+                        power_up.position = entity.position;
+                        power_up.velocity = Vec2::new(0.0, -10.0);
+                        power_up.half_size = Vec2::new(1.0, 1.0);
+                        power_up.collide_with.insert("Wall".to_string());
+                        power_up.collide_with.insert("Player".to_string());
+                        power_up.life = 1;
+                        power_up.color = Some(match kind {
+                            PowerUp::Invincible => 0xffff00,
+                            PowerUp::ExtraLife => 0xff00ff,
+                        });
+                        power_up.power_up = Some(kind.clone());
+                        new_entities.push(power_up);
+                    }
+                } else if entity.life == 0
+                    && (entity.tags.contains("Ball") || entity.tags.contains("PowerUp"))
+                {
+                    to_remove_entities.push(index);
+                }
+            }
+
+            for (position, radius) in explosions.iter() {
+                for entity in self.entities.iter_mut() {
+                    if entity.tags.contains("Block")
+                        && !entity.tags.contains("Indestructible")
+                        && entity.life > 0
+                        && (entity.position - *position).len() <= *radius
+                    {
+                        entity.life -= 1;
                     }
                 }
             }
+
             for index in to_remove_entities.iter() {
                 self.entities.remove(index);
             }
@@ -263,128 +903,339 @@ impl Game {
             }
         }
 
-        // Level manager
+        // Block color
         {
-            let mut ball_count = 0;
-            for entity in self.entities.iter() {
-                if entity.tags.contains("Ball") {
-                    ball_count += 1;
+            for entity in self.entities.iter_mut() {
+                if entity.tags.contains("Block") {
+                    entity.color = Some(block_color(entity));
                 }
             }
+        }
+    }
 
-            if ball_count == 0 {
-                self.load_level(self.current_level);
-            }
+    fn update_juice(&mut self, dt: f32) {
+        let paddle_velocity = self
+            .player
+            .and_then(|player| self.entities.get(player))
+            .map(|player| player.velocity)
+            .unwrap_or_default();
+        let balls = self
+            .entities
+            .iter()
+            .with_index()
+            .filter(|(_, entity)| entity.tags.contains("Ball"))
+            .map(|(index, entity)| (index, entity.position, entity.half_size.x));
+        self.juice.update(dt, paddle_velocity, balls);
+    }
 
-            if input.mouse.button(Button::Left).pressed() {
-                self.load_level(((self.current_level - 1) + self.levels.len()) % self.levels.len());
-            } else if input.mouse.button(Button::Right).pressed() {
-                self.load_level(((self.current_level + 1) + self.levels.len()) % self.levels.len());
-            }
-        }
+    fn render(&self, render_buffer: &mut RenderBuffer) {
+        let arena_color = match self.state {
+            GameState::Playing => 0x551100,
+            GameState::LevelComplete { .. } => 0x115511,
+            GameState::GameWon => 0x555511,
+            GameState::GameOver => 0x333333,
+            GameState::EnterInitials { .. } => 0x112255,
+        };
+
+        render_buffer.camera = self.juice.shake(self.camera);
+        render_buffer.clear_and_draw_rect(
+            Vec2::zero(),
+            self.arena_half_size,
+            arena_color,
+            0x220500,
+        );
 
-        render_buffer.clear_and_draw_rect(Vec2::zero(), self.arena_half_size, 0x551100, 0x220500);
+        self.juice.draw_trails(render_buffer);
 
         for entity in self.entities.iter() {
-            if let Some(color) = entity.color {
-                if entity.invincible > 0.0 {
-                    render_buffer.draw_rect(entity.position, entity.half_size, 0xffffff);
+            if let (Some(sprite), true) = (&entity.sprite, entity.invincible <= 0.0) {
+                render_buffer.draw_bitmap(entity.position, entity.half_size, sprite);
+            } else if let Some(color) = entity.color {
+                let color = if entity.invincible > 0.0 {
+                    0xffffff
+                } else {
+                    color
+                };
+
+                if entity.tags.contains("Ball") {
+                    render_buffer.draw_circle(entity.position, entity.half_size.x, color);
+                } else if entity.tags.contains("Player") {
+                    let scale = self.juice.paddle_scale();
+                    let half_size =
+                        Vec2::new(entity.half_size.x * scale.x, entity.half_size.y * scale.y);
+                    render_buffer.draw_rounded_rect(
+                        entity.position,
+                        half_size,
+                        half_size.y,
+                        color,
+                    );
                 } else {
                     render_buffer.draw_rect(entity.position, entity.half_size, color);
                 }
             }
 
             if entity.tags.contains("Ball") && entity.velocity.len2() > 0.0 {
-                render_buffer.draw_line(
+                render_buffer.draw_line_aa(
                     entity.position,
                     entity.position + entity.velocity.normalized() * 2.0,
-                    0xff0000,
+                    Color::rgb(255, 0, 0),
                 );
             }
         }
-    }
-}
 
-trait Level {
-    fn load(&self, entities: &mut IndexVec<Entity>);
-}
+        self.particles.draw(render_buffer);
 
-struct Level0 {}
+        render_buffer.draw_letterbox(0x000000);
+        render_buffer.camera = Camera::new();
 
-impl Level for Level0 {
-    fn load(&self, entities: &mut IndexVec<Entity>) {
-        // ball
-        {
-            let mut ball = Entity::new();
-            ball.tags.insert("Ball".to_string());
-            ball.collide_with.insert("Wall".to_string());
-            ball.collide_with.insert("Block".to_string());
-            ball.half_size = Vec2::new(0.75, 0.75);
-            ball.position.x = 60.0;
-            ball.velocity.y = -40.0;
-            ball.velocity.x = -30.0;
-            ball.color = Some(0x00ffff);
-            ball.life = 1;
-            entities.insert(ball);
+        self.render_hud(render_buffer);
+
+        let message = match self.state {
+            GameState::Playing => None,
+            GameState::LevelComplete { .. } => Some("LEVEL COMPLETE".to_string()),
+            GameState::GameWon | GameState::GameOver => None,
+            GameState::EnterInitials { .. } => Some(self.initials_prompt()),
+        };
+
+        if let Some(message) = message {
+            render_buffer.draw_text(
+                Vec2::new(0.0, -12.0),
+                3.0,
+                &message,
+                0xffffff,
+                TextAlign::Center,
+            );
+        }
+
+        if let GameState::EnterInitials { .. } = self.state {
+            let slot_half_size = Vec2::new(3.0, 4.0);
+            for i in 0..INITIALS_LEN {
+                let x = (i as f32 - (INITIALS_LEN - 1) as f32 * 0.5) * slot_half_size.x * 3.0;
+                let color = if i == self.initials_cursor {
+                    0xffffff
+                } else {
+                    0x808080
+                };
+                render_buffer.draw_rect(Vec2::new(x, -25.0), slot_half_size, color);
+
+                let initial = (self.initials[i] as char).to_string();
+                render_buffer.draw_text(
+                    Vec2::new(x, -28.0),
+                    6.0,
+                    &initial,
+                    0x000000,
+                    TextAlign::Center,
+                );
+            }
+        }
+
+        if let Some(screen) = self.menu.screen() {
+            let page = self.page(screen);
+            self.menu.draw(render_buffer, &page);
         }
 
-        for y in 0..8 {
-            for x in 0..8 {
-                let mut block = Entity::new();
-                block.tags.insert("Block".to_string());
-                block.position = Vec2::new(x as f32 * 12.0 - 40.0, y as f32 * 5.0);
-                block.half_size = Vec2::new(5.0, 2.0);
-                block.color = Some(0x000000);
-                block.life = 1;
-                if y == 0 { block.spawn_power_ups.push(PowerUp::Invincible); }
-                entities.insert(block);
+        let fade = match self.state {
+            GameState::LevelComplete { time_left } => 1.0 - time_left / FADE_TIME,
+            _ => self.fade_in / FADE_TIME,
+        };
+        render_buffer.fade_to_black(clamp(0.0, fade, 1.0));
+    }
+
+    fn render_hud(&self, render_buffer: &mut RenderBuffer) {
+        let mut timers = Vec::new();
+        if let Some(player) = self.player.and_then(|player| self.entities.get(player)) {
+            if player.invincible > 0.0 {
+                timers.push(Timer {
+                    label: "INVINCIBLE",
+                    time_left: player.invincible,
+                    duration: INVINCIBLE_TIME,
+                    color: 0xffff00,
+                });
             }
         }
+
+        let hud = Hud {
+            score: self.score.total(),
+            multiplier: self.score.multiplier(),
+            lives: self.lives(),
+            level_number: self.current_level + 1,
+            level_name: self
+                .levels
+                .get(self.current_level)
+                .map(|level| level.name())
+                .unwrap_or(""),
+            timers,
+        };
+        hud.draw(render_buffer, self.arena_half_size);
     }
 }
 
-struct Level1 {}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::golden::assert_golden;
 
-impl Level for Level1 {
-    fn load(&self, entities: &mut IndexVec<Entity>) {
-        // ball
-        {
-            let mut ball = Entity::new();
-            ball.tags.insert("Ball".to_string());
-            ball.collide_with.insert("Wall".to_string());
-            ball.collide_with.insert("Block".to_string());
-            ball.half_size = Vec2::new(0.75, 0.75);
-            ball.position.x = 60.0;
-            ball.velocity.y = -40.0;
-            ball.velocity.x = -30.0;
-            ball.color = Some(0x00ffff);
-            ball.life = 1;
-            entities.insert(ball);
-
-            let mut ball = Entity::new();
-            ball.tags.insert("Ball".to_string());
-            ball.collide_with.insert("Wall".to_string());
-            ball.collide_with.insert("Block".to_string());
-            ball.half_size = Vec2::new(0.75, 0.75);
-            ball.position.x = -60.0;
-            ball.velocity.y = -40.0;
-            ball.velocity.x = 30.0;
-            ball.color = Some(0x00ffff);
-            ball.life = 1;
-            entities.insert(ball);
+    #[test]
+    fn test_level0_first_frame() {
+        let (width, height) = (320, 180);
+        let mut pixels = vec![0; (width * height) as usize];
+
+        // The first simulated frame loads the level and opens the title menu
+        let mut game = Game::new();
+        game.simulate(
+            &mut RenderBuffer::new(&mut pixels, width, height),
+            &Input::new(),
+            0.0,
+        );
+        game.menu.clear();
+        game.render(&mut RenderBuffer::new(&mut pixels, width, height));
+        assert_golden("level0_first_frame", &pixels, width, height);
+    }
+
+    /// Runs a frame at 60 Hz, with the keys in `held` down and `pressed` this frame.
+    fn step(game: &mut Game, held: &[Key], pressed: &[Key]) {
+        let mut input = Input::new();
+        for &key in held.iter().chain(pressed.iter()) {
+            let state = input.keyboard.key_mut(key);
+            state.is_down = true;
+            state.was_down = held.contains(&key);
         }
+        let mut pixels = vec![0; 64 * 36];
+        game.simulate(
+            &mut RenderBuffer::new(&mut pixels, 64, 36),
+            &input,
+            1.0 / 60.0,
+        );
+    }
 
-        // blocks
-        for y in 0..8 {
-            for x in 0..8 {
-                let mut block = Entity::new();
-                block.tags.insert("Block".to_string());
-                block.position = Vec2::new(x as f32 * 12.0 - 40.0, y as f32 * 5.0);
-                block.half_size = Vec2::new(5.0, 2.0);
-                block.color = Some(0x000000);
-                block.life = 1;
-                entities.insert(block);
-            }
+    fn playing_game() -> Game {
+        let mut game = Game::new();
+        step(&mut game, &[], &[]);
+        game.menu.clear();
+        game
+    }
+
+    fn player(game: &Game) -> &Entity {
+        game.entities.get(game.player.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_keyboard_paddle() {
+        let mut game = playing_game();
+        step(&mut game, &[], &[Key::Right]);
+        let first_speed = player(&game).velocity.x;
+        assert!(first_speed > 0.0);
+        for _ in 0..4 {
+            step(&mut game, &[Key::Right], &[]);
+        }
+        assert!(player(&game).velocity.x > first_speed);
+
+        // Held long enough, it reaches full speed and then stops at the wall
+        for _ in 0..20 {
+            step(&mut game, &[Key::Right], &[]);
+        }
+        assert!((player(&game).velocity.x - PADDLE_MAX_SPEED).abs() < 0.01);
+        for _ in 0..60 {
+            step(&mut game, &[Key::Right], &[]);
         }
+        let max_x = game.arena_half_size.x - player(&game).half_size.x;
+        assert!((player(&game).position.x - max_x).abs() < 0.01);
+
+        step(&mut game, &[], &[]);
+        assert_eq!(player(&game).velocity.x, 0.0);
+        step(&mut game, &[], &[Key::Left]);
+        assert!((player(&game).velocity.x + first_speed).abs() < 0.01);
+
+        // R restarts the level, putting the paddle back
+        step(&mut game, &[], &[Key::R]);
+        assert_eq!(player(&game).position.x, 0.0);
+    }
+
+    #[test]
+    fn test_keyboard_pause() {
+        let mut game = playing_game();
+        step(&mut game, &[], &[Key::P]);
+        assert_eq!(game.menu.screen(), Some(Screen::Pause));
+        step(&mut game, &[], &[Key::P]);
+        assert_eq!(game.menu.screen(), None);
+
+        step(&mut game, &[], &[Key::Escape]);
+        assert_eq!(game.menu.screen(), Some(Screen::Pause));
+        step(&mut game, &[], &[Key::Escape]);
+        assert_eq!(game.menu.screen(), None);
+
+        // Enter picks the selected item, the first one from the title menu starts a game
+        game.menu.push(Screen::Title);
+        step(&mut game, &[], &[Key::Enter]);
+        assert_eq!(game.menu.screen(), None);
+    }
+
+    #[test]
+    fn test_restart_and_debug_levels() {
+        let mut game = playing_game();
+        game.options.debug = true;
+        step(&mut game, &[], &[Key::N]);
+        assert_eq!(game.current_level, 1);
+        step(&mut game, &[], &[Key::B]);
+        assert_eq!(game.current_level, 0);
+        step(&mut game, &[], &[Key::B]);
+        assert_eq!(game.current_level, game.levels.len() - 1);
+
+        game.options.debug = false;
+        step(&mut game, &[], &[Key::N]);
+        assert_eq!(game.current_level, game.levels.len() - 1);
+
+        // Restart is an action, so it follows its bindings
+        game.bindings
+            .set(Action::Restart, vec![Binding::Key(Key::K)]);
+        step(&mut game, &[], &[Key::Right]);
+        step(&mut game, &[Key::Right], &[Key::R]);
+        assert_ne!(player(&game).position.x, 0.0);
+        step(&mut game, &[], &[Key::K]);
+        assert_eq!(player(&game).position.x, 0.0);
+        step(&mut game, &[], &[Key::P]);
+        step(&mut game, &[], &[Key::K]);
+        assert_eq!(game.menu.screen(), None);
+    }
+
+    #[test]
+    fn test_initials_prompt() {
+        let mut game = playing_game();
+        assert!(game
+            .initials_prompt()
+            .ends_with("\nENTER, MOUSE LEFT, PAD A TO SAVE"));
+
+        game.bindings.set(Action::Fire, vec![Binding::Key(Key::F)]);
+        assert!(game.initials_prompt().ends_with("\nF TO SAVE"));
+    }
+
+    #[test]
+    fn test_rebinding() {
+        let dir = std::env::temp_dir().join(format!("breakout-game-{}", std::process::id()));
+        let mut game = playing_game();
+        game.set_config_dir(&dir);
+
+        // The first item on the controls screen is moving left
+        game.menu.push(Screen::Controls);
+        step(&mut game, &[], &[Key::Enter]);
+        assert_eq!(game.rebinding, Some(Action::MoveLeft));
+        step(&mut game, &[], &[Key::J]);
+        assert_eq!(game.rebinding, None);
+        assert_eq!(
+            game.bindings().get(Action::MoveLeft),
+            &[Binding::Key(Key::J)]
+        );
+        assert_eq!(
+            Bindings::load(&dir).get(Action::MoveLeft),
+            &[Binding::Key(Key::J)]
+        );
+
+        game.menu.clear();
+        step(&mut game, &[], &[Key::J]);
+        assert!(player(&game).velocity.x < 0.0);
+        step(&mut game, &[], &[Key::Left]);
+        assert_eq!(player(&game).velocity.x, 0.0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
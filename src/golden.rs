@@ -0,0 +1,114 @@
+// Golden-image checks for rendering tests. Reference images live in `tests/golden`, run the tests
+// with `UPDATE_GOLDEN=1` to write them after an intended change to the output. A mismatch writes
+// the actual frame and a diff image next to the build output.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bitmap::Bitmap;
+use crate::export;
+
+/// Matching pixels are kept at this fraction of their brightness in the diff image.
+const DIFF_DIM: u32 = 4;
+const DIFF_COLOR: u32 = 0xff0000;
+
+/// Panics unless the `0x00RRGGBB` frame equals the reference image `name`.
+pub fn assert_golden(name: &str, pixels: &[u32], width: i32, height: i32) {
+    let path = golden_dir().join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        export::save(&path, pixels, width, height).unwrap();
+        return;
+    }
+
+    let expected = match Bitmap::load(&path) {
+        Ok(bitmap) => bitmap,
+        Err(error) => panic!(
+            "can't load {}: {}, run with UPDATE_GOLDEN=1 to create it",
+            path.display(),
+            error
+        ),
+    };
+    let actual_path = output_dir().join(format!("{}.actual.png", name));
+    if (expected.width, expected.height) != (width, height) {
+        write_output(&actual_path, pixels, width, height);
+        panic!(
+            "{} is {}x{}, the frame is {}x{}, see {}",
+            path.display(),
+            expected.width,
+            expected.height,
+            width,
+            height,
+            actual_path.display()
+        );
+    }
+
+    let expected: Vec<u32> = expected
+        .pixels
+        .iter()
+        .map(|color| color.pack_argb() & 0xffffff)
+        .collect();
+    let (diff, mismatches) = diff(&expected, pixels);
+    if mismatches > 0 {
+        let diff_path = output_dir().join(format!("{}.diff.png", name));
+        write_output(&actual_path, pixels, width, height);
+        write_output(&diff_path, &diff, width, height);
+        panic!(
+            "{} pixels differ from {}, see {} and {}",
+            mismatches,
+            path.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn write_output(path: &Path, pixels: &[u32], width: i32, height: i32) {
+    fs::create_dir_all(output_dir()).unwrap();
+    export::save(path, pixels, width, height).unwrap();
+}
+
+/// Differing pixels in red over a dimmed copy of `expected`, and how many differ.
+fn diff(expected: &[u32], actual: &[u32]) -> (Vec<u32>, usize) {
+    let mut mismatches = 0;
+    let diff = expected
+        .iter()
+        .zip(actual.iter())
+        .map(|(&expected, &actual)| {
+            if expected == actual & 0xffffff {
+                let dim = |shift: u32| (((expected >> shift) & 0xff) / DIFF_DIM) << shift;
+                dim(16) | dim(8) | dim(0)
+            } else {
+                mismatches += 1;
+                DIFF_COLOR
+            }
+        })
+        .collect();
+    (diff, mismatches)
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+fn output_dir() -> PathBuf {
+    env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target"))
+        .join("golden")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let (diff, mismatches) = diff(&[0x404040, 0x00ff00, 0], &[0xff404040, 0x00fe00, 0]);
+        assert_eq!(diff, vec![0x101010, DIFF_COLOR, 0]);
+        assert_eq!(mismatches, 1);
+    }
+}
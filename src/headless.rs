@@ -0,0 +1,161 @@
+// Runs the game without a window for a fixed number of frames, writing a screenshot of the last
+// frame and/or every frame as an image sequence.
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::command_list::Rasterizer;
+use crate::export::{self, FrameSequence, ImageFormat};
+use crate::game::Game;
+use crate::input::Input;
+use crate::software_rendering::RenderBuffer;
+
+pub const USAGE: &str = "usage: breakout --headless [--size WIDTHxHEIGHT] [--frames COUNT] \
+                         [--screenshot FILE] [--record DIRECTORY] [--format png|ppm]";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub width: i32,
+    pub height: i32,
+    pub frames: u32,
+    /// Simulated seconds per frame.
+    pub dt: f32,
+    /// The last frame is written here, in the format its extension names.
+    pub screenshot: Option<PathBuf>,
+    /// Every frame is written here as `frame_NNNNNN` in `format`.
+    pub record: Option<PathBuf>,
+    pub format: ImageFormat,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            width: 1280,
+            height: 720,
+            frames: 1,
+            dt: 1.0 / 60.0,
+            screenshot: None,
+            record: None,
+            format: ImageFormat::Png,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line arguments, without the program name. `--headless` itself is
+    /// accepted and ignored.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--headless" {
+                continue;
+            }
+
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {}", arg))?;
+            let invalid = || format!("invalid value for {}: {}", arg, value);
+            match arg.as_str() {
+                "--size" => {
+                    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                    options.width = width.parse().map_err(|_| invalid())?;
+                    options.height = height.parse().map_err(|_| invalid())?;
+                    if options.width <= 0 || options.height <= 0 {
+                        return Err(invalid());
+                    }
+                }
+                "--frames" => options.frames = value.parse().map_err(|_| invalid())?,
+                "--screenshot" => options.screenshot = Some(PathBuf::from(value)),
+                "--record" => options.record = Some(PathBuf::from(value)),
+                "--format" => options.format = ImageFormat::parse(&value).ok_or_else(invalid)?,
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        if options.screenshot.is_none() && options.record.is_none() {
+            return Err("nothing to write, pass --screenshot or --record".to_string());
+        }
+        Ok(options)
+    }
+}
+
+pub fn run(options: &Options) -> io::Result<()> {
+    let (width, height) = (options.width, options.height);
+    let mut pixels = vec![0; (width * height) as usize];
+    let mut sequence = match options.record.as_ref() {
+        Some(directory) => Some(FrameSequence::new(directory, options.format)?),
+        None => None,
+    };
+    let rasterizer = Rasterizer::default();
+
+    // No config directory, so high scores aren't touched
+    let mut game = Game::new();
+    let input = Input::new();
+    for _ in 0..options.frames {
+        let mut render_buffer = RenderBuffer::recording(&mut pixels, width, height);
+        game.simulate(&mut render_buffer, &input, options.dt);
+        render_buffer.flush(&rasterizer);
+
+        if let Some(sequence) = sequence.as_mut() {
+            sequence.write(&pixels, width, height)?;
+        }
+    }
+
+    if let Some(path) = options.screenshot.as_ref() {
+        export::save(path, &pixels, width, height)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("--headless --size 320x200 --frames 3 --record out --format ppm"),
+            Ok(Options {
+                width: 320,
+                height: 200,
+                frames: 3,
+                record: Some(PathBuf::from("out")),
+                format: ImageFormat::Ppm,
+                ..Options::default()
+            })
+        );
+        assert!(parse("--headless").is_err());
+        assert!(parse("--screenshot").is_err());
+        assert!(parse("--screenshot a.png --size 0x10").is_err());
+        assert!(parse("--screenshot a.png --format gif").is_err());
+        assert!(parse("--screenshot a.png --fullscreen yes").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir().join(format!("breakout-headless-{}", std::process::id()));
+        let options = Options {
+            width: 64,
+            height: 48,
+            frames: 3,
+            screenshot: Some(dir.join("last.png")),
+            record: Some(dir.join("frames")),
+            ..Options::default()
+        };
+        run(&options).unwrap();
+
+        let last = std::fs::read(dir.join("last.png")).unwrap();
+        assert_eq!(
+            std::fs::read(dir.join("frames").join("frame_000002.png")).unwrap(),
+            last
+        );
+        assert!(!dir.join("frames").join("frame_000003.png").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const MAX_ENTRIES: usize = 10;
+pub const INITIALS_LEN: usize = 3;
+
+const FILE_NAME: &str = "high_scores.txt";
+const HEADER: &str = "breakout high scores v1";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HighScore {
+    pub initials: String,
+    pub score: u32,
+    pub level: usize,
+    pub date: String,
+}
+
+#[derive(Default)]
+pub struct HighScores {
+    path: Option<PathBuf>,
+    packs: BTreeMap<String, Vec<HighScore>>,
+}
+
+impl HighScores {
+    pub fn new() -> HighScores {
+        HighScores::default()
+    }
+
+    /// Loads the table stored in `config_dir`. A missing or corrupt file yields an empty table
+    /// (or whatever entries could still be read), it is never an error.
+    pub fn load(config_dir: &Path) -> HighScores {
+        let path = config_dir.join(FILE_NAME);
+        let packs = fs::read_to_string(&path)
+            .map(|text| parse(&text))
+            .unwrap_or_default();
+
+        HighScores {
+            path: Some(path),
+            packs,
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            // Write then rename so a crash mid-write can't corrupt the existing table
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, serialize(&self.packs))?;
+            fs::rename(&tmp_path, path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn entries(&self, pack: &str) -> &[HighScore] {
+        self.packs
+            .get(pack)
+            .map(|entries| &entries[..])
+            .unwrap_or(&[])
+    }
+
+    pub fn qualifies(&self, pack: &str, score: u32) -> bool {
+        let entries = self.entries(pack);
+        score > 0
+            && (entries.len() < MAX_ENTRIES || entries.iter().any(|entry| score > entry.score))
+    }
+
+    /// Returns the rank of the new entry, or `None` if it didn't make the table.
+    pub fn insert(&mut self, pack: &str, entry: HighScore) -> Option<usize> {
+        let entries = self.packs.entry(pack.to_string()).or_default();
+        let rank = entries
+            .iter()
+            .position(|other| entry.score > other.score)
+            .unwrap_or(entries.len());
+
+        if rank >= MAX_ENTRIES {
+            return None;
+        }
+
+        entries.insert(rank, entry);
+        entries.truncate(MAX_ENTRIES);
+
+        Some(rank)
+    }
+}
+
+fn parse(text: &str) -> BTreeMap<String, Vec<HighScore>> {
+    let mut packs = BTreeMap::new();
+
+    let mut lines = text.lines();
+    if lines.next().map(|line| line.trim()) != Some(HEADER) {
+        return packs;
+    }
+
+    for line in lines {
+        if let Some((pack, entry)) = parse_line(line) {
+            packs.entry(pack).or_insert_with(Vec::new).push(entry);
+        }
+    }
+
+    for entries in packs.values_mut() {
+        entries.sort_by_key(|entry| Reverse(entry.score));
+        entries.truncate(MAX_ENTRIES);
+    }
+
+    packs
+}
+
+fn parse_line(line: &str) -> Option<(String, HighScore)> {
+    let mut fields = line.split('\t');
+    let pack = fields.next()?;
+    let initials = fields.next()?;
+    let score = fields.next()?.parse().ok()?;
+    let level = fields.next()?.parse().ok()?;
+    let date = fields.next()?;
+
+    if fields.next().is_some() || pack.is_empty() || !valid_initials(initials) {
+        return None;
+    }
+
+    Some((
+        pack.to_string(),
+        HighScore {
+            initials: initials.to_string(),
+            score,
+            level,
+            date: date.to_string(),
+        },
+    ))
+}
+
+fn serialize(packs: &BTreeMap<String, Vec<HighScore>>) -> String {
+    let mut text = String::new();
+    text.push_str(HEADER);
+    text.push('\n');
+
+    for (pack, entries) in packs.iter() {
+        for entry in entries.iter() {
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                pack, entry.initials, entry.score, entry.level, entry.date
+            ));
+        }
+    }
+
+    text
+}
+
+pub fn valid_initials(initials: &str) -> bool {
+    !initials.is_empty()
+        && initials.len() <= INITIALS_LEN
+        && initials
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/// Today's date as `YYYY-MM-DD` (UTC).
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86400)
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(initials: &str, score: u32) -> HighScore {
+        HighScore {
+            initials: initials.to_string(),
+            score,
+            level: 1,
+            date: "2020-01-01".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_insert_keeps_top_entries() {
+        let mut high_scores = HighScores::new();
+        for i in 0..MAX_ENTRIES as u32 {
+            assert_eq!(
+                high_scores.insert("pack", entry("AAA", (i + 1) * 10)),
+                Some(0)
+            );
+        }
+
+        assert!(!high_scores.qualifies("pack", 10));
+        assert!(high_scores.qualifies("pack", 15));
+        assert!(high_scores.qualifies("other", 1));
+        assert_eq!(high_scores.insert("pack", entry("BBB", 5)), None);
+        assert_eq!(
+            high_scores.insert("pack", entry("CCC", 15)),
+            Some(MAX_ENTRIES - 1)
+        );
+
+        let entries = high_scores.entries("pack");
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].score, MAX_ENTRIES as u32 * 10);
+        assert_eq!(entries[MAX_ENTRIES - 1].score, 15);
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        let mut high_scores = HighScores::new();
+        high_scores.insert("a", entry("AB", 100));
+        high_scores.insert("a", entry("XYZ", 300));
+        high_scores.insert("b", entry("Q1", 200));
+
+        let packs = parse(&serialize(&high_scores.packs));
+        assert_eq!(packs, high_scores.packs);
+    }
+
+    #[test]
+    fn test_parse_corrupt() {
+        assert!(parse("").is_empty());
+        assert!(parse("\u{0}\u{1}garbage\n").is_empty());
+
+        let text = format!(
+            "{}\npack\tAAA\t100\t2\t2020-01-01\npack\tAAA\tlots\t2\t2020-01-01\npack\taaaa\t5\t1\tx\ntruncated\n",
+            HEADER
+        );
+        let packs = parse(&text);
+        assert_eq!(
+            packs["pack"],
+            vec![HighScore {
+                initials: "AAA".to_string(),
+                score: 100,
+                level: 2,
+                date: "2020-01-01".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_load_missing_or_corrupt_file() {
+        let dir = std::env::temp_dir().join(format!("breakout-high-score-{}", std::process::id()));
+        assert!(HighScores::load(&dir).entries("pack").is_empty());
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(FILE_NAME), [0xff, 0xfe, 0x00]).unwrap();
+        let mut high_scores = HighScores::load(&dir);
+        assert!(high_scores.entries("pack").is_empty());
+
+        high_scores.insert("pack", entry("ABC", 42));
+        high_scores.save().unwrap();
+        assert_eq!(
+            HighScores::load(&dir).entries("pack"),
+            &[entry("ABC", 42)][..]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+}
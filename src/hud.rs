@@ -0,0 +1,148 @@
+use crate::font::TextAlign;
+use crate::math::{clamp, Vec2};
+use crate::software_rendering::RenderBuffer;
+
+const TEXT_SIZE: f32 = 2.5;
+const LINE_HEIGHT: f32 = TEXT_SIZE * 1.5;
+const MARGIN: f32 = 1.0;
+const BAR_WIDTH: f32 = 20.0;
+const TEXT_COLOR: u32 = 0xffffff;
+
+/// Minimum room beside the arena before the HUD moves into side columns.
+const SIDE_COLUMN_WIDTH: f32 = 30.0;
+
+pub struct Timer<'a> {
+    pub label: &'a str,
+    pub time_left: f32,
+    pub duration: f32,
+    pub color: u32,
+}
+
+pub struct Hud<'a> {
+    pub score: u32,
+    pub multiplier: u32,
+    pub lives: i32,
+    pub level_number: usize,
+    pub level_name: &'a str,
+    pub timers: Vec<Timer<'a>>,
+}
+
+impl<'a> Hud<'a> {
+    /// Draws along the top of the arena, or in columns on its sides when the window is wide
+    /// enough for them.
+    pub fn draw(&self, render_buffer: &mut RenderBuffer, arena_half_size: Vec2) {
+        let visible_half_size = render_buffer.pixels_to_world(Vec2::new(
+            render_buffer.width as f32,
+            render_buffer.height as f32,
+        ));
+        // Walls are 2 units thick
+        let side_room = visible_half_size.x - arena_half_size.x - 2.0;
+
+        if side_room >= SIDE_COLUMN_WIDTH {
+            let top = arena_half_size.y - TEXT_SIZE;
+            let left = -visible_half_size.x + MARGIN;
+            let right = visible_half_size.x - MARGIN;
+
+            let lines = [
+                "SCORE".to_string(),
+                self.score.to_string(),
+                format!("X{}", self.multiplier),
+            ];
+            self.draw_lines(render_buffer, Vec2::new(left, top), &lines, TextAlign::Left);
+
+            let lines = [
+                format!("LEVEL {}", self.level_number),
+                self.level_name.to_uppercase(),
+                format!("LIVES {}", self.lives.max(0)),
+            ];
+            self.draw_lines(
+                render_buffer,
+                Vec2::new(right, top),
+                &lines,
+                TextAlign::Right,
+            );
+
+            let mut y = top - LINE_HEIGHT * (lines.len() as f32 + 1.0);
+            for timer in self.timers.iter() {
+                self.draw_timer(render_buffer, Vec2::new(right - BAR_WIDTH, y), timer);
+                y -= LINE_HEIGHT * 2.0;
+            }
+        } else {
+            // Keep clear of the top wall, but use the room above it on tall windows
+            let y =
+                (arena_half_size.y + 2.0 + MARGIN).max(visible_half_size.y - MARGIN - TEXT_SIZE);
+            let left = -arena_half_size.x;
+            let right = arena_half_size.x;
+
+            let text = format!("SCORE {}  X{}", self.score, self.multiplier);
+            render_buffer.draw_text(
+                Vec2::new(left, y),
+                TEXT_SIZE,
+                &text,
+                TEXT_COLOR,
+                TextAlign::Left,
+            );
+
+            let text = format!(
+                "LEVEL {} {}",
+                self.level_number,
+                self.level_name.to_uppercase()
+            );
+            render_buffer.draw_text(
+                Vec2::new(0.0, y),
+                TEXT_SIZE,
+                &text,
+                TEXT_COLOR,
+                TextAlign::Center,
+            );
+
+            let text = format!("LIVES {}", self.lives.max(0));
+            render_buffer.draw_text(
+                Vec2::new(right, y),
+                TEXT_SIZE,
+                &text,
+                TEXT_COLOR,
+                TextAlign::Right,
+            );
+
+            // Timers sit inside the arena, just below the top wall
+            let mut y = arena_half_size.y - TEXT_SIZE - MARGIN;
+            for timer in self.timers.iter() {
+                self.draw_timer(render_buffer, Vec2::new(right - BAR_WIDTH, y), timer);
+                y -= LINE_HEIGHT * 2.0;
+            }
+        }
+    }
+
+    fn draw_lines(
+        &self,
+        render_buffer: &mut RenderBuffer,
+        mut p: Vec2,
+        lines: &[String],
+        align: TextAlign,
+    ) {
+        for line in lines.iter() {
+            render_buffer.draw_text(p, TEXT_SIZE, line, TEXT_COLOR, align);
+            p.y -= LINE_HEIGHT;
+        }
+    }
+
+    /// `p` is the bottom left of the label, the bar goes under it.
+    fn draw_timer(&self, render_buffer: &mut RenderBuffer, p: Vec2, timer: &Timer) {
+        let text = format!("{} {:.0}", timer.label, timer.time_left.ceil());
+        render_buffer.draw_text(p, TEXT_SIZE, &text, timer.color, TextAlign::Left);
+
+        let fraction = if timer.duration > 0.0 {
+            clamp(0.0, timer.time_left / timer.duration, 1.0)
+        } else {
+            0.0
+        };
+        let bar_half_size = Vec2::new(BAR_WIDTH * 0.5, 0.5);
+        let bar_center = Vec2::new(p.x + bar_half_size.x, p.y - 1.0 - bar_half_size.y);
+        render_buffer.draw_rect(bar_center, bar_half_size, 0x202020);
+
+        let fill_half_size = Vec2::new(bar_half_size.x * fraction, bar_half_size.y);
+        let fill_center = Vec2::new(p.x + fill_half_size.x, bar_center.y);
+        render_buffer.draw_rect(fill_center, fill_half_size, timer.color);
+    }
+}
@@ -19,7 +19,7 @@ impl GIndex {
 
 impl<T> Index<T> for GIndex {
     fn index(&self) -> usize {
-        self.index as usize
+        self.index
     }
 
     fn valid(&self, entry: &OccupiedEntry<T>) -> bool {
@@ -29,7 +29,7 @@ impl<T> Index<T> for GIndex {
 
 impl<T> Index<T> for &GIndex {
     fn index(&self) -> usize {
-        self.index as usize
+        self.index
     }
 
     fn valid(&self, entry: &OccupiedEntry<T>) -> bool {
@@ -99,6 +99,10 @@ impl<T> IndexVec<T> {
         self.entries.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
     pub fn get<I: Index<T>>(&self, index: I) -> Option<&T> {
         if let Some(Entry::Occupied(entry)) = self.entries.get(index.index()) {
             if index.valid(entry) {
@@ -110,7 +114,7 @@ impl<T> IndexVec<T> {
     }
 
     pub fn get_mut<I: Index<T>>(&mut self, index: I) -> Option<&mut T> {
-        if let Some(Entry::Occupied(entry)) = self.entries.get_mut(index.index() as usize) {
+        if let Some(Entry::Occupied(entry)) = self.entries.get_mut(index.index()) {
             if index.valid(entry) {
                 return Some(&mut entry.value);
             }
@@ -152,7 +156,7 @@ impl<T> IndexVec<T> {
 
     pub fn insert(&mut self, value: T) -> GIndex {
         if let Some(index) = self.free.pop_front() {
-            let entry = &mut self.entries[index as usize];
+            let entry = &mut self.entries[index];
             let generation = match entry {
                 Entry::Vacant(entry) => entry.generation + 1,
                 _ => unreachable!(),
@@ -195,21 +199,21 @@ impl<T> IndexVec<T> {
         None
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter {
             index_vec: self,
             i: 0,
         }
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
             index_vec: self,
             i: 0,
         }
     }
 
-    pub fn iter_two_mut(&mut self) -> IterTwoMut<T> {
+    pub fn iter_two_mut(&mut self) -> IterTwoMut<'_, T> {
         IterTwoMut {
             index_vec: self,
             i: 0,
@@ -0,0 +1,393 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::input::gamepad::{GamepadButton, GAMEPAD_BUTTONS};
+use crate::input::keyboard::Key;
+use crate::input::mouse::{Button, MOUSE_BUTTONS};
+use crate::input::{ButtonState, Input};
+
+const FILE_NAME: &str = "bindings.txt";
+const HEADER: &str = "breakout bindings v1";
+
+/// What gameplay asks for, independent of the device it comes from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(usize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Launch,
+    Pause,
+    Fire,
+    Restart,
+    PreviousLevel,
+    NextLevel,
+
+    Count,
+}
+
+pub const ACTIONS: [(Action, &str); Action::Count as usize] = [
+    (Action::MoveLeft, "move_left"),
+    (Action::MoveRight, "move_right"),
+    (Action::Launch, "launch"),
+    (Action::Pause, "pause"),
+    (Action::Fire, "fire"),
+    (Action::Restart, "restart"),
+    (Action::PreviousLevel, "previous_level"),
+    (Action::NextLevel, "next_level"),
+];
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        ACTIONS[self as usize].1
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(_, other)| *other == name)
+            .map(|&(action, _)| action)
+    }
+
+    /// For menus, e.g. `MOVE LEFT`.
+    pub fn label(self) -> String {
+        self.name().replace('_', " ").to_uppercase()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Binding {
+    Key(Key),
+    Mouse(Button),
+    Gamepad(GamepadButton),
+}
+
+impl Binding {
+    /// As written in the bindings file, e.g. `key:space`, `mouse:left` or `gamepad:start`.
+    pub fn name(self) -> String {
+        match self {
+            Binding::Key(key) => format!("key:{}", key.name()),
+            Binding::Mouse(button) => format!("mouse:{}", button.name()),
+            Binding::Gamepad(button) => format!("gamepad:{}", button.name()),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Binding> {
+        let (device, input) = name.split_once(':')?;
+        match device {
+            "key" => Key::from_name(input).map(Binding::Key),
+            "mouse" => Button::from_name(input).map(Binding::Mouse),
+            "gamepad" => GamepadButton::from_name(input).map(Binding::Gamepad),
+            _ => None,
+        }
+    }
+
+    /// For menus, e.g. `SPACE`, `MOUSE LEFT` or `PAD START`.
+    pub fn label(self) -> String {
+        let label = match self {
+            Binding::Key(key) => key.name(),
+            Binding::Mouse(button) => format!("mouse {}", button.name()),
+            Binding::Gamepad(button) => format!("pad {}", button.name()),
+        };
+        label.replace('_', " ").to_uppercase()
+    }
+
+    pub fn state(self, input: &Input) -> &ButtonState {
+        match self {
+            Binding::Key(key) => input.keyboard.key(key),
+            Binding::Mouse(button) => input.mouse.button(button),
+            Binding::Gamepad(button) => input.gamepad.button(button),
+        }
+    }
+
+    /// The first key or button pressed this frame, on any device.
+    pub fn pressed(input: &Input) -> Option<Binding> {
+        let keys = Key::all().map(Binding::Key);
+        let buttons = MOUSE_BUTTONS
+            .iter()
+            .map(|&(button, _)| Binding::Mouse(button));
+        let gamepad = GAMEPAD_BUTTONS
+            .iter()
+            .map(|&(button, _)| Binding::Gamepad(button));
+        keys.chain(buttons)
+            .chain(gamepad)
+            .find(|binding| binding.state(input).pressed())
+    }
+}
+
+/// The keys and buttons that trigger each action, any of them will do.
+pub struct Bindings {
+    path: Option<PathBuf>,
+    bindings: [Vec<Binding>; Action::Count as usize],
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings::new()
+    }
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        let mut bindings = Bindings {
+            path: None,
+            bindings: Default::default(),
+        };
+        bindings.reset();
+        bindings
+    }
+
+    /// Loads the bindings stored in `config_dir`. Actions missing from the file, or without a
+    /// valid binding in it, keep their defaults.
+    pub fn load(config_dir: &Path) -> Bindings {
+        let path = config_dir.join(FILE_NAME);
+        let mut bindings = Bindings::new();
+        if let Ok(text) = fs::read_to_string(&path) {
+            bindings.parse(&text);
+        }
+        bindings.path = Some(path);
+        bindings
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+
+            // Write then rename so a crash mid-write can't corrupt the existing bindings
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, self.serialize())?;
+            fs::rename(&tmp_path, path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Back to the defaults, which cover the keyboard, the mouse and a gamepad.
+    pub fn reset(&mut self) {
+        let key = Binding::Key;
+        let mouse = Binding::Mouse;
+        let gamepad = Binding::Gamepad;
+        self.bindings = [
+            vec![
+                key(Key::Left),
+                key(Key::A),
+                gamepad(GamepadButton::DPadLeft),
+            ],
+            vec![
+                key(Key::Right),
+                key(Key::D),
+                gamepad(GamepadButton::DPadRight),
+            ],
+            vec![
+                key(Key::Space),
+                mouse(Button::Left),
+                gamepad(GamepadButton::A),
+            ],
+            vec![
+                key(Key::P),
+                key(Key::Escape),
+                mouse(Button::Right),
+                gamepad(GamepadButton::Start),
+            ],
+            vec![
+                key(Key::Enter),
+                mouse(Button::Left),
+                gamepad(GamepadButton::A),
+            ],
+            vec![key(Key::R), gamepad(GamepadButton::Back)],
+            vec![key(Key::B), gamepad(GamepadButton::LeftShoulder)],
+            vec![key(Key::N), gamepad(GamepadButton::RightShoulder)],
+        ];
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        &self.bindings[action as usize]
+    }
+
+    pub fn set(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.bindings[action as usize] = bindings;
+    }
+
+    /// Adds `binding` to `action` unless it's already there.
+    pub fn add(&mut self, action: Action, binding: Binding) {
+        let bindings = &mut self.bindings[action as usize];
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// The state of every action this frame.
+    pub fn resolve(&self, input: &Input) -> Actions {
+        let mut actions = Actions::new();
+        for (state, bindings) in actions.states.iter_mut().zip(self.bindings.iter()) {
+            for binding in bindings.iter() {
+                let binding = binding.state(input);
+                state.is_down |= binding.is_down;
+                state.was_down |= binding.was_down;
+            }
+        }
+        actions
+    }
+
+    /// One `action<TAB>binding` line per binding.
+    fn parse(&mut self, text: &str) {
+        let mut lines = text.lines();
+        if lines.next().map(|line| line.trim()) != Some(HEADER) {
+            return;
+        }
+
+        let mut loaded: [Vec<Binding>; Action::Count as usize] = Default::default();
+        for line in lines {
+            let mut fields = line.split('\t');
+            let action = fields.next().and_then(Action::from_name);
+            let binding = fields.next().and_then(Binding::from_name);
+            if let (Some(action), Some(binding), None) = (action, binding, fields.next()) {
+                if !loaded[action as usize].contains(&binding) {
+                    loaded[action as usize].push(binding);
+                }
+            }
+        }
+
+        for (bindings, loaded) in self.bindings.iter_mut().zip(loaded.iter_mut()) {
+            if !loaded.is_empty() {
+                *bindings = std::mem::take(loaded);
+            }
+        }
+    }
+
+    fn serialize(&self) -> String {
+        let mut text = String::new();
+        text.push_str(HEADER);
+        text.push('\n');
+
+        for &(action, name) in ACTIONS.iter() {
+            for binding in self.get(action).iter() {
+                text.push_str(&format!("{}\t{}\n", name, binding.name()));
+            }
+        }
+
+        text
+    }
+}
+
+/// Actions are down while any of their bindings is, and pressed when the first one goes down.
+pub struct Actions {
+    states: [ButtonState; Action::Count as usize],
+}
+
+impl Default for Actions {
+    fn default() -> Self {
+        Actions::new()
+    }
+}
+
+impl Actions {
+    pub fn new() -> Actions {
+        Actions {
+            states: [ButtonState::new(); Action::Count as usize],
+        }
+    }
+
+    pub fn action(&self, action: Action) -> &ButtonState {
+        assert!((action as usize) < (Action::Count as usize));
+        &self.states[action as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for &(action, name) in ACTIONS.iter() {
+            assert_eq!(Action::from_name(name), Some(action));
+        }
+        assert_eq!(Action::PreviousLevel.label(), "PREVIOUS LEVEL");
+
+        let bindings = [
+            Binding::Key(Key::Escape),
+            Binding::Key(Key::Num1),
+            Binding::Mouse(Button::Right),
+            Binding::Gamepad(GamepadButton::DPadLeft),
+        ];
+        for &binding in bindings.iter() {
+            assert_eq!(Binding::from_name(&binding.name()), Some(binding));
+        }
+        assert_eq!(
+            Binding::Gamepad(GamepadButton::DPadLeft).name(),
+            "gamepad:dpad_left"
+        );
+        assert_eq!(
+            Binding::Gamepad(GamepadButton::DPadLeft).label(),
+            "PAD DPAD LEFT"
+        );
+        assert_eq!(Binding::from_name("key"), None);
+        assert_eq!(Binding::from_name("joystick:a"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let bindings = Bindings::new();
+        let mut input = Input::new();
+        input.keyboard.key_mut(Key::Space).is_down = true;
+        let actions = bindings.resolve(&input);
+        assert!(actions.action(Action::Launch).pressed());
+        assert!(!actions.action(Action::Pause).is_down());
+
+        // A second binding going down while the first is held isn't a new press
+        input.begin_frame();
+        input.gamepad.button_mut(GamepadButton::A).is_down = true;
+        let actions = bindings.resolve(&input);
+        assert!(actions.action(Action::Launch).is_down());
+        assert!(!actions.action(Action::Launch).pressed());
+        assert!(actions.action(Action::Fire).pressed());
+
+        assert_eq!(
+            Binding::pressed(&input),
+            Some(Binding::Gamepad(GamepadButton::A))
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("breakout-bindings-{}", std::process::id()));
+        let mut bindings = Bindings::load(&dir);
+        assert_eq!(
+            bindings.get(Action::Pause),
+            Bindings::new().get(Action::Pause)
+        );
+
+        bindings.set(Action::MoveLeft, vec![Binding::Key(Key::J)]);
+        bindings.add(Action::MoveLeft, Binding::Gamepad(GamepadButton::X));
+        bindings.add(Action::MoveLeft, Binding::Key(Key::J));
+        bindings.save().unwrap();
+
+        let loaded = Bindings::load(&dir);
+        assert_eq!(
+            loaded.get(Action::MoveLeft),
+            &[Binding::Key(Key::J), Binding::Gamepad(GamepadButton::X)]
+        );
+        assert_eq!(loaded.get(Action::Fire), Bindings::new().get(Action::Fire));
+
+        // Unknown lines are skipped, actions left without bindings keep their defaults
+        fs::write(
+            dir.join(FILE_NAME),
+            format!(
+                "{}\npause\tkey:tab\nfire\tkey:f\nfire\tkey:f extra\n",
+                HEADER
+            ),
+        )
+        .unwrap();
+        let loaded = Bindings::load(&dir);
+        assert_eq!(loaded.get(Action::Fire), &[Binding::Key(Key::F)]);
+        assert_eq!(
+            loaded.get(Action::Pause),
+            Bindings::new().get(Action::Pause)
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
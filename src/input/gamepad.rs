@@ -0,0 +1,78 @@
+use crate::input::ButtonState;
+
+/// Buttons by position, as on an Xbox controller: `A` is the bottom face button.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(usize)]
+pub enum GamepadButton {
+    A,
+    B,
+    X,
+    Y,
+    LeftShoulder,
+    RightShoulder,
+    Back,
+    Start,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+
+    Count,
+}
+
+pub const GAMEPAD_BUTTONS: [(GamepadButton, &str); GamepadButton::Count as usize] = [
+    (GamepadButton::A, "a"),
+    (GamepadButton::B, "b"),
+    (GamepadButton::X, "x"),
+    (GamepadButton::Y, "y"),
+    (GamepadButton::LeftShoulder, "left_shoulder"),
+    (GamepadButton::RightShoulder, "right_shoulder"),
+    (GamepadButton::Back, "back"),
+    (GamepadButton::Start, "start"),
+    (GamepadButton::DPadUp, "dpad_up"),
+    (GamepadButton::DPadDown, "dpad_down"),
+    (GamepadButton::DPadLeft, "dpad_left"),
+    (GamepadButton::DPadRight, "dpad_right"),
+];
+
+impl GamepadButton {
+    pub fn name(self) -> &'static str {
+        GAMEPAD_BUTTONS[self as usize].1
+    }
+
+    pub fn from_name(name: &str) -> Option<GamepadButton> {
+        GAMEPAD_BUTTONS
+            .iter()
+            .find(|(_, other)| *other == name)
+            .map(|&(button, _)| button)
+    }
+}
+
+/// The buttons of the first connected gamepad, all up if there is none.
+pub struct Gamepad {
+    pub buttons: [ButtonState; GamepadButton::Count as usize],
+}
+
+impl Default for Gamepad {
+    fn default() -> Self {
+        Gamepad::new()
+    }
+}
+
+impl Gamepad {
+    pub fn new() -> Gamepad {
+        Gamepad {
+            buttons: [ButtonState::new(); GamepadButton::Count as usize],
+        }
+    }
+
+    pub fn button(&self, button: GamepadButton) -> &ButtonState {
+        assert!((button as usize) < (GamepadButton::Count as usize));
+        &self.buttons[button as usize]
+    }
+
+    pub fn button_mut(&mut self, button: GamepadButton) -> &mut ButtonState {
+        assert!((button as usize) < (GamepadButton::Count as usize));
+        &mut self.buttons[button as usize]
+    }
+}
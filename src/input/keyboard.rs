@@ -1,20 +1,190 @@
 use crate::input::ButtonState;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(usize)]
 pub enum Key {
     Left,
     Right,
     Up,
     Down,
+    F11,
+    F12,
+
+    Space,
+    Enter,
+    Escape,
+    Backspace,
+
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+
+    Num0,
+    Num1,
+    Num2,
+    Num3,
+    Num4,
+    Num5,
+    Num6,
+    Num7,
+    Num8,
+    Num9,
 
     Count,
 }
 
+/// Keys that don't type a letter or digit, and their names.
+pub const NAMED_KEYS: [(Key, &str); 10] = [
+    (Key::Left, "left"),
+    (Key::Right, "right"),
+    (Key::Up, "up"),
+    (Key::Down, "down"),
+    (Key::F11, "f11"),
+    (Key::F12, "f12"),
+    (Key::Space, "space"),
+    (Key::Enter, "enter"),
+    (Key::Escape, "escape"),
+    (Key::Backspace, "backspace"),
+];
+
+/// In alphabetical order, so `LETTERS[i]` is the key for `b'A' + i`.
+pub const LETTERS: [Key; 26] = [
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+];
+
+/// The number row, `DIGITS[i]` is the key for `i`.
+pub const DIGITS: [Key; 10] = [
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+];
+
+impl Key {
+    /// The key that types `c`, ignoring case. Carriage return and line feed are Enter.
+    pub fn from_char(c: char) -> Option<Key> {
+        match c {
+            'a'..='z' => Some(LETTERS[c as usize - 'a' as usize]),
+            'A'..='Z' => Some(LETTERS[c as usize - 'A' as usize]),
+            '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+            ' ' => Some(Key::Space),
+            '\r' | '\n' => Some(Key::Enter),
+            '\x1b' => Some(Key::Escape),
+            '\x08' | '\x7f' => Some(Key::Backspace),
+            _ => None,
+        }
+    }
+
+    /// The uppercase letter or digit on the key.
+    pub fn to_char(self) -> Option<char> {
+        if let Some(i) = LETTERS.iter().position(|&key| key == self) {
+            Some((b'A' + i as u8) as char)
+        } else {
+            self.digit().map(|digit| (b'0' + digit as u8) as char)
+        }
+    }
+
+    pub fn digit(self) -> Option<usize> {
+        DIGITS.iter().position(|&key| key == self)
+    }
+
+    /// Every key, each once.
+    pub fn all() -> impl Iterator<Item = Key> {
+        NAMED_KEYS
+            .iter()
+            .map(|&(key, _)| key)
+            .chain(LETTERS.iter().copied())
+            .chain(DIGITS.iter().copied())
+    }
+
+    /// Lowercase, e.g. `escape`, `q` or `7`.
+    pub fn name(self) -> String {
+        match NAMED_KEYS.iter().find(|&&(key, _)| key == self) {
+            Some((_, name)) => name.to_string(),
+            None => self
+                .to_char()
+                .map(|c| c.to_ascii_lowercase().to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Key> {
+        if let Some(&(key, _)) = NAMED_KEYS.iter().find(|&&(_, other)| other == name) {
+            return Some(key);
+        }
+
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphanumeric() => Key::from_char(c),
+            _ => None,
+        }
+    }
+}
+
 pub struct Keyboard {
     pub keys: [ButtonState; Key::Count as usize],
 }
 
+impl Default for Keyboard {
+    fn default() -> Self {
+        Keyboard::new()
+    }
+}
+
 impl Keyboard {
     pub fn new() -> Keyboard {
         Keyboard {
@@ -31,4 +201,54 @@ impl Keyboard {
         assert!((key as usize) < (Key::Count as usize));
         &mut self.keys[key as usize]
     }
+
+    /// The first letter or digit pressed this frame, as `Key::to_char` returns it.
+    pub fn typed(&self) -> Option<char> {
+        LETTERS
+            .iter()
+            .chain(DIGITS.iter())
+            .find(|&&key| self.key(key).pressed())
+            .and_then(|&key| key.to_char())
+    }
+
+    /// The first of 1 to 9 pressed this frame.
+    pub fn pressed_digit(&self) -> Option<usize> {
+        DIGITS[1..]
+            .iter()
+            .find(|&&key| self.key(key).pressed())
+            .and_then(|&key| key.digit())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chars() {
+        assert_eq!(Key::from_char('a'), Some(Key::A));
+        assert_eq!(Key::from_char('Z'), Some(Key::Z));
+        assert_eq!(Key::from_char('7'), Some(Key::Num7));
+        assert_eq!(Key::from_char('\r'), Some(Key::Enter));
+        assert_eq!(Key::from_char('!'), None);
+        assert_eq!(Key::Q.to_char(), Some('Q'));
+        assert_eq!(Key::Num0.to_char(), Some('0'));
+        assert_eq!(Key::Space.to_char(), None);
+        assert_eq!(Key::Num3.digit(), Some(3));
+
+        assert_eq!(Key::all().count(), Key::Count as usize);
+        for key in Key::all() {
+            assert_eq!(Key::from_name(&key.name()), Some(key));
+        }
+        assert_eq!(Key::from_name("Q"), Some(Key::Q));
+        assert_eq!(Key::from_name(" "), None);
+        assert_eq!(Key::from_name("tab"), None);
+
+        let mut keyboard = Keyboard::new();
+        assert_eq!(keyboard.typed(), None);
+        keyboard.key_mut(Key::Num4).is_down = true;
+        keyboard.key_mut(Key::K).is_down = true;
+        assert_eq!(keyboard.typed(), Some('K'));
+        assert_eq!(keyboard.pressed_digit(), Some(4));
+    }
 }
@@ -1,6 +1,9 @@
+pub mod action;
+pub mod gamepad;
 pub mod keyboard;
 pub mod mouse;
 
+use crate::input::gamepad::Gamepad;
 use crate::input::keyboard::Keyboard;
 use crate::input::mouse::Mouse;
 
@@ -10,6 +13,12 @@ pub struct ButtonState {
     pub was_down: bool,
 }
 
+impl Default for ButtonState {
+    fn default() -> Self {
+        ButtonState::new()
+    }
+}
+
 impl ButtonState {
     pub fn new() -> ButtonState {
         ButtonState {
@@ -39,6 +48,13 @@ impl ButtonState {
 pub struct Input {
     pub mouse: Mouse,
     pub keyboard: Keyboard,
+    pub gamepad: Gamepad,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input::new()
+    }
 }
 
 impl Input {
@@ -46,6 +62,16 @@ impl Input {
         Input {
             mouse: Mouse::new(),
             keyboard: Keyboard::new(),
+            gamepad: Gamepad::new(),
+        }
+    }
+
+    /// Remembers which buttons are down, call before applying a frame's events.
+    pub fn begin_frame(&mut self) {
+        let keys = self.keyboard.keys.iter_mut();
+        let buttons = self.mouse.buttons.iter_mut();
+        for state in keys.chain(buttons).chain(self.gamepad.buttons.iter_mut()) {
+            state.was_down = state.is_down;
         }
     }
 }
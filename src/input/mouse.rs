@@ -1,7 +1,7 @@
 use crate::input::ButtonState;
 use crate::math::Vec2;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(usize)]
 pub enum Button {
     Left,
@@ -10,11 +10,33 @@ pub enum Button {
     Count,
 }
 
+pub const MOUSE_BUTTONS: [(Button, &str); Button::Count as usize] =
+    [(Button::Left, "left"), (Button::Right, "right")];
+
+impl Button {
+    pub fn name(self) -> &'static str {
+        MOUSE_BUTTONS[self as usize].1
+    }
+
+    pub fn from_name(name: &str) -> Option<Button> {
+        MOUSE_BUTTONS
+            .iter()
+            .find(|(_, other)| *other == name)
+            .map(|&(button, _)| button)
+    }
+}
+
 pub struct Mouse {
     pub position: Vec2,
     pub buttons: [ButtonState; Button::Count as usize],
 }
 
+impl Default for Mouse {
+    fn default() -> Self {
+        Mouse::new()
+    }
+}
+
 impl Mouse {
     pub fn new() -> Mouse {
         Mouse {
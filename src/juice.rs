@@ -0,0 +1,307 @@
+use std::collections::VecDeque;
+
+use crate::camera::Camera;
+use crate::color::{BlendMode, Color};
+use crate::index_vec::GIndex;
+use crate::math::{Rng, Vec2};
+use crate::software_rendering::RenderBuffer;
+
+/// Things that happen in the game which the effects react to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum JuiceEvent {
+    BlockBroken,
+    Explosion,
+    PaddleHit,
+    LifeLost,
+}
+
+/// How hard an event kicks each effect.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Impact {
+    /// Added to the shake trauma, which is limited to 1.
+    pub trauma: f32,
+    /// Seconds the simulation is frozen for.
+    pub hit_stop: f32,
+    /// Added to the paddle's squash.
+    pub squash: f32,
+}
+
+impl Impact {
+    pub const fn new(trauma: f32, hit_stop: f32, squash: f32) -> Impact {
+        Impact {
+            trauma,
+            hit_stop,
+            squash,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct JuiceConfig {
+    pub block_break: Impact,
+    pub explosion: Impact,
+    pub paddle_hit: Impact,
+    pub life_lost: Impact,
+
+    /// Trauma lost per second.
+    pub trauma_decay: f32,
+    /// Camera offset, in world units, and rotation, in radians, at full trauma. The shake
+    /// grows with the square of the trauma.
+    pub max_shake_offset: f32,
+    pub max_shake_angle: f32,
+
+    /// Fraction of the squash lost per second.
+    pub squash_recovery: f32,
+    /// Paddle stretch per world unit per second of horizontal speed.
+    pub stretch_per_speed: f32,
+    pub max_stretch: f32,
+
+    /// Ball positions remembered for the trail, one per simulated frame. Zero turns trails off.
+    pub trail_length: usize,
+    pub trail_color: Color,
+}
+
+impl Default for JuiceConfig {
+    fn default() -> Self {
+        JuiceConfig {
+            block_break: Impact::new(0.25, 0.0, 0.0),
+            explosion: Impact::new(0.6, 0.08, 0.0),
+            paddle_hit: Impact::new(0.0, 0.0, 0.35),
+            life_lost: Impact::new(0.8, 0.15, 0.0),
+
+            trauma_decay: 1.5,
+            max_shake_offset: 2.0,
+            max_shake_angle: 0.03,
+
+            squash_recovery: 8.0,
+            stretch_per_speed: 0.002,
+            max_stretch: 0.3,
+
+            trail_length: 12,
+            trail_color: Color::rgba(255, 160, 60, 160),
+        }
+    }
+}
+
+struct Trail {
+    ball: GIndex,
+    radius: f32,
+    positions: VecDeque<Vec2>,
+    /// Cleared every update, trails of balls that weren't seen are dropped.
+    seen: bool,
+}
+
+/// Screen shake, hit-stop, paddle squash-and-stretch and ball trails, fed by `JuiceEvent`s.
+#[derive(Default)]
+pub struct Juice {
+    pub config: JuiceConfig,
+
+    trauma: f32,
+    hit_stop: f32,
+    squash: f32,
+    stretch: f32,
+
+    shake_offset: Vec2,
+    shake_angle: f32,
+
+    trails: Vec<Trail>,
+    rng: Rng,
+}
+
+impl Juice {
+    pub fn new() -> Juice {
+        Juice::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.trauma = 0.0;
+        self.hit_stop = 0.0;
+        self.squash = 0.0;
+        self.stretch = 0.0;
+        self.shake_offset = Vec2::zero();
+        self.shake_angle = 0.0;
+        self.trails.clear();
+    }
+
+    pub fn trigger(&mut self, event: JuiceEvent) {
+        let impact = match event {
+            JuiceEvent::BlockBroken => self.config.block_break,
+            JuiceEvent::Explosion => self.config.explosion,
+            JuiceEvent::PaddleHit => self.config.paddle_hit,
+            JuiceEvent::LifeLost => self.config.life_lost,
+        };
+
+        self.trauma = (self.trauma + impact.trauma).min(1.0);
+        // Hit-stops don't stack, a longer one replaces a shorter one
+        self.hit_stop = self.hit_stop.max(impact.hit_stop);
+        self.squash += impact.squash;
+    }
+
+    /// While frozen the world shouldn't be simulated, the effects keep running.
+    pub fn is_frozen(&self) -> bool {
+        self.hit_stop > 0.0
+    }
+
+    /// Advances the effects by `dt` of real time. `balls` are the live balls with their
+    /// positions and radii, the positions extend the trails unless the simulation was frozen
+    /// this frame.
+    pub fn update<I>(&mut self, dt: f32, paddle_velocity: Vec2, balls: I)
+    where
+        I: IntoIterator<Item = (GIndex, Vec2, f32)>,
+    {
+        let was_frozen = self.is_frozen();
+        self.hit_stop = (self.hit_stop - dt).max(0.0);
+
+        self.trauma = (self.trauma - self.config.trauma_decay * dt).max(0.0);
+        let shake = self.trauma * self.trauma;
+        self.shake_offset = Vec2::new(self.rng.range(-1.0, 1.0), self.rng.range(-1.0, 1.0))
+            * (shake * self.config.max_shake_offset);
+        self.shake_angle = self.rng.range(-1.0, 1.0) * shake * self.config.max_shake_angle;
+
+        self.squash *= (1.0 - self.config.squash_recovery * dt).max(0.0);
+        self.stretch =
+            (paddle_velocity.x.abs() * self.config.stretch_per_speed).min(self.config.max_stretch);
+
+        if was_frozen {
+            return;
+        }
+
+        for trail in self.trails.iter_mut() {
+            trail.seen = false;
+        }
+        for (ball, position, radius) in balls {
+            let index = match self.trails.iter().position(|trail| trail.ball == ball) {
+                Some(index) => index,
+                None => {
+                    self.trails.push(Trail {
+                        ball,
+                        radius,
+                        positions: VecDeque::new(),
+                        seen: false,
+                    });
+                    self.trails.len() - 1
+                }
+            };
+
+            let trail = &mut self.trails[index];
+            trail.seen = true;
+            trail.radius = radius;
+            trail.positions.push_front(position);
+            trail.positions.truncate(self.config.trail_length);
+        }
+        self.trails.retain(|trail| trail.seen);
+    }
+
+    /// `camera` moved and turned by the current shake.
+    pub fn shake(&self, camera: Camera) -> Camera {
+        Camera {
+            position: camera.position + self.shake_offset,
+            rotation: camera.rotation + self.shake_angle,
+            ..camera
+        }
+    }
+
+    /// Scale for the paddle's half size. Squash and stretch both widen it and keep its area.
+    pub fn paddle_scale(&self) -> Vec2 {
+        let amount = 1.0 + self.squash + self.stretch;
+        Vec2::new(amount, 1.0 / amount)
+    }
+
+    /// Trails fade and thin out towards their tail, the newest position is skipped since the
+    /// ball itself covers it.
+    pub fn draw_trails(&self, render_buffer: &mut RenderBuffer) {
+        let length = self.config.trail_length as f32;
+        for trail in self.trails.iter() {
+            for (i, &position) in trail.positions.iter().enumerate().skip(1) {
+                let t = 1.0 - i as f32 / length;
+                let color = self.config.trail_color;
+                let color = color.with_alpha(color.a as f32 / 255.0 * t);
+                render_buffer.draw_circle_blended(
+                    position,
+                    trail.radius * t,
+                    color,
+                    BlendMode::Alpha,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ball() -> GIndex {
+        GIndex::new(0, 0)
+    }
+
+    #[test]
+    fn test_trauma() {
+        let mut juice = Juice::new();
+        juice.trigger(JuiceEvent::LifeLost);
+        juice.trigger(JuiceEvent::LifeLost);
+        assert_eq!(juice.trauma, 1.0);
+
+        juice.update(0.1, Vec2::zero(), None);
+        assert_ne!(juice.shake(Camera::new()), Camera::new());
+
+        juice.update(1.0, Vec2::zero(), None);
+        assert_eq!(juice.trauma, 0.0);
+        assert_eq!(juice.shake(Camera::new()), Camera::new());
+    }
+
+    #[test]
+    fn test_hit_stop() {
+        let mut juice = Juice::new();
+        juice.config.explosion.hit_stop = 0.1;
+        juice.config.life_lost.hit_stop = 0.05;
+        juice.trigger(JuiceEvent::Explosion);
+        juice.trigger(JuiceEvent::LifeLost);
+        assert!(juice.is_frozen());
+
+        juice.update(0.06, Vec2::zero(), None);
+        assert!(juice.is_frozen());
+        juice.update(0.06, Vec2::zero(), None);
+        assert!(!juice.is_frozen());
+    }
+
+    #[test]
+    fn test_paddle_scale() {
+        let mut juice = Juice::new();
+        assert_eq!(juice.paddle_scale(), Vec2::new(1.0, 1.0));
+
+        juice.trigger(JuiceEvent::PaddleHit);
+        let scale = juice.paddle_scale();
+        assert!(scale.x > 1.0 && scale.y < 1.0);
+        assert!((scale.x * scale.y - 1.0).abs() < 1e-6);
+
+        juice.update(1.0, Vec2::new(1e6, 0.0), None);
+        assert_eq!(juice.paddle_scale().x, 1.0 + juice.config.max_stretch);
+    }
+
+    #[test]
+    fn test_trails() {
+        let mut juice = Juice::new();
+        juice.config.trail_length = 3;
+        for i in 0..5 {
+            juice.update(
+                0.1,
+                Vec2::zero(),
+                Some((ball(), Vec2::new(i as f32, 0.0), 1.0)),
+            );
+        }
+        let positions: Vec<_> = juice.trails[0].positions.iter().map(|p| p.x).collect();
+        assert_eq!(positions, vec![4.0, 3.0, 2.0]);
+
+        // Frozen frames don't add positions
+        juice.config.paddle_hit.hit_stop = 1.0;
+        juice.trigger(JuiceEvent::PaddleHit);
+        juice.update(0.1, Vec2::zero(), Some((ball(), Vec2::new(9.0, 0.0), 1.0)));
+        assert_eq!(juice.trails[0].positions[0].x, 4.0);
+
+        // Lost balls lose their trail
+        juice.update(1.0, Vec2::zero(), None);
+        juice.update(0.1, Vec2::zero(), None);
+        assert!(juice.trails.is_empty());
+    }
+}
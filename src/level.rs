@@ -0,0 +1,410 @@
+use crate::game::{Entity, PowerUp};
+use crate::index_vec::{GIndex, IndexVec};
+use crate::math::Vec2;
+
+/// Name of the built-in levels, used to keep high scores apart from other level packs.
+pub const LEVEL_PACK: &str = "classic";
+
+pub trait Level {
+    fn name(&self) -> &str;
+
+    fn load(&self, entities: &mut IndexVec<Entity>);
+}
+
+#[derive(Clone)]
+pub enum BlockKind {
+    Normal,
+    Indestructible,
+    Explosive { radius: f32 },
+}
+
+#[derive(Clone)]
+pub struct BlockDef {
+    pub kind: BlockKind,
+    pub position: Vec2,
+    pub half_size: Vec2,
+    pub life: i32,
+    pub path: Vec<Vec2>,
+    pub path_speed: f32,
+    pub power_ups: Vec<PowerUp>,
+}
+
+impl BlockDef {
+    pub fn new(kind: BlockKind, position: Vec2) -> BlockDef {
+        BlockDef {
+            kind,
+            position,
+            half_size: BLOCK_HALF_SIZE,
+            life: 1,
+            path: Vec::new(),
+            path_speed: 0.0,
+            power_ups: Vec::new(),
+        }
+    }
+
+    pub fn spawn(&self, entities: &mut IndexVec<Entity>) {
+        let mut block = Entity::new();
+        block.tags.insert("Block".to_string());
+        block.position = self.position;
+        block.half_size = self.half_size;
+        block.life = self.life;
+        block.spawn_power_ups = self.power_ups.clone();
+        block.points = block_points(&self.kind, self.life);
+
+        match self.kind {
+            BlockKind::Normal => {}
+            BlockKind::Indestructible => {
+                block.tags.insert("Indestructible".to_string());
+            }
+            BlockKind::Explosive { radius } => {
+                block.tags.insert("Explosive".to_string());
+                block.explosion_radius = radius;
+            }
+        }
+
+        if !self.path.is_empty() {
+            block.path.push(self.position);
+            block.path.extend(self.path.iter().cloned());
+            block.path_speed = self.path_speed;
+        }
+
+        block.color = Some(block_color(&block));
+
+        entities.insert(block);
+    }
+}
+
+pub const BLOCK_HALF_SIZE: Vec2 = Vec2 { x: 5.0, y: 2.0 };
+pub const BLOCK_SPACING: Vec2 = Vec2 { x: 12.0, y: 5.0 };
+
+/// Blast radius of `*` blocks unless the legend gives another one.
+pub const EXPLOSION_RADIUS: f32 = 14.0;
+
+/// Parses a block layout where every character is one cell, rows going from top to bottom.
+///
+/// `.` or ` ` is empty, `1`-`9` is a normal block with that much life, `#` is indestructible,
+/// `*` is explosive, `+` is a normal block that drops an invincible power up and `L` is a normal
+/// block that drops an extra life. The indentation the rows share is ignored, so they can line up
+/// with the code around the layout, and blank lines are skipped, so an empty row needs a `.`.
+///
+/// Lines of the form `a: <cell> [radius=R] [path=X,Y;X,Y...] [speed=S]` add `a` to the legend as
+/// that built-in cell with an explosion radius and a movement path. Path points are in cells
+/// relative to where the block starts, `X` going right and `Y` going down, and the block loops
+/// through them at `speed` units per second. Malformed legend entries are ignored.
+pub fn parse_layout(layout: &str, top_left: Vec2) -> Vec<BlockDef> {
+    let lines: Vec<&str> = layout
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let (legend, grid): (Vec<&str>, Vec<&str>) = lines
+        .into_iter()
+        .partition(|line| line.trim_start().chars().nth(1) == Some(':'));
+    let legend: Vec<LegendEntry> = legend
+        .iter()
+        .filter_map(|line| parse_legend(line.trim_start()))
+        .collect();
+
+    // Leading spaces past the shared indentation are empty cells
+    let indent = grid
+        .iter()
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let grid = grid.iter().map(|line| &line[indent..]);
+
+    let mut blocks = Vec::new();
+
+    for (row, line) in grid.enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let position = Vec2::new(
+                top_left.x + column as f32 * BLOCK_SPACING.x,
+                top_left.y - row as f32 * BLOCK_SPACING.y,
+            );
+
+            let block = match legend.iter().find(|entry| entry.name == c) {
+                Some(entry) => entry.block(position),
+                None => cell_block(c, position),
+            };
+
+            if let Some(block) = block {
+                blocks.push(block);
+            }
+        }
+    }
+
+    blocks
+}
+
+fn cell_block(c: char, position: Vec2) -> Option<BlockDef> {
+    let block = match c {
+        '1'..='9' => {
+            let mut block = BlockDef::new(BlockKind::Normal, position);
+            block.life = c.to_digit(10).unwrap() as i32;
+            block
+        }
+        '#' => BlockDef::new(BlockKind::Indestructible, position),
+        '*' => BlockDef::new(
+            BlockKind::Explosive {
+                radius: EXPLOSION_RADIUS,
+            },
+            position,
+        ),
+        '+' => {
+            let mut block = BlockDef::new(BlockKind::Normal, position);
+            block.power_ups.push(PowerUp::Invincible);
+            block
+        }
+        'L' => {
+            let mut block = BlockDef::new(BlockKind::Normal, position);
+            block.power_ups.push(PowerUp::ExtraLife);
+            block
+        }
+        _ => return None,
+    };
+    Some(block)
+}
+
+struct LegendEntry {
+    name: char,
+    cell: char,
+    radius: Option<f32>,
+    path: Vec<Vec2>,
+    speed: f32,
+}
+
+impl LegendEntry {
+    fn block(&self, position: Vec2) -> Option<BlockDef> {
+        let mut block = cell_block(self.cell, position)?;
+
+        if let (BlockKind::Explosive { radius }, Some(custom)) = (&mut block.kind, self.radius) {
+            *radius = custom;
+        }
+
+        block.path = self
+            .path
+            .iter()
+            .map(|cells| {
+                Vec2::new(
+                    position.x + cells.x * BLOCK_SPACING.x,
+                    position.y - cells.y * BLOCK_SPACING.y,
+                )
+            })
+            .collect();
+        block.path_speed = self.speed;
+
+        Some(block)
+    }
+}
+
+fn parse_legend(line: &str) -> Option<LegendEntry> {
+    let mut chars = line.chars();
+    let name = chars.next()?;
+    chars.next()?;
+
+    let mut fields = chars.as_str().split_whitespace();
+    let mut cell = fields.next()?.chars();
+    let mut entry = LegendEntry {
+        name,
+        cell: cell.next()?,
+        radius: None,
+        path: Vec::new(),
+        speed: 0.0,
+    };
+    if cell.next().is_some() {
+        return None;
+    }
+
+    for field in fields {
+        let (key, value) = field.split_once('=')?;
+        match key {
+            "radius" => entry.radius = Some(value.parse().ok()?),
+            "speed" => entry.speed = value.parse().ok()?,
+            "path" => {
+                for point in value.split(';') {
+                    let (x, y) = point.split_once(',')?;
+                    entry.path.push(Vec2::new(x.parse().ok()?, y.parse().ok()?));
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    Some(entry)
+}
+
+pub fn block_points(kind: &BlockKind, life: i32) -> u32 {
+    match kind {
+        BlockKind::Normal => 10 * life.max(1) as u32,
+        BlockKind::Indestructible => 0,
+        BlockKind::Explosive { .. } => 30,
+    }
+}
+
+pub fn block_color(block: &Entity) -> u32 {
+    if block.tags.contains("Indestructible") {
+        return 0x808080;
+    }
+
+    if block.tags.contains("Explosive") {
+        return 0xff6000;
+    }
+
+    match block.life {
+        i32::MIN..=1 => 0x4080ff,
+        2 => 0x40c040,
+        3 => 0xe0e040,
+        4 => 0xe08020,
+        _ => 0xe02020,
+    }
+}
+
+pub fn spawn_ball(entities: &mut IndexVec<Entity>, position: Vec2, velocity: Vec2) -> GIndex {
+    let mut ball = Entity::new();
+    ball.tags.insert("Ball".to_string());
+    ball.collide_with.insert("Wall".to_string());
+    ball.collide_with.insert("Block".to_string());
+    ball.half_size = Vec2::new(0.75, 0.75);
+    ball.position = position;
+    ball.velocity = velocity;
+    ball.color = Some(0x00ffff);
+    ball.life = 1;
+    entities.insert(ball)
+}
+
+pub struct Level0 {}
+
+static LEVEL0_LAYOUT: &str = "
+    11111111
+    11111111
+    11111111
+    11111111
+    11111111
+    11111111
+    11111111
+    ++++++++
+";
+
+impl Level for Level0 {
+    fn name(&self) -> &str {
+        "Warm Up"
+    }
+
+    fn load(&self, entities: &mut IndexVec<Entity>) {
+        spawn_ball(entities, Vec2::new(60.0, 0.0), Vec2::new(-30.0, -40.0));
+
+        for block in parse_layout(LEVEL0_LAYOUT, Vec2::new(-40.0, 35.0)) {
+            block.spawn(entities);
+        }
+    }
+}
+
+pub struct Level1 {}
+
+static LEVEL1_LAYOUT: &str = "
+    33333333
+    2#2222#2
+    22*22*22
+    11111111
+    1+1**1+1
+    111LL111
+    ........
+    ........
+    a.......
+    .......b
+
+    a: 2 path=8,0 speed=20
+    b: * radius=14 path=-8,0 speed=15
+";
+
+impl Level for Level1 {
+    fn name(&self) -> &str {
+        "Fortress"
+    }
+
+    fn load(&self, entities: &mut IndexVec<Entity>) {
+        spawn_ball(entities, Vec2::new(60.0, 0.0), Vec2::new(-30.0, -40.0));
+        spawn_ball(entities, Vec2::new(-60.0, 0.0), Vec2::new(30.0, -40.0));
+
+        for block in parse_layout(LEVEL1_LAYOUT, Vec2::new(-40.0, 35.0)) {
+            block.spawn(entities);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_layout() {
+        let blocks = parse_layout("\n  3.#\n  *+\n", Vec2::new(0.0, 0.0));
+        assert_eq!(blocks.len(), 4);
+
+        assert_eq!(blocks[0].life, 3);
+        assert_eq!(blocks[0].position, Vec2::new(0.0, 0.0));
+
+        assert!(matches!(blocks[1].kind, BlockKind::Indestructible));
+        assert_eq!(blocks[1].position, Vec2::new(2.0 * BLOCK_SPACING.x, 0.0));
+
+        assert!(matches!(blocks[2].kind, BlockKind::Explosive { .. }));
+        assert_eq!(blocks[2].position, Vec2::new(0.0, -BLOCK_SPACING.y));
+
+        assert_eq!(blocks[3].power_ups.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_layout_leading_empty_cells() {
+        let blocks = parse_layout("\n      .1\n       2\n        3\n", Vec2::new(0.0, 0.0));
+        let cells: Vec<_> = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.position.x / BLOCK_SPACING.x,
+                    -block.position.y / BLOCK_SPACING.y,
+                    block.life,
+                )
+            })
+            .collect();
+        assert_eq!(cells, vec![(1.0, 0.0, 1), (1.0, 1.0, 2), (2.0, 2.0, 3)]);
+    }
+
+    #[test]
+    fn test_parse_legend() {
+        let layout = "
+            a*b
+            a: * radius=20 path=2,0;2,1 speed=15
+            b: 3 path=-2,0
+            c: # speed=fast
+        ";
+        let blocks = parse_layout(layout, Vec2::new(0.0, 0.0));
+        assert_eq!(blocks.len(), 3);
+
+        match blocks[0].kind {
+            BlockKind::Explosive { radius } => assert_eq!(radius, 20.0),
+            _ => panic!("expected an explosive block"),
+        }
+        assert_eq!(
+            blocks[0].path,
+            vec![
+                Vec2::new(2.0 * BLOCK_SPACING.x, 0.0),
+                Vec2::new(2.0 * BLOCK_SPACING.x, -BLOCK_SPACING.y),
+            ]
+        );
+        assert_eq!(blocks[0].path_speed, 15.0);
+
+        match blocks[1].kind {
+            BlockKind::Explosive { radius } => assert_eq!(radius, EXPLOSION_RADIUS),
+            _ => panic!("expected an explosive block"),
+        }
+        assert!(blocks[1].path.is_empty());
+
+        assert_eq!(blocks[2].life, 3);
+        assert_eq!(blocks[2].path, vec![Vec2::new(0.0, 0.0)]);
+
+        // Malformed entries don't define anything
+        assert!(parse_legend("c: # speed=fast").is_none());
+        assert!(parse_legend("c: ## speed=1").is_none());
+    }
+}
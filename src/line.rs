@@ -110,6 +110,189 @@ pub fn line_iter(x0: i32, y0: i32, x1: i32, y1: i32) -> LineIter {
     LineIter::new(x0, y0, x1, y1)
 }
 
+/// Liang-Barsky clipping against the rectangle `min_x..=max_x`, `min_y..=max_y`. Returns the
+/// endpoints of the part of the line inside it, `None` if there is no such part.
+#[allow(clippy::too_many_arguments)]
+pub fn clip_line(
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    min_x: i32,
+    min_y: i32,
+    max_x: i32,
+    max_y: i32,
+) -> Option<(i32, i32, i32, i32)> {
+    let (x0, y0, x1, y1) = clip_line_f64(
+        [x0 as f64, y0 as f64, x1 as f64, y1 as f64],
+        [min_x as f64, min_y as f64, max_x as f64, max_y as f64],
+    )?;
+
+    Some((
+        x0.round() as i32,
+        y0.round() as i32,
+        x1.round() as i32,
+        y1.round() as i32,
+    ))
+}
+
+/// Same as `clip_line` for sub-pixel endpoints, the clipped line keeps the exact slope.
+#[allow(clippy::too_many_arguments)]
+pub fn clip_line_aa(
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+    min_x: f32,
+    min_y: f32,
+    max_x: f32,
+    max_y: f32,
+) -> Option<(f32, f32, f32, f32)> {
+    let (x0, y0, x1, y1) = clip_line_f64(
+        [x0 as f64, y0 as f64, x1 as f64, y1 as f64],
+        [min_x as f64, min_y as f64, max_x as f64, max_y as f64],
+    )?;
+
+    Some((x0 as f32, y0 as f32, x1 as f32, y1 as f32))
+}
+
+fn clip_line_f64(line: [f64; 4], rect: [f64; 4]) -> Option<(f64, f64, f64, f64)> {
+    let [x0, y0, x1, y1] = line;
+    let [min_x, min_y, max_x, max_y] = rect;
+    let dx = x1 - x0;
+    let dy = y1 - y0;
+
+    let mut t0 = 0.0;
+    let mut t1 = 1.0;
+
+    let edges = [
+        (-dx, x0 - min_x),
+        (dx, max_x - x0),
+        (-dy, y0 - min_y),
+        (dy, max_y - y0),
+    ];
+    for &(p, q) in edges.iter() {
+        if p == 0.0 {
+            // Parallel to this edge, and outside of it
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                if t > t1 {
+                    return None;
+                }
+                if t > t0 {
+                    t0 = t;
+                }
+            } else {
+                if t < t0 {
+                    return None;
+                }
+                if t < t1 {
+                    t1 = t;
+                }
+            }
+        }
+    }
+
+    Some((x0 + dx * t0, y0 + dy * t0, x0 + dx * t1, y0 + dy * t1))
+}
+
+// Xiaolin Wu's Line Algorithm, with pixel centers at integer coordinates. Every step along the
+// major axis covers the two pixels straddling the line, `aa` is the coverage of each.
+pub struct AaLineIter {
+    steep: bool,
+    major: i32,
+    end: i32,
+    inc: i32,
+    start_major: f32,
+    start_minor: f32,
+    gradient: f32,
+    pending: Option<Pixel>,
+}
+
+impl AaLineIter {
+    pub fn new(x0: f32, y0: f32, x1: f32, y1: f32) -> AaLineIter {
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        let (major0, minor0, major1, minor1) = if steep {
+            (y0, x0, y1, x1)
+        } else {
+            (x0, y0, x1, y1)
+        };
+
+        let d_major = major1 - major0;
+        let gradient = if d_major == 0.0 {
+            0.0
+        } else {
+            (minor1 - minor0) / d_major
+        };
+
+        let major = major0.round() as i32;
+        let end = major1.round() as i32;
+
+        AaLineIter {
+            steep,
+            major,
+            end,
+            inc: if end >= major { 1 } else { -1 },
+            start_major: major0,
+            start_minor: minor0,
+            gradient,
+            pending: None,
+        }
+    }
+
+    fn pixel(&self, major: i32, minor: i32, aa: f32) -> Pixel {
+        if self.steep {
+            Pixel {
+                x: minor,
+                y: major,
+                aa,
+            }
+        } else {
+            Pixel {
+                x: major,
+                y: minor,
+                aa,
+            }
+        }
+    }
+}
+
+impl Iterator for AaLineIter {
+    type Item = Pixel;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(pixel) = self.pending.take() {
+            return Some(pixel);
+        }
+
+        if (self.major - self.end) * self.inc > 0 {
+            return None;
+        }
+
+        let major = self.major;
+        self.major += self.inc;
+
+        let minor = self.start_minor + (major as f32 - self.start_major) * self.gradient;
+        let minor_floor = minor.floor();
+        let coverage = minor - minor_floor;
+
+        let near = self.pixel(major, minor_floor as i32, 1.0 - coverage);
+        if coverage > 0.0 {
+            self.pending = Some(self.pixel(major, minor_floor as i32 + 1, coverage));
+        }
+
+        Some(near)
+    }
+}
+
+pub fn aa_line_iter(x0: f32, y0: f32, x1: f32, y1: f32) -> AaLineIter {
+    AaLineIter::new(x0, y0, x1, y1)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -209,4 +392,383 @@ mod test {
         let points: Vec<_> = line_iter(4, -3, 0, 0).map(|p| [p.x, p.y]).collect();
         assert_eq!(points, vec![[4, -3], [3, -2], [2, -1], [1, -1], [0, 0]]);
     }
+
+    fn aa_points(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<(i32, i32, f32)> {
+        aa_line_iter(x0, y0, x1, y1)
+            .map(|p| (p.x, p.y, p.aa))
+            .collect()
+    }
+
+    #[test]
+    fn test_aa_point() {
+        assert_eq!(aa_points(2.0, 3.0, 2.0, 3.0), vec![(2, 3, 1.0)]);
+    }
+
+    #[test]
+    fn test_aa_axis_aligned() {
+        assert_eq!(
+            aa_points(0.0, 0.0, 3.0, 0.0),
+            vec![(0, 0, 1.0), (1, 0, 1.0), (2, 0, 1.0), (3, 0, 1.0)]
+        );
+        assert_eq!(
+            aa_points(0.0, 0.0, 0.0, -3.0),
+            vec![(0, 0, 1.0), (0, -1, 1.0), (0, -2, 1.0), (0, -3, 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_aa_diagonal() {
+        assert_eq!(
+            aa_points(0.0, 0.0, -3.0, 3.0),
+            vec![(0, 0, 1.0), (-1, 1, 1.0), (-2, 2, 1.0), (-3, 3, 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_aa_octant_1() {
+        assert_eq!(
+            aa_points(0.0, 0.0, 4.0, 2.0),
+            vec![
+                (0, 0, 1.0),
+                (1, 0, 0.5),
+                (1, 1, 0.5),
+                (2, 1, 1.0),
+                (3, 1, 0.5),
+                (3, 2, 0.5),
+                (4, 2, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_symmetry_1() {
+        assert_eq!(
+            aa_points(4.0, 2.0, 0.0, 0.0),
+            vec![
+                (4, 2, 1.0),
+                (3, 1, 0.5),
+                (3, 2, 0.5),
+                (2, 1, 1.0),
+                (1, 0, 0.5),
+                (1, 1, 0.5),
+                (0, 0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_octant_2() {
+        assert_eq!(
+            aa_points(0.0, 0.0, 1.0, 4.0),
+            vec![
+                (0, 0, 1.0),
+                (0, 1, 0.75),
+                (1, 1, 0.25),
+                (0, 2, 0.5),
+                (1, 2, 0.5),
+                (0, 3, 0.25),
+                (1, 3, 0.75),
+                (1, 4, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_symmetry_2() {
+        assert_eq!(
+            aa_points(1.0, 4.0, 0.0, 0.0),
+            vec![
+                (1, 4, 1.0),
+                (0, 3, 0.25),
+                (1, 3, 0.75),
+                (0, 2, 0.5),
+                (1, 2, 0.5),
+                (0, 1, 0.75),
+                (1, 1, 0.25),
+                (0, 0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_octant_3() {
+        assert_eq!(
+            aa_points(0.0, 0.0, -1.0, 4.0),
+            vec![
+                (0, 0, 1.0),
+                (-1, 1, 0.25),
+                (0, 1, 0.75),
+                (-1, 2, 0.5),
+                (0, 2, 0.5),
+                (-1, 3, 0.75),
+                (0, 3, 0.25),
+                (-1, 4, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_symmetry_3() {
+        assert_eq!(
+            aa_points(-1.0, 4.0, 0.0, 0.0),
+            vec![
+                (-1, 4, 1.0),
+                (-1, 3, 0.75),
+                (0, 3, 0.25),
+                (-1, 2, 0.5),
+                (0, 2, 0.5),
+                (-1, 1, 0.25),
+                (0, 1, 0.75),
+                (0, 0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_octant_4() {
+        assert_eq!(
+            aa_points(0.0, 0.0, -4.0, 2.0),
+            vec![
+                (0, 0, 1.0),
+                (-1, 0, 0.5),
+                (-1, 1, 0.5),
+                (-2, 1, 1.0),
+                (-3, 1, 0.5),
+                (-3, 2, 0.5),
+                (-4, 2, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_symmetry_4() {
+        assert_eq!(
+            aa_points(-4.0, 2.0, 0.0, 0.0),
+            vec![
+                (-4, 2, 1.0),
+                (-3, 1, 0.5),
+                (-3, 2, 0.5),
+                (-2, 1, 1.0),
+                (-1, 0, 0.5),
+                (-1, 1, 0.5),
+                (0, 0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_octant_5() {
+        assert_eq!(
+            aa_points(0.0, 0.0, -4.0, -2.0),
+            vec![
+                (0, 0, 1.0),
+                (-1, -1, 0.5),
+                (-1, 0, 0.5),
+                (-2, -1, 1.0),
+                (-3, -2, 0.5),
+                (-3, -1, 0.5),
+                (-4, -2, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_symmetry_5() {
+        assert_eq!(
+            aa_points(-4.0, -2.0, 0.0, 0.0),
+            vec![
+                (-4, -2, 1.0),
+                (-3, -2, 0.5),
+                (-3, -1, 0.5),
+                (-2, -1, 1.0),
+                (-1, -1, 0.5),
+                (-1, 0, 0.5),
+                (0, 0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_octant_6() {
+        assert_eq!(
+            aa_points(0.0, 0.0, -1.0, -4.0),
+            vec![
+                (0, 0, 1.0),
+                (-1, -1, 0.25),
+                (0, -1, 0.75),
+                (-1, -2, 0.5),
+                (0, -2, 0.5),
+                (-1, -3, 0.75),
+                (0, -3, 0.25),
+                (-1, -4, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_symmetry_6() {
+        assert_eq!(
+            aa_points(-1.0, -4.0, 0.0, 0.0),
+            vec![
+                (-1, -4, 1.0),
+                (-1, -3, 0.75),
+                (0, -3, 0.25),
+                (-1, -2, 0.5),
+                (0, -2, 0.5),
+                (-1, -1, 0.25),
+                (0, -1, 0.75),
+                (0, 0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_octant_7() {
+        assert_eq!(
+            aa_points(0.0, 0.0, 1.0, -4.0),
+            vec![
+                (0, 0, 1.0),
+                (0, -1, 0.75),
+                (1, -1, 0.25),
+                (0, -2, 0.5),
+                (1, -2, 0.5),
+                (0, -3, 0.25),
+                (1, -3, 0.75),
+                (1, -4, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_symmetry_7() {
+        assert_eq!(
+            aa_points(1.0, -4.0, 0.0, 0.0),
+            vec![
+                (1, -4, 1.0),
+                (0, -3, 0.25),
+                (1, -3, 0.75),
+                (0, -2, 0.5),
+                (1, -2, 0.5),
+                (0, -1, 0.75),
+                (1, -1, 0.25),
+                (0, 0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_octant_8() {
+        assert_eq!(
+            aa_points(0.0, 0.0, 4.0, -2.0),
+            vec![
+                (0, 0, 1.0),
+                (1, -1, 0.5),
+                (1, 0, 0.5),
+                (2, -1, 1.0),
+                (3, -2, 0.5),
+                (3, -1, 0.5),
+                (4, -2, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_symmetry_8() {
+        assert_eq!(
+            aa_points(4.0, -2.0, 0.0, 0.0),
+            vec![
+                (4, -2, 1.0),
+                (3, -2, 0.5),
+                (3, -1, 0.5),
+                (2, -1, 1.0),
+                (1, -1, 0.5),
+                (1, 0, 0.5),
+                (0, 0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aa_coverage_sums_to_one() {
+        for &(x1, y1) in [(7.0f32, 3.0f32), (-5.0, 9.0), (-8.0, -1.0), (2.5, -6.25)].iter() {
+            let mut coverage = std::collections::HashMap::new();
+            let steep = y1.abs() > x1.abs();
+            for p in aa_line_iter(0.0, 0.0, x1, y1) {
+                let major = if steep { p.y } else { p.x };
+                *coverage.entry(major).or_insert(0.0) += p.aa;
+            }
+
+            for (_, sum) in coverage {
+                assert!((sum - 1.0f32).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_clip_inside() {
+        assert_eq!(clip_line(1, 2, 8, 5, 0, 0, 9, 9), Some((1, 2, 8, 5)));
+        assert_eq!(clip_line(0, 0, 9, 0, 0, 0, 9, 9), Some((0, 0, 9, 0)));
+    }
+
+    #[test]
+    fn test_clip_outside() {
+        assert_eq!(clip_line(-5, 2, -1, 8, 0, 0, 9, 9), None);
+        assert_eq!(clip_line(2, 10, 8, 20, 0, 0, 9, 9), None);
+        assert_eq!(clip_line(-3, -1, 12, -1, 0, 0, 9, 9), None);
+        // Crosses the lines through the bottom left corner, but not the rectangle
+        assert_eq!(clip_line(-5, 3, 3, -5, 0, 0, 9, 9), None);
+    }
+
+    #[test]
+    fn test_clip_crossing() {
+        assert_eq!(clip_line(-5, -5, 15, 15, 0, 0, 9, 9), Some((0, 0, 9, 9)));
+        assert_eq!(clip_line(15, 15, -5, -5, 0, 0, 9, 9), Some((9, 9, 0, 0)));
+        assert_eq!(clip_line(-2, 4, 12, 11, 0, 0, 9, 9), Some((0, 5, 8, 9)));
+        assert_eq!(clip_line(4, 4, 4, 30, 0, 0, 9, 9), Some((4, 4, 4, 9)));
+        // Touches only the top right corner
+        assert_eq!(clip_line(5, 13, 13, 5, 0, 0, 9, 9), Some((9, 9, 9, 9)));
+    }
+
+    #[test]
+    fn test_clip_keeps_slope() {
+        let (x0, y0, x1, y1) = clip_line(-20, 2, 40, 8, 0, 0, 9, 9).unwrap();
+        assert_eq!((x0, y0, x1, y1), (0, 4, 9, 5));
+    }
+
+    #[test]
+    fn test_clip_degenerate() {
+        assert_eq!(clip_line(3, 3, 3, 3, 0, 0, 9, 9), Some((3, 3, 3, 3)));
+        assert_eq!(clip_line(-3, 3, -3, 3, 0, 0, 9, 9), None);
+        assert_eq!(clip_line(0, 0, 0, 0, 0, 0, 0, 0), Some((0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn test_clip_aa() {
+        assert_eq!(
+            clip_line_aa(-10.0, 1.0, 30.0, 11.0, -1.0, -1.0, 20.0, 20.0),
+            Some((-1.0, 3.25, 20.0, 8.5))
+        );
+        assert_eq!(
+            clip_line_aa(-10.0, 1.0, -3.0, 11.0, -1.0, -1.0, 20.0, 20.0),
+            None
+        );
+
+        // Clipping keeps the pixels inside, with the same coverage
+        let inside = |(x, y, _): &(i32, i32, f32)| (0..20).contains(x) && (0..20).contains(y);
+        let full: Vec<_> = aa_points(-30.0, -7.5, 45.0, 26.0)
+            .into_iter()
+            .filter(inside)
+            .collect();
+        let (x0, y0, x1, y1) =
+            clip_line_aa(-30.0, -7.5, 45.0, 26.0, -1.0, -1.0, 20.0, 20.0).unwrap();
+        let clipped: Vec<_> = aa_points(x0, y0, x1, y1)
+            .into_iter()
+            .filter(inside)
+            .collect();
+        assert_eq!(full.len(), clipped.len());
+        for (a, b) in full.iter().zip(clipped.iter()) {
+            assert_eq!((a.0, a.1), (b.0, b.1));
+            assert!((a.2 - b.2).abs() < 1e-4);
+        }
+    }
 }
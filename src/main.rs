@@ -1,13 +1,62 @@
+pub mod bitmap;
+pub mod camera;
+pub mod color;
+pub mod command_list;
+pub mod config;
+pub mod dirty_rects;
+pub mod export;
+pub mod font;
 pub mod game;
+pub mod headless;
+pub mod high_score;
+pub mod hud;
 pub mod index_vec;
 pub mod input;
+pub mod juice;
+pub mod level;
 pub mod line;
 pub mod math;
+pub mod menu;
+pub mod particles;
+pub mod score;
+pub mod simd;
 pub mod software_rendering;
 
+#[cfg(target_os = "linux")]
+mod fbdev;
+#[cfg(test)]
+mod golden;
+#[cfg(not(windows))]
+mod terminal;
 #[cfg(windows)]
 mod win32;
 
 fn main() {
-    win32::run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--headless") {
+        let result = headless::Options::parse(args)
+            .and_then(|options| headless::run(&options).map_err(|error| error.to_string()));
+        exit_on_error(result, headless::USAGE);
+    } else if args.iter().any(|arg| arg == "--fbdev") {
+        #[cfg(target_os = "linux")]
+        exit_on_error(
+            fbdev::Options::parse(args)
+                .and_then(|options| fbdev::run(&options).map_err(|error| error.to_string())),
+            fbdev::USAGE,
+        );
+        #[cfg(not(target_os = "linux"))]
+        exit_on_error(Err("--fbdev is only supported on Linux".to_string()), "");
+    } else {
+        #[cfg(windows)]
+        win32::run();
+        #[cfg(not(windows))]
+        terminal::run();
+    }
+}
+
+fn exit_on_error(result: Result<(), String>, usage: &str) {
+    if let Err(error) = result {
+        eprintln!("{}\n{}", error, usage);
+        std::process::exit(1);
+    }
 }
@@ -46,6 +46,7 @@ impl Line2 {
 }
 
 /// See https://en.wikipedia.org/wiki/Line%E2%80%93line_intersection#Given_two_points_on_each_line
+#[allow(clippy::too_many_arguments)]
 pub fn line_intersection(
     x1: f32,
     y1: f32,
@@ -141,7 +142,7 @@ pub fn swept_aabb2(
                 edge.end.x,
                 edge.end.y,
             ) {
-                if t >= 0.0 && t <= 1.0 && u >= 0.0 && u <= 1.0 {
+                if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
                     return Some((edge, t));
                 }
             }
@@ -201,6 +202,12 @@ impl Vec2 {
     pub fn reflect(&self, normal: &Vec2) -> Vec2 {
         self - 2.0 * normal * (normal * self)
     }
+
+    /// Rotated counter-clockwise by `angle` radians.
+    pub fn rotated(&self, angle: f32) -> Vec2 {
+        let (sin, cos) = angle.sin_cos();
+        Vec2::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
 }
 
 impl Default for Vec2 {
@@ -334,6 +341,43 @@ pub fn aabb_vs_aabb(p1: Vec2, half_size1: Vec2, p2: Vec2, half_size2: Vec2) -> b
     )
 }
 
+/// Xorshift generator, good enough for effects.
+#[derive(Copy, Clone, Debug)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(0x2545_f491)
+    }
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Rng {
+        // Zero is the one state xorshift can't leave
+        Rng { state: seed.max(1) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    /// In `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
 #[inline(always)]
 fn intersect(left1: f32, right1: f32, left2: f32, right2: f32) -> bool {
     !(left2 > right1 || right2 < left1)
@@ -380,4 +424,15 @@ mod test {
         let normal = Vec2::new(-1.0, 0.0);
         assert_eq!(Vec2::new(1.0, 0.0).reflect(&normal), Vec2::new(-1.0, 0.0));
     }
+
+    #[test]
+    fn test_rng() {
+        let mut rng = Rng::new(0);
+        let mut other = Rng::new(0);
+        for _ in 0..1000 {
+            let value = rng.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&value));
+            assert_eq!(value, other.range(-2.0, 3.0));
+        }
+    }
 }
@@ -0,0 +1,267 @@
+use crate::color::{BlendMode, Color};
+use crate::font::{text_height, TextAlign, GLYPH_HEIGHT};
+use crate::input::action::Action;
+use crate::input::gamepad::GamepadButton;
+use crate::input::keyboard::Key;
+use crate::input::mouse::Button;
+use crate::input::Input;
+use crate::math::{aabb_vs_aabb, Vec2};
+use crate::software_rendering::RenderBuffer;
+
+const TITLE_Y: f32 = 25.0;
+const TITLE_SIZE: f32 = 6.0;
+const TEXT_SIZE: f32 = 3.0;
+const BODY_SIZE: f32 = 2.0;
+const ITEM_HALF_SIZE: Vec2 = Vec2 { x: 30.0, y: 2.5 };
+const ITEM_SPACING: f32 = 6.5;
+/// Lowest center an item can have, longer menus pack their items closer to stay above it.
+const ITEMS_BOTTOM: f32 = -42.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Screen {
+    Title,
+    LevelSelect,
+    Options,
+    Controls,
+    HighScores,
+    Pause,
+    GameOver,
+    GameWon,
+}
+
+impl Screen {
+    /// Root screens can't be closed by going back, an item has to be picked.
+    pub fn is_root(self) -> bool {
+        matches!(self, Screen::Title | Screen::GameOver | Screen::GameWon)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuAction {
+    Open(Screen),
+    Back,
+    NewGame,
+    StartLevel(usize),
+    Resume,
+    RestartLevel,
+    Continue,
+    MainMenu,
+    ToggleDebug,
+    CycleStartLives,
+    /// Binds the next key or button pressed to the action, replacing its bindings.
+    Rebind(Action),
+    ResetBindings,
+}
+
+pub struct MenuItem {
+    pub label: String,
+    pub action: MenuAction,
+}
+
+impl MenuItem {
+    pub fn new(label: &str, action: MenuAction) -> MenuItem {
+        MenuItem {
+            label: label.to_string(),
+            action,
+        }
+    }
+}
+
+/// What a screen shows, built by the game every frame so labels can reflect its state.
+pub struct Page {
+    pub title: String,
+    pub body: Option<String>,
+    pub items: Vec<MenuItem>,
+}
+
+#[derive(Default)]
+pub struct Menu {
+    stack: Vec<(Screen, usize)>,
+    last_mouse_p: Vec2,
+}
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu::default()
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.stack.is_empty()
+    }
+
+    pub fn screen(&self) -> Option<Screen> {
+        self.stack.last().map(|(screen, _)| *screen)
+    }
+
+    /// The selected item on the current screen.
+    pub fn selected(&self) -> Option<usize> {
+        self.stack.last().map(|(_, selected)| *selected)
+    }
+
+    pub fn push(&mut self, screen: Screen) {
+        self.stack.push((screen, 0));
+    }
+
+    pub fn pop(&mut self) {
+        self.stack.pop();
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+
+    /// Moves the selection with up/down or by hovering, and returns the action of the item
+    /// picked with right, enter, space, its number, a click or gamepad A this frame. Left,
+    /// escape, backspace and gamepad B go back.
+    pub fn update(
+        &mut self,
+        render_buffer: &RenderBuffer,
+        input: &Input,
+        page: &Page,
+    ) -> Option<MenuAction> {
+        let item_count = page.items.len();
+        let (_, selected) = self.stack.last_mut()?;
+        if item_count == 0 {
+            return None;
+        }
+
+        let keyboard = &input.keyboard;
+        let gamepad = &input.gamepad;
+        if keyboard.key(Key::Up).pressed() || gamepad.button(GamepadButton::DPadUp).pressed() {
+            *selected = (*selected + item_count - 1) % item_count;
+        } else if keyboard.key(Key::Down).pressed()
+            || gamepad.button(GamepadButton::DPadDown).pressed()
+        {
+            *selected = (*selected + 1) % item_count;
+        }
+        *selected = (*selected).min(item_count - 1);
+
+        let mouse = &input.mouse;
+        let mouse_p = render_buffer.pixels_to_world(mouse.position);
+        let hovered = (0..item_count)
+            .find(|&i| aabb_vs_aabb(mouse_p, Vec2::zero(), item_center(page, i), ITEM_HALF_SIZE));
+        if let Some(hovered) = hovered {
+            // Only follow the mouse when it moves, so it doesn't fight the keyboard
+            if mouse_p != self.last_mouse_p || mouse.button(Button::Left).pressed() {
+                *selected = hovered;
+            }
+        }
+        self.last_mouse_p = mouse_p;
+
+        if let Some(digit) = keyboard
+            .pressed_digit()
+            .filter(|&digit| digit <= item_count)
+        {
+            *selected = digit - 1;
+            return Some(page.items[*selected].action);
+        }
+
+        let clicked = hovered == Some(*selected) && mouse.button(Button::Left).released();
+        let confirmed = [Key::Right, Key::Enter, Key::Space]
+            .iter()
+            .any(|&key| keyboard.key(key).pressed());
+        if confirmed || clicked || gamepad.button(GamepadButton::A).pressed() {
+            return Some(page.items[*selected].action);
+        }
+
+        let back = [Key::Left, Key::Escape, Key::Backspace]
+            .iter()
+            .any(|&key| keyboard.key(key).pressed());
+        if back || gamepad.button(GamepadButton::B).pressed() {
+            return Some(MenuAction::Back);
+        }
+
+        None
+    }
+
+    pub fn draw(&self, render_buffer: &mut RenderBuffer, page: &Page) {
+        let selected = match self.stack.last() {
+            Some((_, selected)) => *selected,
+            None => return,
+        };
+
+        // Dim whatever is behind the menu
+        render_buffer.draw_rect_blended_in_pixels(
+            0,
+            0,
+            render_buffer.width,
+            render_buffer.height,
+            Color::BLACK.with_alpha(0.5),
+            BlendMode::Alpha,
+        );
+
+        let panel_top = TITLE_Y + TITLE_SIZE + 3.0;
+        let panel_bottom = page
+            .items
+            .len()
+            .checked_sub(1)
+            .map(|last| item_center(page, last).y - ITEM_HALF_SIZE.y - 3.0)
+            .unwrap_or(TITLE_Y - 3.0);
+        let panel_center = Vec2::new(0.0, (panel_top + panel_bottom) * 0.5);
+        let panel_half_size = Vec2::new(ITEM_HALF_SIZE.x + 6.0, (panel_top - panel_bottom) * 0.5);
+        render_buffer.draw_rect(
+            panel_center,
+            panel_half_size + Vec2::new(0.5, 0.5),
+            0x808080,
+        );
+        render_buffer.draw_rect(panel_center, panel_half_size, 0x101018);
+
+        render_buffer.draw_text(
+            Vec2::new(0.0, TITLE_Y),
+            TITLE_SIZE,
+            &page.title,
+            0xffffff,
+            TextAlign::Center,
+        );
+
+        if let Some(body) = &page.body {
+            render_buffer.draw_text(
+                Vec2::new(0.0, body_bottom(page)),
+                BODY_SIZE,
+                body,
+                0xc0c0c0,
+                TextAlign::Center,
+            );
+        }
+
+        for (i, item) in page.items.iter().enumerate() {
+            let center = item_center(page, i);
+            let text_color = if i == selected {
+                render_buffer.draw_rect(center, ITEM_HALF_SIZE, 0xc0c0c0);
+                0x000000
+            } else {
+                0xffffff
+            };
+
+            render_buffer.draw_text(
+                Vec2::new(center.x, center.y - TEXT_SIZE * 0.5),
+                TEXT_SIZE,
+                &item.label,
+                text_color,
+                TextAlign::Center,
+            );
+        }
+    }
+}
+
+fn body_bottom(page: &Page) -> f32 {
+    let body_height = page
+        .body
+        .as_ref()
+        .map(|body| text_height(body) as f32 * BODY_SIZE / GLYPH_HEIGHT as f32)
+        .unwrap_or(0.0);
+    TITLE_Y - 4.0 - body_height
+}
+
+fn item_center(page: &Page, i: usize) -> Vec2 {
+    let top = if page.body.is_some() {
+        body_bottom(page) - ITEM_SPACING * 0.5
+    } else {
+        TITLE_Y - ITEM_SPACING
+    };
+    let spacing = match page.items.len() {
+        0 | 1 => ITEM_SPACING,
+        count => ITEM_SPACING.min((top - ITEMS_BOTTOM) / (count - 1) as f32),
+    };
+    Vec2::new(0.0, top - spacing * i as f32)
+}
@@ -0,0 +1,301 @@
+use std::f32::consts::TAU;
+
+use crate::color::{BlendMode, Color};
+use crate::math::{Rng, Vec2};
+use crate::software_rendering::RenderBuffer;
+
+/// Live particles are capped, new ones are dropped once the pool is full.
+pub const MAX_PARTICLES: usize = 4096;
+
+/// Describes what an emitter spawns. Ranges are `(min, max)` and picked per particle.
+#[derive(Copy, Clone, Debug)]
+pub struct EmitterConfig {
+    /// Particles spawned at once when the emitter starts.
+    pub burst: u32,
+    /// Particles per second while the emitter is alive.
+    pub rate: f32,
+    /// How long the emitter keeps spawning, in seconds.
+    pub duration: f32,
+
+    pub lifetime: (f32, f32),
+    pub speed: (f32, f32),
+    /// Radians, counter-clockwise from +x.
+    pub direction: f32,
+    /// Radians, the full width of the cone velocities are picked from. `TAU` goes all around.
+    pub spread: f32,
+    pub gravity: Vec2,
+    /// Fraction of velocity lost per second.
+    pub drag: f32,
+
+    /// Half size at birth and death, interpolated in between.
+    pub size: (f32, f32),
+    pub color: (Color, Color),
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        EmitterConfig {
+            burst: 0,
+            rate: 0.0,
+            duration: 0.0,
+            lifetime: (1.0, 1.0),
+            speed: (0.0, 0.0),
+            direction: 0.0,
+            spread: TAU,
+            gravity: Vec2::zero(),
+            drag: 0.0,
+            size: (0.5, 0.5),
+            color: (Color::WHITE, Color::WHITE.with_alpha(0.0)),
+        }
+    }
+}
+
+impl EmitterConfig {
+    /// Chunks flying out of a destroyed block.
+    pub fn block_break(color: Color) -> EmitterConfig {
+        EmitterConfig {
+            burst: 24,
+            lifetime: (0.4, 0.9),
+            speed: (10.0, 35.0),
+            gravity: Vec2::new(0.0, -60.0),
+            drag: 1.0,
+            size: (0.6, 0.1),
+            color: (color, color.lerp(Color::BLACK, 0.5).with_alpha(0.0)),
+            ..EmitterConfig::default()
+        }
+    }
+
+    /// Sparks thrown up where the ball meets the paddle.
+    pub fn paddle_hit() -> EmitterConfig {
+        EmitterConfig {
+            burst: 10,
+            lifetime: (0.15, 0.35),
+            speed: (15.0, 40.0),
+            direction: TAU * 0.25,
+            spread: TAU * 0.4,
+            drag: 3.0,
+            size: (0.3, 0.1),
+            color: (
+                Color::rgb(255, 255, 160),
+                Color::rgb(255, 80, 0).with_alpha(0.0),
+            ),
+            ..EmitterConfig::default()
+        }
+    }
+
+    /// A short fountain around the paddle when a power-up is caught.
+    pub fn power_up_pickup(color: Color) -> EmitterConfig {
+        EmitterConfig {
+            burst: 12,
+            rate: 60.0,
+            duration: 0.3,
+            lifetime: (0.3, 0.6),
+            speed: (8.0, 20.0),
+            direction: TAU * 0.25,
+            spread: TAU * 0.5,
+            gravity: Vec2::new(0.0, -20.0),
+            size: (0.4, 0.2),
+            color: (Color::WHITE, color.with_alpha(0.0)),
+            ..EmitterConfig::default()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Particle {
+    position: Vec2,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    gravity: Vec2,
+    drag: f32,
+    size: (f32, f32),
+    color: (Color, Color),
+}
+
+struct Emitter {
+    config: EmitterConfig,
+    position: Vec2,
+    time_left: f32,
+    /// Fractional particles owed from previous frames.
+    pending: f32,
+}
+
+/// Particles live in a dense pool, dead ones are swapped out.
+#[derive(Default)]
+pub struct Particles {
+    particles: Vec<Particle>,
+    emitters: Vec<Emitter>,
+    rng: Rng,
+}
+
+impl Particles {
+    pub fn new() -> Particles {
+        Particles::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.emitters.clear();
+    }
+
+    /// Spawns the config's burst now and keeps emitting at its rate for its duration.
+    pub fn emit(&mut self, config: &EmitterConfig, position: Vec2) {
+        for _ in 0..config.burst {
+            self.spawn(config, position);
+        }
+
+        if config.rate > 0.0 && config.duration > 0.0 {
+            self.emitters.push(Emitter {
+                config: *config,
+                position,
+                time_left: config.duration,
+                pending: 0.0,
+            });
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let mut emitters = std::mem::take(&mut self.emitters);
+        for emitter in emitters.iter_mut() {
+            let active_time = dt.min(emitter.time_left);
+            emitter.time_left -= dt;
+            emitter.pending += emitter.config.rate * active_time;
+            while emitter.pending >= 1.0 {
+                emitter.pending -= 1.0;
+                self.spawn(&emitter.config, emitter.position);
+            }
+        }
+        emitters.retain(|emitter| emitter.time_left > 0.0);
+        self.emitters = emitters;
+
+        let mut i = 0;
+        while i < self.particles.len() {
+            let particle = &mut self.particles[i];
+            particle.age += dt;
+            if particle.age >= particle.lifetime {
+                self.particles.swap_remove(i);
+                continue;
+            }
+
+            particle.velocity = particle.velocity + particle.gravity * dt;
+            particle.velocity = particle.velocity * (1.0 - particle.drag * dt).max(0.0);
+            particle.position = particle.position + particle.velocity * dt;
+            i += 1;
+        }
+    }
+
+    /// Draws additively, so overlapping particles glow.
+    pub fn draw(&self, render_buffer: &mut RenderBuffer) {
+        for particle in self.particles.iter() {
+            let t = particle.age / particle.lifetime;
+            let size = particle.size.0 + (particle.size.1 - particle.size.0) * t;
+            let color = particle.color.0.lerp(particle.color.1, t);
+            render_buffer.draw_rect_blended(
+                particle.position,
+                Vec2::new(size, size),
+                color,
+                BlendMode::Additive,
+            );
+        }
+    }
+
+    fn spawn(&mut self, config: &EmitterConfig, position: Vec2) {
+        if self.particles.len() >= MAX_PARTICLES {
+            return;
+        }
+
+        let angle = config.direction + self.rng.range(-0.5, 0.5) * config.spread;
+        let speed = self.rng.range(config.speed.0, config.speed.1);
+        let lifetime = self.rng.range(config.lifetime.0, config.lifetime.1);
+
+        self.particles.push(Particle {
+            position,
+            velocity: Vec2::new(angle.cos(), angle.sin()) * speed,
+            age: 0.0,
+            lifetime: lifetime.max(f32::EPSILON),
+            gravity: config.gravity,
+            drag: config.drag,
+            size: config.size,
+            color: config.color,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_burst_and_expire() {
+        let mut particles = Particles::new();
+        let config = EmitterConfig {
+            burst: 10,
+            lifetime: (0.5, 1.0),
+            ..EmitterConfig::default()
+        };
+        particles.emit(&config, Vec2::zero());
+        assert_eq!(particles.len(), 10);
+
+        particles.update(0.4);
+        assert_eq!(particles.len(), 10);
+        particles.update(0.7);
+        assert!(particles.is_empty());
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut particles = Particles::new();
+        let config = EmitterConfig {
+            rate: 10.0,
+            duration: 1.0,
+            lifetime: (5.0, 5.0),
+            ..EmitterConfig::default()
+        };
+        particles.emit(&config, Vec2::zero());
+        assert!(particles.is_empty());
+
+        for _ in 0..20 {
+            particles.update(0.1);
+        }
+        // Only spawns while the emitter is alive, within a particle of rounding
+        assert!((9..=10).contains(&particles.len()));
+    }
+
+    #[test]
+    fn test_motion() {
+        let mut particles = Particles::new();
+        let config = EmitterConfig {
+            burst: 1,
+            speed: (10.0, 10.0),
+            spread: 0.0,
+            gravity: Vec2::new(0.0, -10.0),
+            ..EmitterConfig::default()
+        };
+        particles.emit(&config, Vec2::zero());
+        particles.update(0.5);
+
+        let particle = &particles.particles[0];
+        assert_eq!(particle.velocity, Vec2::new(10.0, -5.0));
+        assert_eq!(particle.position, Vec2::new(5.0, -2.5));
+    }
+
+    #[test]
+    fn test_pool_limit() {
+        let mut particles = Particles::new();
+        let config = EmitterConfig {
+            burst: MAX_PARTICLES as u32 + 100,
+            ..EmitterConfig::default()
+        };
+        particles.emit(&config, Vec2::zero());
+        assert_eq!(particles.len(), MAX_PARTICLES);
+    }
+}
@@ -0,0 +1,178 @@
+pub const POWER_UP_POINTS: u32 = 50;
+
+const COMBO_STEP: u32 = 4;
+const MAX_MULTIPLIER: u32 = 8;
+
+const PAR_TIME: f32 = 90.0;
+const TIME_BONUS_PER_SECOND: f32 = 10.0;
+
+#[derive(Clone, Default)]
+pub struct LevelScore {
+    pub level: usize,
+    pub time: f32,
+    pub blocks: u32,
+    pub power_ups: u32,
+    pub time_bonus: u32,
+    pub best_combo: u32,
+}
+
+impl LevelScore {
+    pub fn total(&self) -> u32 {
+        self.blocks + self.power_ups + self.time_bonus
+    }
+}
+
+#[derive(Default)]
+pub struct Score {
+    total: u32,
+    combo: u32,
+    current: LevelScore,
+    levels: Vec<LevelScore>,
+}
+
+impl Score {
+    pub fn new() -> Score {
+        Score::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Score::default();
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    /// Every `COMBO_STEP` hits without touching the paddle raise the multiplier by one.
+    pub fn multiplier(&self) -> u32 {
+        (1 + self.combo / COMBO_STEP).min(MAX_MULTIPLIER)
+    }
+
+    pub fn current_level(&self) -> &LevelScore {
+        &self.current
+    }
+
+    /// Breakdowns of the levels cleared in this game, in order.
+    pub fn levels(&self) -> &[LevelScore] {
+        &self.levels
+    }
+
+    pub fn begin_level(&mut self, level: usize) {
+        self.total -= self.current.total();
+        self.current = LevelScore {
+            level,
+            ..LevelScore::default()
+        };
+        self.combo = 0;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.current.time += dt;
+    }
+
+    pub fn block_hit(&mut self) {
+        self.combo += 1;
+        self.current.best_combo = self.current.best_combo.max(self.combo);
+    }
+
+    pub fn paddle_hit(&mut self) {
+        self.combo = 0;
+    }
+
+    pub fn ball_lost(&mut self) {
+        self.combo = 0;
+    }
+
+    /// Returns the points awarded after applying the combo multiplier.
+    pub fn block_destroyed(&mut self, points: u32) -> u32 {
+        let points = points * self.multiplier();
+        self.current.blocks += points;
+        self.total += points;
+        points
+    }
+
+    pub fn power_up_picked(&mut self) {
+        self.current.power_ups += POWER_UP_POINTS;
+        self.total += POWER_UP_POINTS;
+    }
+
+    pub fn level_cleared(&mut self) {
+        let time_bonus = ((PAR_TIME - self.current.time).max(0.0) * TIME_BONUS_PER_SECOND) as u32;
+        self.current.time_bonus = time_bonus;
+        self.total += time_bonus;
+
+        self.levels.push(self.current.clone());
+        self.current = LevelScore {
+            level: self.current.level,
+            ..LevelScore::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_combo_multiplier() {
+        let mut score = Score::new();
+        score.begin_level(0);
+        assert_eq!(score.block_destroyed(10), 10);
+
+        for _ in 0..COMBO_STEP {
+            score.block_hit();
+        }
+        assert_eq!(score.multiplier(), 2);
+        assert_eq!(score.block_destroyed(10), 20);
+
+        score.paddle_hit();
+        assert_eq!(score.multiplier(), 1);
+
+        for _ in 0..COMBO_STEP * 100 {
+            score.block_hit();
+        }
+        assert_eq!(score.multiplier(), MAX_MULTIPLIER);
+        assert_eq!(score.block_destroyed(10), 10 * MAX_MULTIPLIER);
+        assert_eq!(score.total(), 30 + 10 * MAX_MULTIPLIER);
+    }
+
+    #[test]
+    fn test_level_breakdown() {
+        let mut score = Score::new();
+        score.begin_level(0);
+        score.block_destroyed(10);
+        score.power_up_picked();
+        score.update(PAR_TIME - 1.0);
+        score.level_cleared();
+
+        score.begin_level(1);
+        score.block_destroyed(30);
+
+        let levels = score.levels();
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels[0].blocks, 10);
+        assert_eq!(levels[0].power_ups, POWER_UP_POINTS);
+        assert_eq!(levels[0].time_bonus, TIME_BONUS_PER_SECOND as u32);
+        assert_eq!(score.current_level().blocks, 30);
+        assert_eq!(score.total(), levels[0].total() + 30);
+    }
+
+    #[test]
+    fn test_restart_level_discards_its_points() {
+        let mut score = Score::new();
+        score.begin_level(0);
+        score.block_destroyed(10);
+        score.level_cleared();
+
+        score.begin_level(1);
+        score.block_destroyed(20);
+        score.begin_level(1);
+
+        assert_eq!(score.total(), 10 + score.levels()[0].time_bonus);
+        assert_eq!(score.current_level().blocks, 0);
+    }
+}
@@ -0,0 +1,462 @@
+// Row span kernels for the software renderer, with AVX2 and SSE2 paths picked at runtime and a
+// scalar fallback. Every path gives exactly the same result as `Color::blend`.
+//
+// Blends work on 16 bit lanes, one per channel, as `mul(dst, f) + mul(mul(dst, m), a) + k`
+// where `mul` is the rounded `x * y / 255` used by `Color`:
+//
+//   Alpha:    f = 255 - a, m = 0,   k = mul(src, a)
+//   Additive: f = 255,     m = 0,   k = mul(src, a), saturated by packing back to bytes
+//   Multiply: f = 255 - a, m = src, k = 0
+//
+// Benchmarks are ignored tests: `cargo test --release bench_ -- --ignored --nocapture`.
+
+use crate::color::{BlendMode, Color};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Isa {
+    Scalar,
+    Sse2,
+    Avx2,
+}
+
+impl Isa {
+    /// The best instruction set the CPU supports.
+    pub fn detect() -> Isa {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Isa::Avx2;
+            }
+            if is_x86_feature_detected!("sse2") {
+                return Isa::Sse2;
+            }
+        }
+        Isa::Scalar
+    }
+}
+
+/// Sets every pixel of `span` to `color`.
+pub fn fill(span: &mut [u32], color: u32) {
+    fill_with(Isa::detect(), span, color);
+}
+
+/// Blends `color` over every pixel of `span`.
+pub fn blend(span: &mut [u32], color: Color, mode: BlendMode) {
+    blend_with(Isa::detect(), span, color, mode);
+}
+
+/// Alpha blends `0xAARRGGBB` pixels from `src` over `dst`, pixel by pixel.
+pub fn blend_argb(dst: &mut [u32], src: &[u32]) {
+    blend_argb_with(Isa::detect(), dst, src);
+}
+
+/// Factors for the blend formula above, per channel in memory order (b, g, r, unused).
+#[derive(Copy, Clone)]
+struct BlendFactors {
+    f: u16,
+    m: [u16; 4],
+    k: [u16; 4],
+    a: u16,
+}
+
+impl BlendFactors {
+    fn new(color: Color, mode: BlendMode) -> BlendFactors {
+        let a = color.a as u16;
+        let src = [color.b as u16, color.g as u16, color.r as u16, 0];
+        let scaled = [mul(src[0], a), mul(src[1], a), mul(src[2], a), 0];
+        match mode {
+            BlendMode::Alpha => BlendFactors {
+                f: 255 - a,
+                m: [0; 4],
+                k: scaled,
+                a,
+            },
+            BlendMode::Additive => BlendFactors {
+                f: 255,
+                m: [0; 4],
+                k: scaled,
+                a,
+            },
+            BlendMode::Multiply => BlendFactors {
+                f: 255 - a,
+                m: src,
+                k: [0; 4],
+                a,
+            },
+        }
+    }
+}
+
+/// `x * y / 255`, rounded, for `x, y <= 255`.
+#[inline(always)]
+fn mul(x: u16, y: u16) -> u16 {
+    let t = x * y + 128;
+    (t + (t >> 8)) >> 8
+}
+
+fn fill_with(isa: Isa, span: &mut [u32], color: u32) {
+    match isa {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Avx2 => unsafe { x86::fill_avx2(span, color) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Sse2 => unsafe { x86::fill_sse2(span, color) },
+        _ => span.fill(color),
+    }
+}
+
+fn blend_with(isa: Isa, span: &mut [u32], color: Color, mode: BlendMode) {
+    let factors = BlendFactors::new(color, mode);
+    match isa {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Avx2 => unsafe { x86::blend_avx2(span, &factors) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Sse2 => unsafe { x86::blend_sse2(span, &factors) },
+        _ => blend_scalar(span, &factors),
+    }
+}
+
+fn blend_argb_with(isa: Isa, dst: &mut [u32], src: &[u32]) {
+    let len = dst.len().min(src.len());
+    let (dst, src) = (&mut dst[..len], &src[..len]);
+    match isa {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Avx2 => unsafe { x86::blend_argb_avx2(dst, src) },
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        Isa::Sse2 => unsafe { x86::blend_argb_sse2(dst, src) },
+        _ => blend_argb_scalar(dst, src),
+    }
+}
+
+fn blend_scalar(span: &mut [u32], factors: &BlendFactors) {
+    for pixel in span.iter_mut() {
+        let mut result = 0;
+        for channel in 0..3 {
+            let dst = (*pixel >> (channel * 8)) as u16 & 0xff;
+            let value = mul(dst, factors.f)
+                + mul(mul(dst, factors.m[channel]), factors.a)
+                + factors.k[channel];
+            result |= (value.min(255) as u32) << (channel * 8);
+        }
+        *pixel = result;
+    }
+}
+
+fn blend_argb_scalar(dst: &mut [u32], src: &[u32]) {
+    for (pixel, &src) in dst.iter_mut().zip(src.iter()) {
+        *pixel = Color::unpack_argb(src).blend(*pixel, BlendMode::Alpha);
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    use super::{blend_argb_scalar, blend_scalar, BlendFactors};
+
+    const RGB_MASK: i32 = 0x00ff_ffff;
+
+    fn lanes(values: [u16; 4]) -> [i16; 4] {
+        [
+            values[0] as i16,
+            values[1] as i16,
+            values[2] as i16,
+            values[3] as i16,
+        ]
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn fill_sse2(span: &mut [u32], color: u32) {
+        let value = _mm_set1_epi32(color as i32);
+        let mut chunks = span.chunks_exact_mut(4);
+        for chunk in &mut chunks {
+            _mm_storeu_si128(chunk.as_mut_ptr() as *mut __m128i, value);
+        }
+        chunks.into_remainder().fill(color);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn fill_avx2(span: &mut [u32], color: u32) {
+        let value = _mm256_set1_epi32(color as i32);
+        let mut chunks = span.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            _mm256_storeu_si256(chunk.as_mut_ptr() as *mut __m256i, value);
+        }
+        chunks.into_remainder().fill(color);
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn mul_sse2(x: __m128i, y: __m128i) -> __m128i {
+        let t = _mm_add_epi16(_mm_mullo_epi16(x, y), _mm_set1_epi16(128));
+        _mm_srli_epi16(_mm_add_epi16(t, _mm_srli_epi16(t, 8)), 8)
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn mul_avx2(x: __m256i, y: __m256i) -> __m256i {
+        let t = _mm256_add_epi16(_mm256_mullo_epi16(x, y), _mm256_set1_epi16(128));
+        _mm256_srli_epi16(_mm256_add_epi16(t, _mm256_srli_epi16(t, 8)), 8)
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn blend_sse2(span: &mut [u32], factors: &BlendFactors) {
+        let zero = _mm_setzero_si128();
+        let f = _mm_set1_epi16(factors.f as i16);
+        let a = _mm_set1_epi16(factors.a as i16);
+        let [m0, m1, m2, m3] = lanes(factors.m);
+        let m = _mm_setr_epi16(m0, m1, m2, m3, m0, m1, m2, m3);
+        let [k0, k1, k2, k3] = lanes(factors.k);
+        let k = _mm_setr_epi16(k0, k1, k2, k3, k0, k1, k2, k3);
+        let rgb_mask = _mm_set1_epi32(RGB_MASK);
+
+        let blend = |dst: __m128i| {
+            let value = _mm_add_epi16(mul_sse2(dst, f), mul_sse2(mul_sse2(dst, m), a));
+            _mm_add_epi16(value, k)
+        };
+
+        let mut chunks = span.chunks_exact_mut(4);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr() as *mut __m128i;
+            let pixels = _mm_loadu_si128(ptr);
+            let low = blend(_mm_unpacklo_epi8(pixels, zero));
+            let high = blend(_mm_unpackhi_epi8(pixels, zero));
+            let result = _mm_and_si128(_mm_packus_epi16(low, high), rgb_mask);
+            _mm_storeu_si128(ptr, result);
+        }
+        blend_scalar(chunks.into_remainder(), factors);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn blend_avx2(span: &mut [u32], factors: &BlendFactors) {
+        let zero = _mm256_setzero_si256();
+        let f = _mm256_set1_epi16(factors.f as i16);
+        let a = _mm256_set1_epi16(factors.a as i16);
+        let [m0, m1, m2, m3] = lanes(factors.m);
+        let m = _mm256_setr_epi16(
+            m0, m1, m2, m3, m0, m1, m2, m3, m0, m1, m2, m3, m0, m1, m2, m3,
+        );
+        let [k0, k1, k2, k3] = lanes(factors.k);
+        let k = _mm256_setr_epi16(
+            k0, k1, k2, k3, k0, k1, k2, k3, k0, k1, k2, k3, k0, k1, k2, k3,
+        );
+        let rgb_mask = _mm256_set1_epi32(RGB_MASK);
+
+        let blend = |dst: __m256i| {
+            let value = _mm256_add_epi16(mul_avx2(dst, f), mul_avx2(mul_avx2(dst, m), a));
+            _mm256_add_epi16(value, k)
+        };
+
+        let mut chunks = span.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            let ptr = chunk.as_mut_ptr() as *mut __m256i;
+            let pixels = _mm256_loadu_si256(ptr);
+            // Unpacking and packing both work within 128 bit halves, so pixels stay in order
+            let low = blend(_mm256_unpacklo_epi8(pixels, zero));
+            let high = blend(_mm256_unpackhi_epi8(pixels, zero));
+            let result = _mm256_and_si256(_mm256_packus_epi16(low, high), rgb_mask);
+            _mm256_storeu_si256(ptr, result);
+        }
+        blend_scalar(chunks.into_remainder(), factors);
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn blend_argb_sse2(dst: &mut [u32], src: &[u32]) {
+        let zero = _mm_setzero_si128();
+        let max = _mm_set1_epi16(255);
+        let rgb_mask = _mm_set1_epi32(RGB_MASK);
+
+        let blend = |dst: __m128i, src: __m128i| {
+            // Spread each pixel's alpha over its four lanes
+            let a = _mm_shufflehi_epi16(_mm_shufflelo_epi16(src, 0xff), 0xff);
+            _mm_add_epi16(mul_sse2(dst, _mm_sub_epi16(max, a)), mul_sse2(src, a))
+        };
+
+        let mut dst_chunks = dst.chunks_exact_mut(4);
+        let mut src_chunks = src.chunks_exact(4);
+        for (dst_chunk, src_chunk) in (&mut dst_chunks).zip(&mut src_chunks) {
+            let ptr = dst_chunk.as_mut_ptr() as *mut __m128i;
+            let pixels = _mm_loadu_si128(ptr);
+            let source = _mm_loadu_si128(src_chunk.as_ptr() as *const __m128i);
+            let low = blend(
+                _mm_unpacklo_epi8(pixels, zero),
+                _mm_unpacklo_epi8(source, zero),
+            );
+            let high = blend(
+                _mm_unpackhi_epi8(pixels, zero),
+                _mm_unpackhi_epi8(source, zero),
+            );
+            let result = _mm_and_si128(_mm_packus_epi16(low, high), rgb_mask);
+            _mm_storeu_si128(ptr, result);
+        }
+        blend_argb_scalar(dst_chunks.into_remainder(), src_chunks.remainder());
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn blend_argb_avx2(dst: &mut [u32], src: &[u32]) {
+        let zero = _mm256_setzero_si256();
+        let max = _mm256_set1_epi16(255);
+        let rgb_mask = _mm256_set1_epi32(RGB_MASK);
+
+        let blend = |dst: __m256i, src: __m256i| {
+            let a = _mm256_shufflehi_epi16(_mm256_shufflelo_epi16(src, 0xff), 0xff);
+            _mm256_add_epi16(mul_avx2(dst, _mm256_sub_epi16(max, a)), mul_avx2(src, a))
+        };
+
+        let mut dst_chunks = dst.chunks_exact_mut(8);
+        let mut src_chunks = src.chunks_exact(8);
+        for (dst_chunk, src_chunk) in (&mut dst_chunks).zip(&mut src_chunks) {
+            let ptr = dst_chunk.as_mut_ptr() as *mut __m256i;
+            let pixels = _mm256_loadu_si256(ptr);
+            let source = _mm256_loadu_si256(src_chunk.as_ptr() as *const __m256i);
+            let low = blend(
+                _mm256_unpacklo_epi8(pixels, zero),
+                _mm256_unpacklo_epi8(source, zero),
+            );
+            let high = blend(
+                _mm256_unpackhi_epi8(pixels, zero),
+                _mm256_unpackhi_epi8(source, zero),
+            );
+            let result = _mm256_and_si256(_mm256_packus_epi16(low, high), rgb_mask);
+            _mm256_storeu_si256(ptr, result);
+        }
+        blend_argb_scalar(dst_chunks.into_remainder(), src_chunks.remainder());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::math::Rng;
+
+    fn isas() -> Vec<Isa> {
+        let mut isas = vec![Isa::Scalar];
+        match Isa::detect() {
+            Isa::Avx2 => isas.extend_from_slice(&[Isa::Sse2, Isa::Avx2]),
+            Isa::Sse2 => isas.push(Isa::Sse2),
+            Isa::Scalar => {}
+        }
+        isas
+    }
+
+    fn random_pixels(rng: &mut Rng, len: usize, mask: u32) -> Vec<u32> {
+        (0..len).map(|_| rng.next_u32() & mask).collect()
+    }
+
+    #[test]
+    fn test_fill() {
+        for isa in isas() {
+            // Odd lengths exercise the scalar tail
+            let mut span = [0; 37];
+            fill_with(isa, &mut span[1..], 0x123456);
+            assert_eq!(span[0], 0);
+            assert!(
+                span[1..].iter().all(|&pixel| pixel == 0x123456),
+                "{:?}",
+                isa
+            );
+        }
+    }
+
+    #[test]
+    fn test_blend_matches_color() {
+        let mut rng = Rng::new(7);
+        let modes = [BlendMode::Alpha, BlendMode::Additive, BlendMode::Multiply];
+        for _ in 0..200 {
+            let color = Color::unpack_argb(rng.next_u32());
+            let mode = modes[rng.next_u32() as usize % modes.len()];
+            let pixels = random_pixels(&mut rng, 29, 0x00ff_ffff);
+            let expected: Vec<u32> = pixels.iter().map(|&p| color.blend(p, mode)).collect();
+
+            for isa in isas() {
+                let mut span = pixels.clone();
+                blend_with(isa, &mut span, color, mode);
+                assert_eq!(span, expected, "{:?} {:?} {:?}", isa, mode, color);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blend_argb_matches_color() {
+        let mut rng = Rng::new(11);
+        for _ in 0..200 {
+            let mut src = random_pixels(&mut rng, 27, 0xffff_ffff);
+            // Fully transparent and opaque pixels are the common case for sprites
+            src[3] &= 0x00ff_ffff;
+            src[4] |= 0xff00_0000;
+            let pixels = random_pixels(&mut rng, 27, 0x00ff_ffff);
+            let expected: Vec<u32> = pixels
+                .iter()
+                .zip(src.iter())
+                .map(|(&p, &s)| Color::unpack_argb(s).blend(p, BlendMode::Alpha))
+                .collect();
+
+            for isa in isas() {
+                let mut dst = pixels.clone();
+                blend_argb_with(isa, &mut dst, &src);
+                assert_eq!(dst, expected, "{:?}", isa);
+            }
+        }
+    }
+
+    /// Median time of `samples` runs of `f`, after warming up.
+    fn bench<F: FnMut()>(samples: usize, mut f: F) -> Duration {
+        for _ in 0..3 {
+            f();
+        }
+        let mut times: Vec<Duration> = (0..samples)
+            .map(|_| {
+                let start = Instant::now();
+                f();
+                start.elapsed()
+            })
+            .collect();
+        times.sort();
+        times[samples / 2]
+    }
+
+    /// Runs `f` on a 4K buffer with every path and prints the speedup over scalar.
+    fn bench_isas<F: FnMut(Isa, &mut [u32])>(name: &str, mut f: F) {
+        let mut pixels = vec![0x0033_6699; 3840 * 2160];
+        let mut scalar = None;
+        for isa in isas() {
+            let time = bench(31, || f(isa, &mut pixels));
+            let scalar = *scalar.get_or_insert(time);
+            println!(
+                "{:<16} {:<8} {:>10.3?} {:>6.2}x",
+                name,
+                format!("{:?}", isa),
+                time,
+                scalar.as_secs_f64() / time.as_secs_f64()
+            );
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_fill() {
+        bench_isas("fill", |isa, pixels| fill_with(isa, pixels, 0x0011_2233));
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_blend() {
+        let color = Color::rgba(200, 100, 50, 128);
+        bench_isas("blend alpha", |isa, pixels| {
+            blend_with(isa, pixels, color, BlendMode::Alpha)
+        });
+        bench_isas("blend additive", |isa, pixels| {
+            blend_with(isa, pixels, color, BlendMode::Additive)
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_blend_argb() {
+        let mut rng = Rng::new(3);
+        let src = random_pixels(&mut rng, 3840 * 2160, 0xffff_ffff);
+        bench_isas("blit", |isa, pixels| blend_argb_with(isa, pixels, &src));
+    }
+}
@@ -1,110 +1,662 @@
-use crate::line::line_iter;
+use std::f32::consts::TAU;
+use std::ops::Range;
+
+use crate::bitmap::Sprite;
+use crate::camera::{Camera, Fit};
+use crate::color::{BlendMode, Color};
+use crate::command_list::{self, Command, Rasterizer};
+use crate::dirty_rects::{FrameHistory, PixelRect};
+use crate::font::{
+    glyph, text_width, TextAlign, GLYPH_ADVANCE, GLYPH_HEIGHT, GLYPH_WIDTH, LINE_ADVANCE,
+};
+use crate::line::{aa_line_iter, clip_line, clip_line_aa, line_iter};
 use crate::math::{clamp, Vec2};
+use crate::simd;
+
+const ELLIPSE_SEGMENTS: usize = 32;
+const CORNER_SEGMENTS: usize = 8;
 
-static SCALE: f32 = 0.01;
+#[derive(Copy, Clone, PartialEq)]
+pub(crate) enum Paint {
+    Solid(u32),
+    Blended(Color, BlendMode),
+}
+
+impl Paint {
+    fn apply(self, pixel: u32) -> u32 {
+        match self {
+            Paint::Solid(color) => color,
+            Paint::Blended(color, mode) => color.blend(pixel, mode),
+        }
+    }
+
+    fn apply_to_span(self, span: &mut [u32]) {
+        match self {
+            Paint::Solid(color) => simd::fill(span, color),
+            Paint::Blended(color, mode) => simd::blend(span, color, mode),
+        }
+    }
+}
 
 pub struct RenderBuffer<'a> {
     pub pixels: &'a mut [u32],
     pub width: i32,
     pub height: i32,
+    /// Maps world space to pixels for every draw call that isn't `_in_pixels`.
+    pub camera: Camera,
+
+    /// `pixels` holds rows from `row0` of the frame. A tile only holds its own rows, everything
+    /// is still drawn in frame coordinates and clipped to `clip`, which lies within them.
+    row0: i32,
+    clip: PixelRect,
+    /// Draw calls waiting for `flush`, when recording.
+    commands: Option<Vec<Command>>,
 }
 
 impl<'a> RenderBuffer<'a> {
+    pub fn new(pixels: &'a mut [u32], width: i32, height: i32) -> RenderBuffer<'a> {
+        RenderBuffer {
+            pixels,
+            width,
+            height,
+            camera: Camera::new(),
+            row0: 0,
+            clip: PixelRect::new(0, 0, width, height),
+            commands: None,
+        }
+    }
+
+    /// Records draw calls instead of rasterizing them, until `flush`.
+    pub fn recording(pixels: &'a mut [u32], width: i32, height: i32) -> RenderBuffer<'a> {
+        RenderBuffer {
+            commands: Some(Vec::new()),
+            ..RenderBuffer::new(pixels, width, height)
+        }
+    }
+
+    /// Rows `row0..` of a `width` x `height` frame, as used by `command_list`. Only pixels in
+    /// `clip` are drawn.
+    pub(crate) fn tile(
+        pixels: &'a mut [u32],
+        width: i32,
+        height: i32,
+        row0: i32,
+        clip: PixelRect,
+    ) -> RenderBuffer<'a> {
+        let row1 = row0 + pixels.len() as i32 / width.max(1);
+        RenderBuffer {
+            row0,
+            clip: clip.intersection(&PixelRect::new(0, row0, width, row1)),
+            ..RenderBuffer::new(pixels, width, height)
+        }
+    }
+
+    /// Rasterizes the recorded draw calls on the rasterizer's threads, with the same result as
+    /// drawing them immediately. Does nothing when not recording.
+    pub fn flush(&mut self, rasterizer: &Rasterizer) {
+        if let Some(commands) = self.commands.as_mut() {
+            let commands = std::mem::take(commands);
+            let frame = [PixelRect::new(0, 0, self.width, self.height)];
+            command_list::rasterize(
+                &commands,
+                self.pixels,
+                self.width,
+                self.height,
+                &frame,
+                rasterizer,
+            );
+        }
+    }
+
+    /// Like `flush`, but `pixels` must still hold the frame `history` last saw, and only the
+    /// regions that changed since are redrawn. Returns those regions, for presenting. When not
+    /// recording everything was drawn already, so the whole frame is returned.
+    pub fn flush_changes(
+        &mut self,
+        history: &mut FrameHistory,
+        rasterizer: &Rasterizer,
+    ) -> Vec<PixelRect> {
+        let frame = PixelRect::new(0, 0, self.width, self.height);
+        let commands = match self.commands.as_mut() {
+            Some(commands) => std::mem::take(commands),
+            None => return vec![frame],
+        };
+
+        let dirty = history.changes(&commands, self.width, self.height);
+        command_list::rasterize(
+            &commands,
+            self.pixels,
+            self.width,
+            self.height,
+            &dirty,
+            rasterizer,
+        );
+        history.set(commands, self.width, self.height);
+        dirty
+    }
+
     pub fn clear(&mut self, color: u32) {
-        for y in 0..self.height {
-            for x in 0..self.width {
-                self.pixels[(y * self.width + x) as usize] = color;
-            }
+        if self.record(|| Command::Clear(color)) {
+            return;
+        }
+
+        for y in self.clip.y0..self.clip.y1 {
+            let span = self.clip_span(y);
+            simd::fill(&mut self.pixels[span], color);
         }
     }
 
     pub fn draw_line_in_pixels(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
-        let x0 = clamp(0, x0, self.width - 1);
-        let x1 = clamp(0, x1, self.width - 1);
-        let y0 = clamp(0, y0, self.height - 1);
-        let y1 = clamp(0, y1, self.height - 1);
+        self.draw_line_painted(x0, y0, x1, y1, Paint::Solid(color));
+    }
 
-        for p in line_iter(x0, y0, x1, y1) {
-            self.pixels[(p.y * self.width + p.x) as usize] = color;
+    pub fn draw_rect_in_pixels(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        self.fill_rect(x0, y0, x1, y1, Paint::Solid(color));
+    }
+
+    pub fn draw_line_blended_in_pixels(
+        &mut self,
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        color: Color,
+        mode: BlendMode,
+    ) {
+        self.draw_line_painted(x0, y0, x1, y1, Paint::Blended(color, mode));
+    }
+
+    pub fn draw_rect_blended_in_pixels(
+        &mut self,
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        color: Color,
+        mode: BlendMode,
+    ) {
+        self.fill_rect(x0, y0, x1, y1, Paint::Blended(color, mode));
+    }
+
+    /// Anti-aliased line between sub-pixel endpoints, each pixel is alpha blended by its
+    /// coverage. Pixels outside the buffer are skipped.
+    pub fn draw_line_aa_in_pixels(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, color: Color) {
+        if self.record(|| Command::LineAa {
+            x0,
+            y0,
+            x1,
+            y1,
+            color,
+        }) {
+            return;
+        }
+
+        // Clipped to the frame plus the pixel that can still cover its edge, not the clip, so
+        // every tile walks the same pixels
+        let (x0, y0, x1, y1) = match clip_line_aa(
+            x0,
+            y0,
+            x1,
+            y1,
+            -1.0,
+            -1.0,
+            self.width as f32,
+            self.height as f32,
+        ) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        let alpha = color.a as f32 / 255.0;
+        for p in aa_line_iter(x0, y0, x1, y1) {
+            if !self.clip.contains(p.x, p.y) {
+                continue;
+            }
+
+            let index = self.index(p.x, p.y);
+            let pixel = &mut self.pixels[index];
+            *pixel = color
+                .with_alpha(alpha * p.aa)
+                .blend(*pixel, BlendMode::Alpha);
         }
     }
 
-    pub fn draw_rect_in_pixels(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
-        let x0 = clamp(0, x0, self.width);
-        let x1 = clamp(0, x1, self.width);
-        let y0 = clamp(0, y0, self.height);
-        let y1 = clamp(0, y1, self.height);
+    /// Darkens the whole buffer, `amount` goes from 0 (unchanged) to 1 (black).
+    pub fn fade_to_black(&mut self, amount: f32) {
+        if amount <= 0.0 || self.record(|| Command::FadeToBlack(amount)) {
+            return;
+        }
 
-        for y in y0..y1 {
-            for x in x0..x1 {
-                self.pixels[(y * self.width + x) as usize] = color;
+        let color = Color::BLACK.with_alpha(amount);
+        for y in self.clip.y0..self.clip.y1 {
+            let span = self.clip_span(y);
+            simd::blend(&mut self.pixels[span], color, BlendMode::Alpha);
+        }
+    }
+
+    /// Draws `text` with the bottom edge of its last line at `y`. `x` is the left edge, the
+    /// center or the right edge of every line depending on `align`. Each glyph pixel is drawn
+    /// as a `scale` x `scale` square.
+    pub fn draw_text_in_pixels(
+        &mut self,
+        x: i32,
+        y: i32,
+        text: &str,
+        scale: i32,
+        color: u32,
+        align: TextAlign,
+    ) {
+        if self.record(|| Command::Text {
+            x,
+            y,
+            text: text.to_string(),
+            scale,
+            color,
+            align,
+        }) {
+            return;
+        }
+
+        let scale = scale.max(1);
+        let line_count = text.lines().count() as i32;
+
+        for (line_index, line) in text.lines().enumerate() {
+            let width = text_width(line) * scale;
+            let mut x = match align {
+                TextAlign::Left => x,
+                TextAlign::Center => x - width / 2,
+                TextAlign::Right => x - width,
+            };
+            let y = y + (line_count - 1 - line_index as i32) * LINE_ADVANCE * scale;
+
+            for c in line.chars() {
+                for (row, bits) in glyph(c).iter().enumerate() {
+                    let y = y + (GLYPH_HEIGHT - 1 - row as i32) * scale;
+                    for column in 0..GLYPH_WIDTH {
+                        if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                            let x = x + column * scale;
+                            self.draw_rect_in_pixels(x, y, x + scale, y + scale, color);
+                        }
+                    }
+                }
+
+                x += GLYPH_ADVANCE * scale;
             }
         }
     }
 
-    pub fn draw_rect(&mut self, mut p: Vec2, mut half_size: Vec2, color: u32) {
-        let aspect_multiplier = self.calc_aspect_multiplier();
+    /// Filled shapes cover every pixel whose center is inside them, so edges stay correct for
+    /// sub-pixel positions and sizes.
+    pub fn draw_ellipse_in_pixels(&mut self, cx: f32, cy: f32, rx: f32, ry: f32, color: u32) {
+        self.fill_ellipse(cx, cy, rx, ry, Paint::Solid(color));
+    }
 
-        half_size.x *= aspect_multiplier * SCALE;
-        half_size.y *= aspect_multiplier * SCALE;
+    pub fn draw_circle_in_pixels(&mut self, cx: f32, cy: f32, radius: f32, color: u32) {
+        self.draw_ellipse_in_pixels(cx, cy, radius, radius, color);
+    }
 
-        p.x *= aspect_multiplier * SCALE;
-        p.y *= aspect_multiplier * SCALE;
+    /// Rect from `x0, y0` to `x1, y1` with its corners rounded by `radius`, which is limited to
+    /// half the shorter side.
+    pub fn draw_rounded_rect_in_pixels(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        radius: f32,
+        color: u32,
+    ) {
+        if self.record(|| Command::RoundedRect {
+            x0,
+            y0,
+            x1,
+            y1,
+            radius,
+            color,
+        }) {
+            return;
+        }
 
-        p.x += self.width as f32 * 0.5;
-        p.y += self.height as f32 * 0.5;
+        let radius = clamp(0.0, radius, (x1 - x0).min(y1 - y0) * 0.5);
 
-        let x0 = (p.x - half_size.x) as i32;
-        let y0 = (p.y - half_size.y) as i32;
-        let x1 = (p.x + half_size.x) as i32;
-        let y1 = (p.y + half_size.y) as i32;
+        let (row0, row1) = self.pixel_rows(y0, y1);
+        for y in row0..=row1 {
+            let yc = y as f32 + 0.5;
+            let d = (y0 + radius - yc).max(yc - (y1 - radius)).max(0.0);
+            let inset = radius - (radius * radius - d * d).max(0.0).sqrt();
+            self.fill_span(y, x0 + inset, x1 - inset, Paint::Solid(color));
+        }
+    }
 
-        self.draw_rect_in_pixels(x0, y0, x1, y1, color);
+    /// `points` must form a convex polygon, in either winding order.
+    pub fn draw_polygon_in_pixels(&mut self, points: &[Vec2], color: u32) {
+        self.fill_polygon(points, Paint::Solid(color));
     }
 
-    pub fn draw_line(&mut self, mut start: Vec2, mut end: Vec2, color: u32) {
-        let aspect_multiplier = self.calc_aspect_multiplier();
+    /// Stretches the sprite's source rect over `x0, y0` to `x1, y1`, alpha blending it.
+    pub fn draw_bitmap_in_pixels(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, sprite: &Sprite) {
+        if x1 <= x0 || y1 <= y0 || sprite.alpha <= 0.0 {
+            return;
+        }
+        if self.record(|| Command::Bitmap {
+            x0,
+            y0,
+            x1,
+            y1,
+            sprite: sprite.clone(),
+        }) {
+            return;
+        }
 
-        start.x *= aspect_multiplier * SCALE;
-        start.y *= aspect_multiplier * SCALE;
-        start.x += self.width as f32 * 0.5;
-        start.y += self.height as f32 * 0.5;
+        let source = sprite.source();
+        let scale_x = source.width as f32 / (x1 - x0);
+        let scale_y = source.height as f32 / (y1 - y0);
 
-        end.x *= aspect_multiplier * SCALE;
-        end.y *= aspect_multiplier * SCALE;
-        end.x += self.width as f32 * 0.5;
-        end.y += self.height as f32 * 0.5;
+        let (row0, row1) = self.pixel_rows(y0, y1);
+        let (column0, column1) = self.pixel_columns(x0, x1);
+        if column0 >= column1 {
+            return;
+        }
 
-        let x0 = start.x as i32;
-        let y0 = start.y as i32;
-        let x1 = end.x as i32;
-        let y1 = end.y as i32;
+        // Each row is sampled first, then blended in one go
+        let mut samples = Vec::with_capacity((column1 - column0) as usize);
+        for y in row0..=row1 {
+            let v = source.y as f32 + (y as f32 + 0.5 - y0) * scale_y;
+            samples.clear();
+            samples.extend((column0..column1).map(|x| {
+                let u = source.x as f32 + (x as f32 + 0.5 - x0) * scale_x;
+                let color = sprite.bitmap.sample(&source, u, v, sprite.sampling);
+                color
+                    .with_alpha(color.a as f32 / 255.0 * sprite.alpha)
+                    .pack_argb()
+            }));
 
-        self.draw_line_in_pixels(x0, y0, x1, y1, color);
+            let start = self.index(column0, y);
+            simd::blend_argb(&mut self.pixels[start..start + samples.len()], &samples);
+        }
     }
 
-    pub fn clear_and_draw_rect(
+    /// Midpoint circle algorithm.
+    pub fn draw_circle_outline_in_pixels(&mut self, cx: i32, cy: i32, radius: i32, color: u32) {
+        if radius < 0
+            || self.record(|| Command::CircleOutline {
+                cx,
+                cy,
+                radius,
+                color,
+            })
+        {
+            return;
+        }
+
+        let mut x = radius;
+        let mut y = 0;
+        let mut d = 1 - radius;
+        while x >= y {
+            self.plot_symmetric(cx, cy, x, y, color);
+            self.plot_symmetric(cx, cy, y, x, color);
+
+            y += 1;
+            if d < 0 {
+                d += 2 * y + 1;
+            } else {
+                x -= 1;
+                d += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Midpoint ellipse algorithm. Decision variables are kept 4x scaled so they stay integers.
+    pub fn draw_ellipse_outline_in_pixels(
         &mut self,
-        mut p: Vec2,
-        mut half_size: Vec2,
+        cx: i32,
+        cy: i32,
+        rx: i32,
+        ry: i32,
         color: u32,
-        clear_color: u32,
     ) {
-        let aspect_multiplier = self.calc_aspect_multiplier();
+        if rx < 0
+            || ry < 0
+            || self.record(|| Command::EllipseOutline {
+                cx,
+                cy,
+                rx,
+                ry,
+                color,
+            })
+        {
+            return;
+        }
+        if rx == 0 || ry == 0 {
+            self.draw_line_in_pixels(cx - rx, cy - ry, cx + rx, cy + ry, color);
+            return;
+        }
+
+        let rx2 = rx as i64 * rx as i64;
+        let ry2 = ry as i64 * ry as i64;
+
+        let mut x = 0i64;
+        let mut y = ry as i64;
+        let mut px = 0;
+        let mut py = 2 * rx2 * y;
+
+        // Region 1, the slope is shallower than -1
+        let mut p = 4 * ry2 - 4 * rx2 * y + rx2;
+        while px < py {
+            self.plot_symmetric(cx, cy, x as i32, y as i32, color);
+
+            x += 1;
+            px += 2 * ry2;
+            if p < 0 {
+                p += 4 * (ry2 + px);
+            } else {
+                y -= 1;
+                py -= 2 * rx2;
+                p += 4 * (ry2 + px - py);
+            }
+        }
+
+        // Region 2
+        let mut p = ry2 * (2 * x + 1) * (2 * x + 1) + 4 * rx2 * (y - 1) * (y - 1) - 4 * rx2 * ry2;
+        while y >= 0 {
+            self.plot_symmetric(cx, cy, x as i32, y as i32, color);
+
+            y -= 1;
+            py -= 2 * rx2;
+            if p > 0 {
+                p += 4 * (rx2 - py);
+            } else {
+                x += 1;
+                px += 2 * ry2;
+                p += 4 * (rx2 - py + px);
+            }
+        }
+    }
+
+    pub fn draw_polygon_outline_in_pixels(&mut self, points: &[Vec2], color: u32) {
+        for (i, a) in points.iter().enumerate() {
+            let b = &points[(i + 1) % points.len()];
+            self.draw_line_in_pixels(a.x as i32, a.y as i32, b.x as i32, b.y as i32, color);
+        }
+    }
+
+    pub fn draw_rect(&mut self, p: Vec2, half_size: Vec2, color: u32) {
+        match self.world_rect_to_pixels(p, half_size) {
+            Some((x0, y0, x1, y1)) => self.draw_rect_in_pixels(x0, y0, x1, y1, color),
+            None => {
+                let corners = self.world_rect_corners(p, half_size);
+                self.fill_polygon(&corners, Paint::Solid(color));
+            }
+        }
+    }
 
-        half_size.x *= aspect_multiplier * SCALE;
-        half_size.y *= aspect_multiplier * SCALE;
+    pub fn draw_line(&mut self, start: Vec2, end: Vec2, color: u32) {
+        let start = self.world_to_pixels(start);
+        let end = self.world_to_pixels(end);
 
-        p.x *= aspect_multiplier * SCALE;
-        p.y *= aspect_multiplier * SCALE;
+        self.draw_line_in_pixels(
+            start.x as i32,
+            start.y as i32,
+            end.x as i32,
+            end.y as i32,
+            color,
+        );
+    }
+
+    pub fn draw_rect_blended(&mut self, p: Vec2, half_size: Vec2, color: Color, mode: BlendMode) {
+        match self.world_rect_to_pixels(p, half_size) {
+            Some((x0, y0, x1, y1)) => self.draw_rect_blended_in_pixels(x0, y0, x1, y1, color, mode),
+            None => {
+                let corners = self.world_rect_corners(p, half_size);
+                self.fill_polygon(&corners, Paint::Blended(color, mode));
+            }
+        }
+    }
+
+    pub fn draw_line_blended(&mut self, start: Vec2, end: Vec2, color: Color, mode: BlendMode) {
+        let start = self.world_to_pixels(start);
+        let end = self.world_to_pixels(end);
+
+        self.draw_line_blended_in_pixels(
+            start.x as i32,
+            start.y as i32,
+            end.x as i32,
+            end.y as i32,
+            color,
+            mode,
+        );
+    }
+
+    pub fn draw_line_aa(&mut self, start: Vec2, end: Vec2, color: Color) {
+        let start = self.world_to_pixels(start);
+        let end = self.world_to_pixels(end);
+
+        // Pixel `i` covers `i..i + 1`, the line iterator puts pixel centers on integers
+        self.draw_line_aa_in_pixels(
+            start.x - 0.5,
+            start.y - 0.5,
+            end.x - 0.5,
+            end.y - 0.5,
+            color,
+        );
+    }
+
+    /// `p` is the anchor passed to `draw_text_in_pixels` and `size` the height of a glyph, in
+    /// world units. Glyphs are only scaled by whole pixels so they stay crisp, and they stay
+    /// upright when the camera rotates.
+    pub fn draw_text(&mut self, p: Vec2, size: f32, text: &str, color: u32, align: TextAlign) {
+        let p = self.world_to_pixels(p);
+        let scale = (self.world_to_pixel_length(size) / GLYPH_HEIGHT as f32).round() as i32;
+
+        self.draw_text_in_pixels(p.x as i32, p.y as i32, text, scale, color, align);
+    }
+
+    pub fn draw_circle(&mut self, p: Vec2, radius: f32, color: u32) {
+        let p = self.world_to_pixels(p);
+        let radius = self.world_to_pixel_length(radius);
+        self.draw_circle_in_pixels(p.x, p.y, radius, color);
+    }
+
+    pub fn draw_circle_blended(&mut self, p: Vec2, radius: f32, color: Color, mode: BlendMode) {
+        let p = self.world_to_pixels(p);
+        let radius = self.world_to_pixel_length(radius);
+        self.fill_ellipse(p.x, p.y, radius, radius, Paint::Blended(color, mode));
+    }
 
-        p.x += self.width as f32 * 0.5;
-        p.y += self.height as f32 * 0.5;
+    pub fn draw_ellipse(&mut self, p: Vec2, half_size: Vec2, color: u32) {
+        if self.camera.rotation == 0.0 {
+            let p = self.world_to_pixels(p);
+            let rx = self.world_to_pixel_length(half_size.x);
+            let ry = self.world_to_pixel_length(half_size.y);
+            self.draw_ellipse_in_pixels(p.x, p.y, rx, ry, color);
+        } else {
+            let points = self.world_ellipse_points(p, half_size);
+            self.fill_polygon(&points, Paint::Solid(color));
+        }
+    }
 
-        let x0 = (p.x - half_size.x) as i32;
-        let y0 = (p.y - half_size.y) as i32;
-        let x1 = (p.x + half_size.x) as i32;
-        let y1 = (p.y + half_size.y) as i32;
+    pub fn draw_rounded_rect(&mut self, p: Vec2, half_size: Vec2, radius: f32, color: u32) {
+        if self.camera.rotation == 0.0 {
+            let min = self.world_to_pixels(p - half_size);
+            let max = self.world_to_pixels(p + half_size);
+            let radius = self.world_to_pixel_length(radius);
+            self.draw_rounded_rect_in_pixels(min.x, min.y, max.x, max.y, radius, color);
+        } else {
+            let radius = clamp(0.0, radius, half_size.x.min(half_size.y));
+            let inner = half_size - Vec2::new(radius, radius);
+            let corners = [
+                (Vec2::new(inner.x, inner.y), 0.0),
+                (Vec2::new(-inner.x, inner.y), 0.25),
+                (Vec2::new(-inner.x, -inner.y), 0.5),
+                (Vec2::new(inner.x, -inner.y), 0.75),
+            ];
+
+            let mut points = Vec::with_capacity(4 * (CORNER_SEGMENTS + 1));
+            for &(corner, turn) in corners.iter() {
+                for i in 0..=CORNER_SEGMENTS {
+                    let angle = (turn + 0.25 * i as f32 / CORNER_SEGMENTS as f32) * TAU;
+                    let offset = Vec2::new(angle.cos(), angle.sin()) * radius;
+                    points.push(self.world_to_pixels(p + corner + offset));
+                }
+            }
+            self.fill_polygon(&points, Paint::Solid(color));
+        }
+    }
+
+    pub fn draw_polygon(&mut self, points: &[Vec2], color: u32) {
+        let points: Vec<Vec2> = points.iter().map(|&p| self.world_to_pixels(p)).collect();
+        self.draw_polygon_in_pixels(&points, color);
+    }
+
+    pub fn draw_bitmap(&mut self, p: Vec2, half_size: Vec2, sprite: &Sprite) {
+        if self.camera.rotation == 0.0 {
+            let min = self.world_to_pixels(p - half_size);
+            let max = self.world_to_pixels(p + half_size);
+            self.draw_bitmap_in_pixels(min.x, min.y, max.x, max.y, sprite);
+        } else {
+            let corners = self.world_rect_corners(p, half_size);
+            self.draw_bitmap_mapped(&corners, sprite);
+        }
+    }
+
+    pub fn draw_circle_outline(&mut self, p: Vec2, radius: f32, color: u32) {
+        let p = self.world_to_pixels(p);
+        let radius = self.world_to_pixel_length(radius);
+        self.draw_circle_outline_in_pixels(p.x as i32, p.y as i32, radius.round() as i32, color);
+    }
+
+    pub fn draw_ellipse_outline(&mut self, p: Vec2, half_size: Vec2, color: u32) {
+        if self.camera.rotation == 0.0 {
+            let p = self.world_to_pixels(p);
+            let rx = self.world_to_pixel_length(half_size.x).round() as i32;
+            let ry = self.world_to_pixel_length(half_size.y).round() as i32;
+            self.draw_ellipse_outline_in_pixels(p.x as i32, p.y as i32, rx, ry, color);
+        } else {
+            let points = self.world_ellipse_points(p, half_size);
+            self.draw_polygon_outline_in_pixels(&points, color);
+        }
+    }
+
+    pub fn draw_polygon_outline(&mut self, points: &[Vec2], color: u32) {
+        let points: Vec<Vec2> = points.iter().map(|&p| self.world_to_pixels(p)).collect();
+        self.draw_polygon_outline_in_pixels(&points, color);
+    }
+
+    pub fn clear_and_draw_rect(&mut self, p: Vec2, half_size: Vec2, color: u32, clear_color: u32) {
+        let (x0, y0, x1, y1) = match self.world_rect_to_pixels(p, half_size) {
+            Some(rect) => rect,
+            None => {
+                self.clear(clear_color);
+                self.draw_rect(p, half_size, color);
+                return;
+            }
+        };
+
+        // Clamped so the clear rects below don't overlap when the rect is partly off screen
+        let x0 = clamp(0, x0, self.width);
+        let x1 = clamp(x0, x1, self.width);
+        let y0 = clamp(0, y0, self.height);
+        let y1 = clamp(y0, y1, self.height);
 
         self.draw_rect_in_pixels(x0, y0, x1, y1, color);
 
@@ -114,24 +666,629 @@ impl<'a> RenderBuffer<'a> {
         self.draw_rect_in_pixels(x0, y1, x1, self.height, clear_color);
     }
 
+    /// Covers what lies outside the camera's view box, if its fit is `Fit::Letterbox`.
+    pub fn draw_letterbox(&mut self, color: u32) {
+        if self.camera.fit != Fit::Letterbox {
+            return;
+        }
+
+        let (min, max) = self.camera.viewport(self.width, self.height);
+        let (x0, y0) = (min.x as i32, min.y as i32);
+        let (x1, y1) = (max.x as i32, max.y as i32);
+
+        self.draw_rect_in_pixels(0, 0, x0, self.height, color);
+        self.draw_rect_in_pixels(x1, 0, self.width, self.height, color);
+        self.draw_rect_in_pixels(x0, 0, x1, y0, color);
+        self.draw_rect_in_pixels(x0, y1, x1, self.height, color);
+    }
+
     pub fn pixels_to_world(&self, pixels: Vec2) -> Vec2 {
-        let aspect_multiplier = self.calc_aspect_multiplier();
+        self.camera.pixels_to_world(pixels, self.width, self.height)
+    }
 
-        let mut result = Vec2::zero();
-        result.x = pixels.x as f32 - self.width as f32 * 0.5;
-        result.y = pixels.y as f32 - self.height as f32 * 0.5;
+    pub fn world_to_pixels(&self, p: Vec2) -> Vec2 {
+        self.camera.world_to_pixels(p, self.width, self.height)
+    }
 
-        result.x /= aspect_multiplier * SCALE;
-        result.y /= aspect_multiplier * SCALE;
+    fn world_to_pixel_length(&self, length: f32) -> f32 {
+        length * self.camera.pixels_per_unit(self.width, self.height)
+    }
 
-        result
+    /// Pixel rect covered by a world rect, `None` if the camera is rotated and it isn't axis
+    /// aligned anymore.
+    fn world_rect_to_pixels(&self, p: Vec2, half_size: Vec2) -> Option<(i32, i32, i32, i32)> {
+        if self.camera.rotation != 0.0 {
+            return None;
+        }
+
+        let min = self.world_to_pixels(p - half_size);
+        let max = self.world_to_pixels(p + half_size);
+        Some((min.x as i32, min.y as i32, max.x as i32, max.y as i32))
     }
 
-    fn calc_aspect_multiplier(&self) -> f32 {
-        if (self.width as f32 / self.height as f32) < 1.77 {
-            self.width as f32 / 1.77
-        } else {
-            self.height as f32
+    /// Bottom left, bottom right, top right and top left corners, in pixels.
+    fn world_rect_corners(&self, p: Vec2, half_size: Vec2) -> [Vec2; 4] {
+        [
+            self.world_to_pixels(p + Vec2::new(-half_size.x, -half_size.y)),
+            self.world_to_pixels(p + Vec2::new(half_size.x, -half_size.y)),
+            self.world_to_pixels(p + Vec2::new(half_size.x, half_size.y)),
+            self.world_to_pixels(p + Vec2::new(-half_size.x, half_size.y)),
+        ]
+    }
+
+    fn world_ellipse_points(&self, p: Vec2, half_size: Vec2) -> Vec<Vec2> {
+        (0..ELLIPSE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * TAU;
+                let offset = Vec2::new(angle.cos() * half_size.x, angle.sin() * half_size.y);
+                self.world_to_pixels(p + offset)
+            })
+            .collect()
+    }
+
+    /// Draws the sprite over the parallelogram with the given bottom left, bottom right and
+    /// top left corners (the top right one is implied), in pixels.
+    pub(crate) fn draw_bitmap_mapped(&mut self, corners: &[Vec2; 4], sprite: &Sprite) {
+        if sprite.alpha <= 0.0 {
+            return;
+        }
+        if self.record(|| Command::BitmapMapped {
+            corners: *corners,
+            sprite: sprite.clone(),
+        }) {
+            return;
+        }
+
+        let origin = corners[0];
+        let axis_u = corners[1] - origin;
+        let axis_v = corners[3] - origin;
+        let determinant = axis_u.cross(&axis_v);
+        if determinant == 0.0 {
+            return;
+        }
+
+        let min_x = corners.iter().fold(f32::MAX, |min, p| min.min(p.x));
+        let max_x = corners.iter().fold(f32::MIN, |max, p| max.max(p.x));
+        let min_y = corners.iter().fold(f32::MAX, |min, p| min.min(p.y));
+        let max_y = corners.iter().fold(f32::MIN, |max, p| max.max(p.y));
+
+        let source = sprite.source();
+        let (row0, row1) = self.pixel_rows(min_y, max_y);
+        let (column0, column1) = self.pixel_columns(min_x, max_x);
+        for y in row0..=row1 {
+            for x in column0..column1 {
+                // Solve `origin + axis_u * s + axis_v * t = center` for s and t
+                let d = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - origin;
+                let s = d.cross(&axis_v) / determinant;
+                let t = axis_u.cross(&d) / determinant;
+                if !(0.0..=1.0).contains(&s) || !(0.0..=1.0).contains(&t) {
+                    continue;
+                }
+
+                let u = source.x as f32 + s * source.width as f32;
+                let v = source.y as f32 + t * source.height as f32;
+                let color = sprite.bitmap.sample(&source, u, v, sprite.sampling);
+                if color.a == 0 {
+                    continue;
+                }
+
+                let color = color.with_alpha(color.a as f32 / 255.0 * sprite.alpha);
+                let index = self.index(x, y);
+                let pixel = &mut self.pixels[index];
+                *pixel = color.blend(*pixel, BlendMode::Alpha);
+            }
+        }
+    }
+
+    /// Records the command built by `command`, if recording. Returns whether it did, in which case
+    /// the caller skips drawing.
+    fn record<F: FnOnce() -> Command>(&mut self, command: F) -> bool {
+        match self.commands.as_mut() {
+            Some(commands) => {
+                commands.push(command());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        ((y - self.row0) * self.width + x) as usize
+    }
+
+    /// The part of row `y` inside the clip, as indices into `pixels`.
+    fn clip_span(&self, y: i32) -> Range<usize> {
+        self.index(self.clip.x0, y)..self.index(self.clip.x1, y)
+    }
+
+    /// Rows whose pixel centers can be inside `y0..=y1`, limited to the clip.
+    fn pixel_rows(&self, y0: f32, y1: f32) -> (i32, i32) {
+        let row0 = (y0 - 0.5).ceil().max(self.clip.y0 as f32) as i32;
+        let row1 = (y1 - 0.5).floor().min(self.clip.y1 as f32 - 1.0) as i32;
+        (row0, row1)
+    }
+
+    /// Columns whose pixel centers are inside `x0..=x1`, limited to the clip. The end is
+    /// exclusive.
+    fn pixel_columns(&self, x0: f32, x1: f32) -> (i32, i32) {
+        let column0 = (x0 - 0.5).ceil().max(self.clip.x0 as f32) as i32;
+        let column1 = ((x1 - 0.5).floor() + 1.0).min(self.clip.x1 as f32) as i32;
+        (column0, column1)
+    }
+
+    pub(crate) fn draw_line_painted(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, paint: Paint) {
+        if self.record(|| Command::Line {
+            x0,
+            y0,
+            x1,
+            y1,
+            paint,
+        }) {
+            return;
+        }
+
+        // Clipped to the frame, not the clip, so every tile walks the same pixels
+        let (x0, y0, x1, y1) =
+            match clip_line(x0, y0, x1, y1, 0, 0, self.width - 1, self.height - 1) {
+                Some(clipped) => clipped,
+                None => return,
+            };
+
+        for p in line_iter(x0, y0, x1, y1) {
+            if self.clip.contains(p.x, p.y) {
+                let index = self.index(p.x, p.y);
+                let pixel = &mut self.pixels[index];
+                *pixel = paint.apply(*pixel);
+            }
+        }
+    }
+
+    pub(crate) fn fill_rect(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, paint: Paint) {
+        if self.record(|| Command::Rect {
+            x0,
+            y0,
+            x1,
+            y1,
+            paint,
+        }) {
+            return;
+        }
+
+        let rect = PixelRect::new(x0, y0, x1, y1).intersection(&self.clip);
+        if rect.is_empty() {
+            return;
+        }
+
+        for y in rect.y0..rect.y1 {
+            let start = self.index(rect.x0, y);
+            paint.apply_to_span(&mut self.pixels[start..start + rect.width() as usize]);
+        }
+    }
+
+    /// Fills the pixels of row `y` whose centers are inside `left..=right`.
+    fn fill_span(&mut self, y: i32, left: f32, right: f32, paint: Paint) {
+        if y < self.clip.y0 || y >= self.clip.y1 {
+            return;
+        }
+
+        let (x0, x1) = self.pixel_columns(left, right);
+        if x0 < x1 {
+            let start = self.index(x0, y);
+            paint.apply_to_span(&mut self.pixels[start..start + (x1 - x0) as usize]);
+        }
+    }
+
+    pub(crate) fn fill_ellipse(&mut self, cx: f32, cy: f32, rx: f32, ry: f32, paint: Paint) {
+        if rx <= 0.0
+            || ry <= 0.0
+            || self.record(|| Command::Ellipse {
+                cx,
+                cy,
+                rx,
+                ry,
+                paint,
+            })
+        {
+            return;
+        }
+
+        let (y0, y1) = self.pixel_rows(cy - ry, cy + ry);
+        for y in y0..=y1 {
+            let dy = (y as f32 + 0.5 - cy) / ry;
+            let t = 1.0 - dy * dy;
+            if t >= 0.0 {
+                let half_width = rx * t.sqrt();
+                self.fill_span(y, cx - half_width, cx + half_width, paint);
+            }
+        }
+    }
+
+    /// `points` must form a convex polygon, in either winding order.
+    pub(crate) fn fill_polygon(&mut self, points: &[Vec2], paint: Paint) {
+        if points.len() < 3
+            || self.record(|| Command::Polygon {
+                points: points.to_vec(),
+                paint,
+            })
+        {
+            return;
+        }
+
+        let min_y = points.iter().fold(f32::MAX, |min, p| min.min(p.y));
+        let max_y = points.iter().fold(f32::MIN, |max, p| max.max(p.y));
+
+        let (y0, y1) = self.pixel_rows(min_y, max_y);
+        for y in y0..=y1 {
+            let yc = y as f32 + 0.5;
+            let mut left = f32::MAX;
+            let mut right = f32::MIN;
+
+            for (i, a) in points.iter().enumerate() {
+                let b = &points[(i + 1) % points.len()];
+                if (a.y <= yc) != (b.y <= yc) {
+                    let x = a.x + (yc - a.y) * (b.x - a.x) / (b.y - a.y);
+                    left = left.min(x);
+                    right = right.max(x);
+                }
+            }
+
+            if left <= right {
+                self.fill_span(y, left, right, paint);
+            }
+        }
+    }
+
+    fn plot(&mut self, x: i32, y: i32, color: u32) {
+        if self.clip.contains(x, y) {
+            let index = self.index(x, y);
+            self.pixels[index] = color;
+        }
+    }
+
+    /// Plots `cx ± x, cy ± y`.
+    fn plot_symmetric(&mut self, cx: i32, cy: i32, x: i32, y: i32, color: u32) {
+        self.plot(cx + x, cy + y, color);
+        self.plot(cx - x, cy + y, color);
+        self.plot(cx + x, cy - y, color);
+        self.plot(cx - x, cy - y, color);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::bitmap::{Bitmap, Sampling};
+    use crate::golden::assert_golden;
+
+    /// Checks the scene against its golden image, drawn immediately and through a command list.
+    fn assert_scene<F: Fn(&mut RenderBuffer)>(name: &str, width: i32, height: i32, draw: F) {
+        let mut pixels = vec![0; (width * height) as usize];
+        draw(&mut RenderBuffer::new(&mut pixels, width, height));
+        assert_golden(name, &pixels, width, height);
+
+        let mut recorded = vec![0; (width * height) as usize];
+        let mut render_buffer = RenderBuffer::recording(&mut recorded, width, height);
+        draw(&mut render_buffer);
+        render_buffer.flush(&Rasterizer::new(3));
+        assert!(recorded == pixels, "{} differs when recorded", name);
+    }
+
+    fn draw_world(render_buffer: &mut RenderBuffer) {
+        render_buffer.camera.fit = Fit::Letterbox;
+        render_buffer.clear_and_draw_rect(Vec2::zero(), Vec2::new(80.0, 45.0), 0x551100, 0x220500);
+        render_buffer.draw_rect(Vec2::new(-60.0, 30.0), Vec2::new(12.0, 4.0), 0x2040ff);
+        render_buffer.draw_circle(Vec2::new(10.0, -5.0), 8.0, 0xffffff);
+        render_buffer.draw_rounded_rect(Vec2::new(0.0, -38.0), Vec2::new(15.0, 3.0), 3.0, 0x00c000);
+        render_buffer.draw_line(Vec2::new(-80.0, -45.0), Vec2::new(80.0, 45.0), 0xffff00);
+        render_buffer.draw_text(
+            Vec2::new(0.0, 10.0),
+            10.0,
+            "GOLD",
+            0xff8000,
+            TextAlign::Center,
+        );
+        render_buffer.draw_letterbox(0x000000);
+    }
+
+    /// Coordinates of the pixels `draw` sets on an empty buffer.
+    fn drawn_pixels<F: Fn(&mut RenderBuffer)>(width: i32, height: i32, draw: F) -> Vec<(i32, i32)> {
+        let mut pixels = vec![0; (width * height) as usize];
+        draw(&mut RenderBuffer::new(&mut pixels, width, height));
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixels[(y * width + x) as usize] != 0)
+            .collect()
+    }
+
+    /// Pixels whose centers are inside `inside`, which filled shapes should match exactly.
+    fn pixels_inside<F: Fn(f32, f32) -> bool>(
+        width: i32,
+        height: i32,
+        inside: F,
+    ) -> Vec<(i32, i32)> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| inside(x as f32 + 0.5, y as f32 + 0.5))
+            .collect()
+    }
+
+    fn mirrored(pixels: &[(i32, i32)], f: impl Fn(i32, i32) -> (i32, i32)) -> Vec<(i32, i32)> {
+        let mut mirrored: Vec<_> = pixels.iter().map(|&(x, y)| f(x, y)).collect();
+        mirrored.sort_by_key(|&(x, y)| (y, x));
+        mirrored
+    }
+
+    #[test]
+    fn test_fill_circle() {
+        let pixels = drawn_pixels(20, 20, |render_buffer| {
+            render_buffer.draw_circle_in_pixels(10.0, 10.0, 5.3, 0xffffff);
+        });
+        let expected = pixels_inside(20, 20, |x, y| {
+            (x - 10.0) * (x - 10.0) + (y - 10.0) * (y - 10.0) <= 5.3 * 5.3
+        });
+        assert_eq!(pixels, expected);
+        assert_eq!(pixels.len(), 88);
+        assert_eq!(mirrored(&pixels, |x, y| (19 - x, y)), pixels);
+        assert_eq!(mirrored(&pixels, |x, y| (x, 19 - y)), pixels);
+        assert_eq!(mirrored(&pixels, |x, y| (y, x)), pixels);
+    }
+
+    #[test]
+    fn test_fill_ellipse() {
+        let pixels = drawn_pixels(24, 16, |render_buffer| {
+            render_buffer.draw_ellipse_in_pixels(12.0, 8.0, 9.4, 4.6, 0xffffff);
+        });
+        let expected = pixels_inside(24, 16, |x, y| {
+            let dx = (x - 12.0) / 9.4;
+            let dy = (y - 8.0) / 4.6;
+            dx * dx + dy * dy <= 1.0
+        });
+        assert_eq!(pixels, expected);
+        assert_eq!(mirrored(&pixels, |x, y| (23 - x, y)), pixels);
+        assert_eq!(mirrored(&pixels, |x, y| (x, 15 - y)), pixels);
+
+        // Degenerate ellipses draw nothing
+        assert!(drawn_pixels(8, 8, |render_buffer| {
+            render_buffer.draw_ellipse_in_pixels(4.0, 4.0, 3.0, 0.0, 0xffffff);
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn test_fill_rounded_rect() {
+        let rect = drawn_pixels(30, 20, |render_buffer| {
+            render_buffer.draw_rect_in_pixels(3, 2, 27, 18, 0xffffff);
+        });
+        let square_corners = drawn_pixels(30, 20, |render_buffer| {
+            render_buffer.draw_rounded_rect_in_pixels(3.0, 2.0, 27.0, 18.0, 0.0, 0xffffff);
+        });
+        assert_eq!(square_corners, rect);
+
+        let pixels = drawn_pixels(30, 20, |render_buffer| {
+            render_buffer.draw_rounded_rect_in_pixels(3.0, 2.0, 27.0, 18.0, 5.0, 0xffffff);
+        });
+        assert!(pixels.iter().all(|pixel| rect.contains(pixel)));
+        assert_eq!(mirrored(&pixels, |x, y| (29 - x, y)), pixels);
+        assert_eq!(mirrored(&pixels, |x, y| (x, 19 - y)), pixels);
+
+        // Each corner cuts away what a radius 5 quarter circle leaves of its 5x5 square
+        let quarter = pixels_inside(5, 5, |x, y| x * x + y * y <= 25.0).len();
+        assert_eq!(rect.len() - pixels.len(), 4 * (25 - quarter));
+
+        // The radius is limited to half the shorter side, making a stadium
+        let stadium = drawn_pixels(30, 20, |render_buffer| {
+            render_buffer.draw_rounded_rect_in_pixels(3.0, 2.0, 27.0, 18.0, 100.0, 0xffffff);
+        });
+        let expected = pixels_inside(30, 20, |x, y| {
+            let dx = (11.0 - x).max(x - 19.0).max(0.0);
+            let dy = y - 10.0;
+            (3.0..=27.0).contains(&x) && dx * dx + dy * dy <= 64.0
+        });
+        assert_eq!(stadium, expected);
+    }
+
+    #[test]
+    fn test_fill_polygon() {
+        let points = [
+            Vec2::new(2.0, 3.0),
+            Vec2::new(17.5, 1.0),
+            Vec2::new(21.0, 12.0),
+            Vec2::new(9.0, 18.5),
+            Vec2::new(1.0, 11.0),
+        ];
+        let counter_clockwise = drawn_pixels(24, 20, |render_buffer| {
+            render_buffer.draw_polygon_in_pixels(&points, 0xffffff);
+        });
+        let mut reversed = points;
+        reversed.reverse();
+        let clockwise = drawn_pixels(24, 20, |render_buffer| {
+            render_buffer.draw_polygon_in_pixels(&reversed, 0xffffff);
+        });
+        assert!(!counter_clockwise.is_empty());
+        assert_eq!(clockwise, counter_clockwise);
+
+        // Starting from another vertex doesn't matter either
+        let mut rotated = points;
+        rotated.rotate_left(2);
+        let rotated = drawn_pixels(24, 20, |render_buffer| {
+            render_buffer.draw_polygon_in_pixels(&rotated, 0xffffff);
+        });
+        assert_eq!(rotated, counter_clockwise);
+
+        // An axis aligned square covers the same pixels as the rect
+        let square = drawn_pixels(24, 20, |render_buffer| {
+            render_buffer.draw_polygon_in_pixels(
+                &[
+                    Vec2::new(4.0, 5.0),
+                    Vec2::new(4.0, 15.0),
+                    Vec2::new(14.0, 15.0),
+                    Vec2::new(14.0, 5.0),
+                ],
+                0xffffff,
+            );
+        });
+        let rect = drawn_pixels(24, 20, |render_buffer| {
+            render_buffer.draw_rect_in_pixels(4, 5, 14, 15, 0xffffff);
+        });
+        assert_eq!(square, rect);
+    }
+
+    #[test]
+    fn test_circle_outline() {
+        let pixels = drawn_pixels(21, 21, |render_buffer| {
+            render_buffer.draw_circle_outline_in_pixels(10, 10, 8, 0xffffff);
+        });
+        assert_eq!(pixels.len(), 44);
+
+        // Eight-way symmetric, and every pixel within half a pixel of the radius
+        assert_eq!(mirrored(&pixels, |x, y| (20 - x, y)), pixels);
+        assert_eq!(mirrored(&pixels, |x, y| (x, 20 - y)), pixels);
+        assert_eq!(mirrored(&pixels, |x, y| (y, x)), pixels);
+        for &(x, y) in pixels.iter() {
+            let distance = (((x - 10) * (x - 10) + (y - 10) * (y - 10)) as f32).sqrt();
+            assert!((distance - 8.0).abs() <= 0.5, "{:?}", (x, y));
+        }
+
+        let point = drawn_pixels(5, 5, |render_buffer| {
+            render_buffer.draw_circle_outline_in_pixels(2, 2, 0, 0xffffff);
+        });
+        assert_eq!(point, vec![(2, 2)]);
+    }
+
+    #[test]
+    fn test_ellipse_outline() {
+        let pixels = drawn_pixels(31, 15, |render_buffer| {
+            render_buffer.draw_ellipse_outline_in_pixels(15, 7, 14, 6, 0xffffff);
+        });
+        assert_eq!(mirrored(&pixels, |x, y| (30 - x, y)), pixels);
+        assert_eq!(mirrored(&pixels, |x, y| (x, 14 - y)), pixels);
+        for extreme in [(1, 7), (29, 7), (15, 1), (15, 13)].iter() {
+            assert!(pixels.contains(extreme));
         }
+
+        // Closed, every pixel touches two others
+        for &(x, y) in pixels.iter() {
+            let neighbours = pixels
+                .iter()
+                .filter(|&&(nx, ny)| {
+                    (nx, ny) != (x, y) && (nx - x).abs() <= 1 && (ny - y).abs() <= 1
+                })
+                .count();
+            assert!(neighbours >= 2, "{:?}", (x, y));
+        }
+
+        // Equal radii give the midpoint circle
+        let circle = drawn_pixels(21, 21, |render_buffer| {
+            render_buffer.draw_circle_outline_in_pixels(10, 10, 8, 0xffffff);
+        });
+        let round = drawn_pixels(21, 21, |render_buffer| {
+            render_buffer.draw_ellipse_outline_in_pixels(10, 10, 8, 8, 0xffffff);
+        });
+        assert_eq!(round, circle);
+    }
+
+    #[test]
+    fn test_clipped_rects() {
+        assert_scene("clipped_rects", 61, 47, |render_buffer| {
+            render_buffer.clear(0x101020);
+            render_buffer.draw_rect_in_pixels(-10, -10, 15, 12, 0xff0000);
+            render_buffer.draw_rect_in_pixels(50, 40, 80, 60, 0x00ff00);
+            render_buffer.draw_rect_in_pixels(-5, 20, 70, 25, 0x0000ff);
+            render_buffer.draw_rect_in_pixels(30, -20, 35, 100, 0xffff00);
+            // Empty, min and max are swapped
+            render_buffer.draw_rect_in_pixels(20, 30, 10, 40, 0xffffff);
+            render_buffer.draw_rect_blended_in_pixels(
+                10,
+                10,
+                50,
+                37,
+                Color::rgba(255, 0, 255, 128),
+                BlendMode::Additive,
+            );
+            render_buffer.draw_rect_blended_in_pixels(
+                -100,
+                30,
+                100,
+                100,
+                Color::rgb(128, 255, 128),
+                BlendMode::Multiply,
+            );
+        });
+    }
+
+    #[test]
+    fn test_edge_lines() {
+        assert_scene("edge_lines", 40, 30, |render_buffer| {
+            render_buffer.clear(0x000000);
+            render_buffer.draw_line_in_pixels(0, 0, 39, 0, 0xff0000);
+            render_buffer.draw_line_in_pixels(39, 0, 39, 29, 0x00ff00);
+            render_buffer.draw_line_in_pixels(39, 29, 0, 29, 0x0000ff);
+            render_buffer.draw_line_in_pixels(0, 29, 0, 0, 0xffff00);
+            // Crossing the frame through opposite corners
+            render_buffer.draw_line_in_pixels(-40, -30, 80, 60, 0xffffff);
+            render_buffer.draw_line_in_pixels(-1000, 1000, 1000, -1000, 0x00ffff);
+            render_buffer.draw_line_blended_in_pixels(
+                -5,
+                15,
+                45,
+                12,
+                Color::rgba(255, 128, 0, 128),
+                BlendMode::Alpha,
+            );
+            render_buffer.draw_line_aa_in_pixels(0.0, 5.5, 39.9, 8.2, Color::rgb(255, 0, 255));
+            render_buffer.draw_line_aa_in_pixels(-3.0, -2.0, 12.5, 31.7, Color::rgb(0, 128, 255));
+            // Entirely outside
+            render_buffer.draw_line_in_pixels(-10, -10, -1, 50, 0xffffff);
+        });
+    }
+
+    #[test]
+    fn test_shapes() {
+        let mut bitmap = Bitmap::new(2, 2);
+        bitmap.set_pixel(0, 0, Color::rgb(255, 0, 0));
+        bitmap.set_pixel(1, 0, Color::rgb(0, 255, 0));
+        bitmap.set_pixel(0, 1, Color::rgb(0, 0, 255));
+        bitmap.set_pixel(1, 1, Color::rgba(255, 255, 255, 128));
+        let sprite = Sprite::new(Arc::new(bitmap));
+        let smooth = Sprite {
+            sampling: Sampling::Bilinear,
+            alpha: 0.75,
+            ..sprite.clone()
+        };
+
+        assert_scene("shapes", 80, 60, |render_buffer| {
+            render_buffer.clear(0x202020);
+            render_buffer.draw_circle_in_pixels(12.5, 12.5, 9.3, 0xff0000);
+            render_buffer.draw_ellipse_in_pixels(35.2, 10.0, 12.0, 5.5, 0x00ff00);
+            render_buffer.draw_rounded_rect_in_pixels(52.0, 3.0, 77.5, 20.0, 6.0, 0x0000ff);
+            render_buffer.draw_polygon_in_pixels(
+                &[
+                    Vec2::new(5.0, 25.0),
+                    Vec2::new(25.0, 28.0),
+                    Vec2::new(12.0, 45.0),
+                ],
+                0xffff00,
+            );
+            render_buffer.draw_circle_outline_in_pixels(40, 35, 8, 0xff00ff);
+            render_buffer.draw_ellipse_outline_in_pixels(63, 35, 14, 6, 0x00ffff);
+            render_buffer.draw_text_in_pixels(2, 48, "LEFT", 1, 0xffffff, TextAlign::Left);
+            render_buffer.draw_text_in_pixels(40, 52, "MID\nDLE", 1, 0xff8000, TextAlign::Center);
+            render_buffer.draw_text_in_pixels(78, 48, "R", 2, 0x80ff80, TextAlign::Right);
+            render_buffer.draw_bitmap_in_pixels(20.0, 22.0, 36.0, 30.0, &sprite);
+            render_buffer.draw_bitmap_in_pixels(46.5, 21.5, 58.3, 30.7, &smooth);
+            render_buffer.camera.rotation = 0.5;
+            render_buffer.draw_bitmap(Vec2::new(35.0, 30.0), Vec2::new(6.0, 6.0), &smooth);
+            render_buffer.fade_to_black(0.2);
+        });
+    }
+
+    #[test]
+    fn test_aspect_ratios() {
+        assert_scene("aspect_wide", 96, 24, draw_world);
+        assert_scene("aspect_tall", 24, 96, draw_world);
+        assert_scene("aspect_odd", 37, 29, draw_world);
+        assert_scene("aspect_pixel", 1, 1, draw_world);
     }
 }
@@ -0,0 +1,583 @@
+// Plays the game in a terminal, e.g. over SSH. Each character cell shows two pixels with an upper
+// half block, the top one as the foreground and the bottom one as the background color, in 24-bit
+// ANSI color. Keys are read from stdin in raw mode and the mouse through SGR mouse reporting.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::command_list::Rasterizer;
+use crate::config;
+use crate::game::Game;
+use crate::input::keyboard::{Key, Keyboard};
+use crate::input::mouse::Button;
+use crate::input::Input;
+use crate::software_rendering::RenderBuffer;
+
+/// Frames are rendered at this many pixels per terminal pixel and averaged down, so text and
+/// thin shapes don't vanish at terminal resolutions.
+const SUPERSAMPLE: i32 = 2;
+const FRAME_TIME: Duration = Duration::from_millis(33);
+const SIZE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// How long an unfinished escape sequence waits for the rest before its ESC counts as the
+/// escape key.
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Terminals only report key presses, repeated while a key is held. A key counts as held for a
+/// while after each press, longer after the first one to cover the delay before repeats start.
+const FIRST_PRESS_HOLD: f32 = 0.55;
+const REPEAT_HOLD: f32 = 0.1;
+
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l\x1b[?1003h\x1b[?1006h\x1b[2J";
+const LEAVE_SCREEN: &str = "\x1b[?1006l\x1b[?1003l\x1b[0m\x1b[?25h\x1b[?1049l";
+
+pub fn run() {
+    if let Err(error) = do_run() {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn do_run() -> io::Result<()> {
+    let _raw_mode = RawMode::enable()?;
+    let stdin = spawn_stdin_reader();
+    let mut stdout = io::stdout();
+
+    let mut game = Game::new();
+    if let Some(config_dir) = config::config_dir() {
+        game.set_config_dir(&config_dir);
+    }
+    let mut input = Input::new();
+    let mut held_keys = HeldKeys::new();
+    let rasterizer = Rasterizer::default();
+
+    let mut screen = Screen::new();
+    let mut pixels = Vec::new();
+    let mut output = String::new();
+    let mut pending = Vec::new();
+    let mut pending_since: Option<Instant> = None;
+    let mut events = Vec::new();
+    let mut size = (0, 0);
+    let mut last_size_check: Option<Instant> = None;
+    let mut last_dt = FRAME_TIME.as_secs_f32();
+
+    loop {
+        let frame_start = Instant::now();
+
+        if last_size_check.map_or(true, |time| time.elapsed() >= SIZE_CHECK_INTERVAL) {
+            size = terminal_size().unwrap_or((80, 24));
+            last_size_check = Some(frame_start);
+        }
+        let (columns, rows) = size;
+        let width = columns * SUPERSAMPLE;
+        let height = rows * 2 * SUPERSAMPLE;
+        pixels.resize((width * height) as usize, 0);
+
+        input.begin_frame();
+
+        while let Ok(bytes) = stdin.try_recv() {
+            pending.extend(bytes);
+        }
+        let timed_out = pending_since.map_or(false, |since| since.elapsed() >= ESCAPE_TIMEOUT);
+        let consumed = parse_events(&pending, timed_out, &mut events);
+        pending.drain(..consumed);
+        pending_since = match pending_since {
+            _ if pending.is_empty() => None,
+            Some(since) if consumed == 0 => Some(since),
+            _ => Some(frame_start),
+        };
+        for event in events.drain(..) {
+            match event {
+                Event::Key(key) => held_keys.press(key, &mut input.keyboard),
+                Event::Mouse {
+                    column,
+                    row,
+                    button,
+                } => {
+                    input.mouse.position.x = (column as f32 + 0.5) * (width / columns) as f32;
+                    input.mouse.position.y =
+                        height as f32 - (row as f32 + 0.5) * (height / rows) as f32;
+                    if let Some((button, is_down)) = button {
+                        input.mouse.button_mut(button).is_down = is_down;
+                    }
+                }
+                Event::Quit => return Ok(()),
+            }
+        }
+        held_keys.update(last_dt, &mut input.keyboard);
+
+        {
+            let mut render_buffer = RenderBuffer::recording(&mut pixels, width, height);
+            game.simulate(&mut render_buffer, &input, last_dt);
+            render_buffer.flush(&rasterizer);
+        }
+
+        output.clear();
+        screen.draw(&pixels, width, height, columns, rows, &mut output);
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()?;
+
+        if let Some(rest) = FRAME_TIME.checked_sub(frame_start.elapsed()) {
+            thread::sleep(rest);
+        }
+        last_dt = frame_start.elapsed().as_secs_f32();
+    }
+}
+
+/// Puts the terminal in raw mode and switches to the alternate screen, both are undone on drop.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        let mut stdout = io::stdout();
+        stdout.write_all(ENTER_SCREEN.as_bytes())?;
+        stdout.flush()?;
+
+        Ok(RawMode {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(LEAVE_SCREEN.as_bytes());
+        let _ = stdout.flush();
+        let _ = stty(&[self.saved.as_str()]);
+    }
+}
+
+/// Runs `stty` on the terminal stdin is connected to.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "stdin is not a terminal",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Columns and rows.
+fn terminal_size() -> Option<(i32, i32)> {
+    let size = stty(&["size"]).ok()?;
+    let mut numbers = size.split_whitespace().map(|number| number.parse::<i32>());
+    let rows = numbers.next()?.ok()?;
+    let columns = numbers.next()?.ok()?;
+    if columns > 0 && rows > 0 {
+        Some((columns, rows))
+    } else {
+        None
+    }
+}
+
+/// Reads stdin on its own thread, since it can't be polled without blocking.
+fn spawn_stdin_reader() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buffer = [0; 256];
+        while let Ok(count) = stdin.read(&mut buffer) {
+            if count == 0 || sender.send(buffer[..count].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+struct HeldKeys {
+    /// Seconds each key stays down for without another press.
+    remaining: [f32; Key::Count as usize],
+}
+
+impl HeldKeys {
+    fn new() -> HeldKeys {
+        HeldKeys {
+            remaining: [0.0; Key::Count as usize],
+        }
+    }
+
+    /// Every press, repeated or not, counts as a new press, so taps faster than the hold time
+    /// aren't lost.
+    fn press(&mut self, key: Key, keyboard: &mut Keyboard) {
+        let state = keyboard.key_mut(key);
+        self.remaining[key as usize] = if state.is_down {
+            REPEAT_HOLD
+        } else {
+            FIRST_PRESS_HOLD
+        };
+        state.is_down = true;
+        state.was_down = false;
+    }
+
+    fn update(&mut self, dt: f32, keyboard: &mut Keyboard) {
+        for (remaining, state) in self.remaining.iter_mut().zip(keyboard.keys.iter_mut()) {
+            if *remaining > 0.0 && !state.pressed() {
+                *remaining -= dt;
+            }
+            state.is_down = *remaining > 0.0;
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Event {
+    Key(Key),
+    /// `column` and `row` count from 0 at the top left. `button` is set when one was pressed
+    /// (`true`) or released.
+    Mouse {
+        column: i32,
+        row: i32,
+        button: Option<(Button, bool)>,
+    },
+    Quit,
+}
+
+/// Parses the input events at the start of `data` into `events`. Returns how many bytes were
+/// used, an incomplete escape sequence at the end is left for the next call since the rest can
+/// arrive in a later read. Once `timed_out`, a lone escape at the end is the escape key and an
+/// unfinished sequence is dropped.
+fn parse_events(data: &[u8], timed_out: bool, events: &mut Vec<Event>) -> usize {
+    let mut index = 0;
+    while index < data.len() {
+        match data[index] {
+            // Ctrl-C doesn't raise a signal in raw mode
+            0x03 => {
+                events.push(Event::Quit);
+                index += 1;
+            }
+            0x1b => match parse_escape(&data[index..]) {
+                Some((length, event)) => {
+                    events.extend(event);
+                    index += length;
+                }
+                None if timed_out => {
+                    if index + 1 == data.len() {
+                        events.push(Event::Key(Key::Escape));
+                    }
+                    index = data.len();
+                }
+                None => break,
+            },
+            byte => {
+                events.extend(Key::from_char(byte as char).map(Event::Key));
+                index += 1;
+            }
+        }
+    }
+    index
+}
+
+/// Parses the escape sequence `data` starts with, `None` if it's incomplete. Unknown sequences
+/// are skipped.
+fn parse_escape(data: &[u8]) -> Option<(usize, Option<Event>)> {
+    let introducer = *data.get(1)?;
+    if introducer == b'O' {
+        // SS3, sent for arrows in application cursor mode
+        return Some((3, arrow(*data.get(2)?).map(Event::Key)));
+    }
+    if introducer != b'[' {
+        // The escape key, or alt with the next key
+        return Some((1, Some(Event::Key(Key::Escape))));
+    }
+
+    // CSI: parameter bytes, then a final byte
+    let length = data[2..]
+        .iter()
+        .position(|&byte| (0x40..=0x7e).contains(&byte))?
+        + 3;
+    let parameters = std::str::from_utf8(&data[2..length - 1]).unwrap_or("");
+    let event = match (data[length - 1], parameters) {
+        (final_byte, "") if arrow(final_byte).is_some() => arrow(final_byte).map(Event::Key),
+        (b'~', "23") => Some(Event::Key(Key::F11)),
+        (b'~', "24") => Some(Event::Key(Key::F12)),
+        (final_byte @ (b'M' | b'm'), parameters) if parameters.starts_with('<') => {
+            parse_mouse(&parameters[1..], final_byte == b'M')
+        }
+        _ => None,
+    };
+    Some((length, event))
+}
+
+fn arrow(byte: u8) -> Option<Key> {
+    match byte {
+        b'A' => Some(Key::Up),
+        b'B' => Some(Key::Down),
+        b'C' => Some(Key::Right),
+        b'D' => Some(Key::Left),
+        _ => None,
+    }
+}
+
+/// SGR mouse report, `button;column;row` with 1-based cells.
+fn parse_mouse(parameters: &str, is_press: bool) -> Option<Event> {
+    let mut numbers = parameters.split(';').map(|number| number.parse::<i32>());
+    let code = numbers.next()?.ok()?;
+    let column = numbers.next()?.ok()? - 1;
+    let row = numbers.next()?.ok()? - 1;
+
+    const MOTION: i32 = 32;
+    const WHEEL: i32 = 64;
+    let button = if code & (MOTION | WHEEL) != 0 {
+        None
+    } else {
+        match code & 3 {
+            0 => Some((Button::Left, is_press)),
+            2 => Some((Button::Right, is_press)),
+            _ => None,
+        }
+    };
+
+    Some(Event::Mouse {
+        column,
+        row,
+        button,
+    })
+}
+
+/// What the terminal shows, so only cells that changed are written.
+struct Screen {
+    columns: i32,
+    rows: i32,
+    /// Top and bottom color of each cell, top row first.
+    cells: Vec<(u32, u32)>,
+}
+
+impl Screen {
+    fn new() -> Screen {
+        Screen {
+            columns: 0,
+            rows: 0,
+            cells: Vec::new(),
+        }
+    }
+
+    /// Scales the `width` x `height` frame to `columns` x `rows` cells and appends what updates
+    /// the terminal to `output`.
+    fn draw(
+        &mut self,
+        pixels: &[u32],
+        width: i32,
+        height: i32,
+        columns: i32,
+        rows: i32,
+        output: &mut String,
+    ) {
+        let redraw_all = (columns, rows) != (self.columns, self.rows);
+        if redraw_all {
+            self.columns = columns;
+            self.rows = rows;
+            self.cells = vec![(0, 0); (columns * rows) as usize];
+            output.push_str("\x1b[0m\x1b[2J");
+        }
+
+        // Pixel rows of the scaled frame, counted from the top
+        let scaled_height = rows * 2;
+        let sample = |column: i32, scaled_row: i32| {
+            let x0 = column * width / columns;
+            let x1 = ((column + 1) * width / columns).max(x0 + 1);
+            let y1 = height - scaled_row * height / scaled_height;
+            let y0 = (height - (scaled_row + 1) * height / scaled_height).min(y1 - 1);
+            average(pixels, width, x0, y0, x1, y1)
+        };
+
+        let mut cursor = None;
+        let mut colors = None;
+        for row in 0..rows {
+            for column in 0..columns {
+                let cell = (sample(column, row * 2), sample(column, row * 2 + 1));
+                let index = (row * columns + column) as usize;
+                if !redraw_all && self.cells[index] == cell {
+                    continue;
+                }
+                self.cells[index] = cell;
+
+                if cursor != Some((column, row)) {
+                    let _ = write!(output, "\x1b[{};{}H", row + 1, column + 1);
+                }
+                if colors != Some(cell) {
+                    let (top, bottom) = cell;
+                    let _ = write!(
+                        output,
+                        "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                        (top >> 16) & 0xff,
+                        (top >> 8) & 0xff,
+                        top & 0xff,
+                        (bottom >> 16) & 0xff,
+                        (bottom >> 8) & 0xff,
+                        bottom & 0xff
+                    );
+                    colors = Some(cell);
+                }
+                output.push('\u{2580}');
+                // Writing the last column may not move the cursor, so it's placed again
+                cursor = Some((column + 1, row)).filter(|_| column + 1 < columns);
+            }
+        }
+    }
+}
+
+/// Average color of the pixels in `x0..x1`, `y0..y1`.
+fn average(pixels: &[u32], width: i32, x0: i32, y0: i32, x1: i32, y1: i32) -> u32 {
+    let mut sums = [0u32; 3];
+    for y in y0..y1 {
+        for &pixel in pixels[(y * width + x0) as usize..(y * width + x1) as usize].iter() {
+            sums[0] += (pixel >> 16) & 0xff;
+            sums[1] += (pixel >> 8) & 0xff;
+            sums[2] += pixel & 0xff;
+        }
+    }
+
+    let count = ((x1 - x0) * (y1 - y0)) as u32;
+    let [r, g, b] = sums.map(|sum| (sum + count / 2) / count);
+    r << 16 | g << 8 | b
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_events() {
+        let mut events = Vec::new();
+        let data =
+            b"\x1b[A\x1bOBx\x1b[24~\x1b[<0;3;5M\x1b[<35;10;2M\x1b[<2;1;1m\x1b[99Z\r\x03\x1b[<0;1";
+        let consumed = parse_events(data, false, &mut events);
+        assert_eq!(
+            events,
+            vec![
+                Event::Key(Key::Up),
+                Event::Key(Key::Down),
+                Event::Key(Key::X),
+                Event::Key(Key::F12),
+                Event::Mouse {
+                    column: 2,
+                    row: 4,
+                    button: Some((Button::Left, true)),
+                },
+                Event::Mouse {
+                    column: 9,
+                    row: 1,
+                    button: None,
+                },
+                Event::Mouse {
+                    column: 0,
+                    row: 0,
+                    button: Some((Button::Right, false)),
+                },
+                Event::Key(Key::Enter),
+                Event::Quit,
+            ]
+        );
+        // The unfinished mouse report is kept for later
+        assert_eq!(&data[consumed..], b"\x1b[<0;1");
+
+        // An escape followed by something that isn't a sequence is the key
+        events.clear();
+        assert_eq!(parse_events(b"\x1b\x1bq", false, &mut events), 3);
+        assert_eq!(
+            events,
+            vec![
+                Event::Key(Key::Escape),
+                Event::Key(Key::Escape),
+                Event::Key(Key::Q),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_split_escape() {
+        // A report split right after its ESC waits for the rest
+        let mut events = Vec::new();
+        assert_eq!(parse_events(b"q\x1b", false, &mut events), 1);
+        assert_eq!(events, vec![Event::Key(Key::Q)]);
+
+        events.clear();
+        assert_eq!(parse_events(b"\x1b[<0;3;5M", false, &mut events), 9);
+        assert_eq!(
+            events,
+            vec![Event::Mouse {
+                column: 2,
+                row: 4,
+                button: Some((Button::Left, true)),
+            }]
+        );
+
+        // Nothing followed in time, so it was the escape key
+        events.clear();
+        assert_eq!(parse_events(b"\x1b", true, &mut events), 1);
+        assert_eq!(events, vec![Event::Key(Key::Escape)]);
+
+        // The start of a sequence that never finished isn't read as keys
+        events.clear();
+        assert_eq!(parse_events(b"\x1b[<0;1", true, &mut events), 6);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_held_keys() {
+        let mut keyboard = Keyboard::new();
+        let mut held_keys = HeldKeys::new();
+        held_keys.press(Key::Left, &mut keyboard);
+        held_keys.update(0.5, &mut keyboard);
+        assert!(keyboard.key(Key::Left).pressed());
+
+        keyboard.key_mut(Key::Left).was_down = true;
+        held_keys.update(0.5, &mut keyboard);
+        assert!(keyboard.key(Key::Left).is_down());
+
+        // A repeat keeps it down for a shorter time
+        held_keys.press(Key::Left, &mut keyboard);
+        assert!(keyboard.key(Key::Left).pressed());
+        keyboard.key_mut(Key::Left).was_down = true;
+        held_keys.update(0.2, &mut keyboard);
+        assert!(!keyboard.key(Key::Left).is_down());
+    }
+
+    #[test]
+    fn test_screen() {
+        // 2x4 pixels into 1x2 cells: white on red, then green on blue
+        let pixels = [
+            0x0000ff, 0x0000ff, 0x00ff00, 0x00ff00, 0xff0000, 0xff0000, 0xffffff, 0xffffff,
+        ];
+        let mut screen = Screen::new();
+        let mut output = String::new();
+        screen.draw(&pixels, 2, 4, 1, 2, &mut output);
+        assert_eq!(
+            output,
+            "\x1b[0m\x1b[2J\
+             \x1b[1;1H\x1b[38;2;255;255;255;48;2;255;0;0m\u{2580}\
+             \x1b[2;1H\x1b[38;2;0;255;0;48;2;0;0;255m\u{2580}"
+        );
+
+        // Unchanged cells aren't written again
+        output.clear();
+        screen.draw(&pixels, 2, 4, 1, 2, &mut output);
+        assert!(output.is_empty());
+
+        let pixels = [0x000000, 0x000000, 0x000000, 0xffffff];
+        screen.draw(&pixels, 2, 2, 1, 2, &mut output);
+        assert!(output.ends_with("\x1b[2;1H\x1b[38;2;0;0;0;48;2;0;0;0m\u{2580}"));
+    }
+
+    #[test]
+    fn test_average() {
+        let pixels = [0x000000, 0xff0000, 0x00ff00, 0x0000ff];
+        assert_eq!(average(&pixels, 2, 0, 0, 2, 2), 0x404040);
+        assert_eq!(average(&pixels, 2, 1, 0, 2, 1), 0xff0000);
+    }
+}
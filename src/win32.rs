@@ -9,18 +9,42 @@ use winapi::um::profileapi::*;
 use winapi::um::wingdi::*;
 use winapi::um::winnt::*;
 use winapi::um::winuser::*;
+use winapi::um::xinput::*;
 
+use crate::command_list::Rasterizer;
+use crate::config;
+use crate::dirty_rects::FrameHistory;
+use crate::export::{Exporter, ImageFormat};
 use crate::game::*;
+use crate::input::gamepad::GamepadButton;
 use crate::input::keyboard::*;
 use crate::input::mouse::Button;
 use crate::input::Input;
 use crate::software_rendering::*;
 
+const XINPUT_BUTTONS: [(WORD, GamepadButton); 12] = [
+    (XINPUT_GAMEPAD_A, GamepadButton::A),
+    (XINPUT_GAMEPAD_B, GamepadButton::B),
+    (XINPUT_GAMEPAD_X, GamepadButton::X),
+    (XINPUT_GAMEPAD_Y, GamepadButton::Y),
+    (XINPUT_GAMEPAD_LEFT_SHOULDER, GamepadButton::LeftShoulder),
+    (XINPUT_GAMEPAD_RIGHT_SHOULDER, GamepadButton::RightShoulder),
+    (XINPUT_GAMEPAD_BACK, GamepadButton::Back),
+    (XINPUT_GAMEPAD_START, GamepadButton::Start),
+    (XINPUT_GAMEPAD_DPAD_UP, GamepadButton::DPadUp),
+    (XINPUT_GAMEPAD_DPAD_DOWN, GamepadButton::DPadDown),
+    (XINPUT_GAMEPAD_DPAD_LEFT, GamepadButton::DPadLeft),
+    (XINPUT_GAMEPAD_DPAD_RIGHT, GamepadButton::DPadRight),
+];
+
 struct Win32RenderBuffer {
     width: i32,
     height: i32,
     pixels: *mut u32,
     bitmap_info: BITMAPINFO,
+    /// Set when the pixels or the window contents were lost, so the next frame is drawn and
+    /// presented in full.
+    repaint: bool,
 }
 
 static mut RUNNING: bool = true;
@@ -66,6 +90,11 @@ unsafe extern "system" fn window_proc(
             render_buffer.bitmap_info.bmiHeader.biPlanes = 1;
             render_buffer.bitmap_info.bmiHeader.biBitCount = 32;
             render_buffer.bitmap_info.bmiHeader.biCompression = BI_RGB;
+            render_buffer.repaint = true;
+        }
+        WM_PAINT => {
+            ValidateRect(hwnd, std::ptr::null());
+            render_buffer.repaint = true;
         }
         _ => return DefWindowProcW(hwnd, msg, wparam, lparam),
     }
@@ -80,6 +109,12 @@ fn wstr(s: &str) -> Vec<u16> {
     OsStr::new(s).encode_wide().chain(once(0)).collect()
 }
 
+/// Shows `status` in the title bar, there's no console to print it to.
+unsafe fn set_status(hwnd: HWND, status: &str) {
+    let title = wstr(&format!("Breakout - {}", status));
+    SetWindowTextW(hwnd, title.as_ptr());
+}
+
 unsafe fn do_run() {
     let render_buffer = &mut *RENDER_BUFFER;
 
@@ -109,7 +144,23 @@ unsafe fn do_run() {
     let hdc = GetDC(hwnd);
 
     let mut game = Game::new();
+    if let Some(config_dir) = config::config_dir() {
+        game.set_config_dir(&config_dir);
+    }
     let mut input = Input::new();
+    let rasterizer = Rasterizer::default();
+    let mut history = FrameHistory::new();
+    // Beside the executable when there's no config directory, not wherever it was started from
+    let screenshot_dir = config::config_dir()
+        .or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+        })
+        .map(|dir| dir.join("screenshots"));
+    let mut exporter = screenshot_dir
+        .as_ref()
+        .map(|dir| Exporter::new(dir, ImageFormat::Png));
 
     let mut last_counter = std::mem::zeroed();
     QueryPerformanceCounter(&mut last_counter);
@@ -120,13 +171,7 @@ unsafe fn do_run() {
     let mut last_dt = 0.01666;
 
     while RUNNING {
-        input.keyboard.keys.iter_mut().for_each(|key| {
-            key.was_down = false;
-        });
-
-        input.mouse.buttons.iter_mut().for_each(|button| {
-            button.was_down = button.is_down;
-        });
+        input.begin_frame();
 
         let mut msg = std::mem::MaybeUninit::<MSG>::uninit();
         while PeekMessageW(msg.as_mut_ptr(), hwnd, 0 as UINT, 0 as UINT, PM_REMOVE) != 0 {
@@ -163,6 +208,18 @@ unsafe fn do_run() {
                     process_key!(VK_RIGHT, Key::Right);
                     process_key!(VK_UP, Key::Up);
                     process_key!(VK_DOWN, Key::Down);
+                    process_key!(VK_F11, Key::F11);
+                    process_key!(VK_F12, Key::F12);
+                    process_key!(VK_SPACE, Key::Space);
+                    process_key!(VK_RETURN, Key::Enter);
+                    process_key!(VK_ESCAPE, Key::Escape);
+                    process_key!(VK_BACK, Key::Backspace);
+                    // Letter and digit keys have their uppercase ASCII code
+                    if (0x30..=0x39).contains(&vk_code) || (0x41..=0x5a).contains(&vk_code) {
+                        if let Some(key) = Key::from_char(vk_code as u8 as char) {
+                            process_key!(vk_code, key);
+                        }
+                    }
                 }
                 _ => {
                     TranslateMessage(&msg);
@@ -178,36 +235,98 @@ unsafe fn do_run() {
         input.mouse.position.x = mouse_pointer.x as f32;
         input.mouse.position.y = (render_buffer.height - mouse_pointer.y) as f32;
 
+        // The first controller, every button is up while it's disconnected
+        let mut gamepad_state: XINPUT_STATE = std::mem::zeroed();
+        if XInputGetState(0, &mut gamepad_state) != 0 {
+            gamepad_state = std::mem::zeroed();
+        }
+        for &(mask, button) in XINPUT_BUTTONS.iter() {
+            input.gamepad.button_mut(button).is_down = gamepad_state.Gamepad.wButtons & mask != 0;
+        }
+
+        if render_buffer.repaint {
+            history.invalidate();
+            render_buffer.repaint = false;
+        }
+
         // Simulation
-        {
-            let mut render_buffer = RenderBuffer {
-                pixels: std::slice::from_raw_parts_mut(
+        let dirty = {
+            let mut render_buffer = RenderBuffer::recording(
+                std::slice::from_raw_parts_mut(
                     render_buffer.pixels,
                     (render_buffer.width * render_buffer.height) as usize,
                 ),
-                width: render_buffer.width,
-                height: render_buffer.height,
-            };
+                render_buffer.width,
+                render_buffer.height,
+            );
 
             game.simulate(&mut render_buffer, &input, last_dt);
-        }
+            let dirty = render_buffer.flush_changes(&mut history, &rasterizer);
+
+            // F12 takes a screenshot, F11 starts or stops recording every frame
+            if let (Some(exporter), Some(screenshot_dir)) =
+                (exporter.as_mut(), screenshot_dir.as_ref())
+            {
+                let result = if input.keyboard.key(Key::F12).pressed() {
+                    exporter
+                        .screenshot(
+                            render_buffer.pixels,
+                            render_buffer.width,
+                            render_buffer.height,
+                        )
+                        .map(|path| Some(format!("Saved {}", path.display())))
+                } else if input.keyboard.key(Key::F11).pressed() {
+                    exporter.toggle_recording().map(|_| {
+                        Some(if exporter.is_recording() {
+                            format!("Recording to {}", screenshot_dir.display())
+                        } else {
+                            "Stopped recording".to_string()
+                        })
+                    })
+                } else {
+                    Ok(None)
+                };
+                let result = result.and_then(|status| {
+                    exporter
+                        .record(
+                            render_buffer.pixels,
+                            render_buffer.width,
+                            render_buffer.height,
+                        )
+                        .map(|_| status)
+                });
+                match result {
+                    Ok(Some(status)) => set_status(hwnd, &status),
+                    Ok(None) => {}
+                    Err(error) => set_status(
+                        hwnd,
+                        &format!("Couldn't write to {}: {}", screenshot_dir.display(), error),
+                    ),
+                }
+            }
 
-        // Render
-        StretchDIBits(
-            hdc,
-            0,
-            0,
-            render_buffer.width,
-            render_buffer.height,
-            0,
-            0,
-            render_buffer.width,
-            render_buffer.height,
-            render_buffer.pixels as LPVOID,
-            &mut render_buffer.bitmap_info,
-            DIB_RGB_COLORS,
-            SRCCOPY,
-        );
+            dirty
+        };
+
+        // Render, only the regions that changed. The window's origin is its top left, the
+        // bitmap's is its bottom left.
+        for rect in dirty.iter() {
+            StretchDIBits(
+                hdc,
+                rect.x0,
+                render_buffer.height - rect.y1,
+                rect.width(),
+                rect.height(),
+                rect.x0,
+                rect.y0,
+                rect.width(),
+                rect.height(),
+                render_buffer.pixels as LPVOID,
+                &mut render_buffer.bitmap_info,
+                DIB_RGB_COLORS,
+                SRCCOPY,
+            );
+        }
 
         let mut current_counter = std::mem::zeroed();
         QueryPerformanceCounter(&mut current_counter);